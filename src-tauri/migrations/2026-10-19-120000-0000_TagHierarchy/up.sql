@@ -0,0 +1 @@
+ALTER TABLE "tags" ADD COLUMN "parent" TEXT;
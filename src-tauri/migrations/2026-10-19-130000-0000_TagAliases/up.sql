@@ -0,0 +1 @@
+ALTER TABLE "tags" ADD COLUMN "aliases" TEXT;
@@ -0,0 +1,10 @@
+CREATE TABLE
+    IF NOT EXISTS "tag_rules" (
+        "id" TEXT NOT NULL UNIQUE,
+        "name" TEXT NOT NULL,
+        "kind" TEXT NOT NULL,
+        "tags" TEXT,
+        "field" TEXT,
+        "condition" TEXT,
+        PRIMARY KEY ("id")
+    );
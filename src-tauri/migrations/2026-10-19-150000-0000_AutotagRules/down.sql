@@ -0,0 +1 @@
+DROP TABLE autotag_rules;
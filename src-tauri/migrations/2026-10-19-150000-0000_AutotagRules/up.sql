@@ -0,0 +1,11 @@
+CREATE TABLE
+    IF NOT EXISTS "autotag_rules" (
+        "id" TEXT NOT NULL UNIQUE,
+        "name" TEXT NOT NULL,
+        "query" TEXT NOT NULL,
+        "tags" TEXT,
+        "people" TEXT,
+        "photographer" TEXT,
+        "location" TEXT,
+        PRIMARY KEY ("id")
+    );
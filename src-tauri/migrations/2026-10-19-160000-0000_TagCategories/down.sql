@@ -0,0 +1,3 @@
+ALTER TABLE "tags" DROP COLUMN "category";
+
+DROP TABLE tag_categories;
@@ -0,0 +1,11 @@
+CREATE TABLE
+    IF NOT EXISTS "tag_categories" (
+        "id" TEXT NOT NULL UNIQUE,
+        "name" TEXT NOT NULL,
+        "color" TEXT NOT NULL,
+        "sort_order" INTEGER NOT NULL DEFAULT 0,
+        "max_per_photo" INTEGER,
+        PRIMARY KEY ("id")
+    );
+
+ALTER TABLE "tags" ADD COLUMN "category" TEXT;
@@ -0,0 +1,37 @@
+ALTER TABLE "photos" ADD COLUMN "tags" TEXT;
+ALTER TABLE "photos" ADD COLUMN "people" TEXT;
+ALTER TABLE "tags" ADD COLUMN "prereqs" TEXT;
+ALTER TABLE "tags" ADD COLUMN "coreqs" TEXT;
+ALTER TABLE "tags" ADD COLUMN "incompatible" TEXT;
+
+UPDATE "photos"
+SET
+    "tags" = (
+        SELECT group_concat("tag", ',')
+        FROM (SELECT "tag" FROM "photo_tags" WHERE "photo" = "photos"."name" ORDER BY "position")
+    ),
+    "people" = (
+        SELECT group_concat("person", ',')
+        FROM (SELECT "person" FROM "photo_people" WHERE "photo" = "photos"."name" ORDER BY "position")
+    );
+
+UPDATE "tags"
+SET
+    "prereqs" = (
+        SELECT group_concat("related", ',')
+        FROM (SELECT "related" FROM "tag_relations" WHERE "tag" = "tags"."name" AND "kind" = 'Prereq' ORDER BY "position")
+    ),
+    "coreqs" = (
+        SELECT group_concat("related", ',')
+        FROM (SELECT "related" FROM "tag_relations" WHERE "tag" = "tags"."name" AND "kind" = 'Coreq' ORDER BY "position")
+    ),
+    "incompatible" = (
+        SELECT group_concat("related", ',')
+        FROM (SELECT "related" FROM "tag_relations" WHERE "tag" = "tags"."name" AND "kind" = 'Incompatible' ORDER BY "position")
+    );
+
+DROP TABLE tag_relations;
+
+DROP TABLE photo_people;
+
+DROP TABLE photo_tags;
@@ -0,0 +1,77 @@
+CREATE TABLE
+    IF NOT EXISTS "photo_tags" (
+        "photo" TEXT NOT NULL REFERENCES "photos" ("name") ON DELETE CASCADE,
+        "tag" TEXT NOT NULL REFERENCES "tags" ("name") ON DELETE CASCADE,
+        "position" INTEGER NOT NULL,
+        PRIMARY KEY ("photo", "tag")
+    );
+
+CREATE INDEX IF NOT EXISTS "photo_tags_tag" ON "photo_tags" ("tag");
+
+-- People referenced by photos aren't always in the people table, so only the photo is a foreign key
+CREATE TABLE
+    IF NOT EXISTS "photo_people" (
+        "photo" TEXT NOT NULL REFERENCES "photos" ("name") ON DELETE CASCADE,
+        "person" TEXT NOT NULL,
+        "position" INTEGER NOT NULL,
+        PRIMARY KEY ("photo", "person")
+    );
+
+CREATE INDEX IF NOT EXISTS "photo_people_person" ON "photo_people" ("person");
+
+CREATE TABLE
+    IF NOT EXISTS "tag_relations" (
+        "tag" TEXT NOT NULL REFERENCES "tags" ("name") ON DELETE CASCADE,
+        "kind" TEXT NOT NULL,
+        "related" TEXT NOT NULL REFERENCES "tags" ("name") ON DELETE CASCADE,
+        "position" INTEGER NOT NULL,
+        PRIMARY KEY ("tag", "kind", "related")
+    );
+
+CREATE INDEX IF NOT EXISTS "tag_relations_related" ON "tag_relations" ("related");
+
+-- Split the comma-joined columns into one row per value, keeping the order of each list
+CREATE TEMPORARY TABLE "split_values" AS
+WITH RECURSIVE
+    "split" ("owner", "kind", "value", "rest", "position") AS (
+        SELECT "name", 'tag', '', "tags" || ',', -1 FROM "photos" WHERE "tags" IS NOT NULL
+        UNION ALL
+        SELECT "name", 'person', '', "people" || ',', -1 FROM "photos" WHERE "people" IS NOT NULL
+        UNION ALL
+        SELECT "name", 'Prereq', '', "prereqs" || ',', -1 FROM "tags" WHERE "prereqs" IS NOT NULL
+        UNION ALL
+        SELECT "name", 'Coreq', '', "coreqs" || ',', -1 FROM "tags" WHERE "coreqs" IS NOT NULL
+        UNION ALL
+        SELECT "name", 'Incompatible', '', "incompatible" || ',', -1 FROM "tags" WHERE "incompatible" IS NOT NULL
+        UNION ALL
+        SELECT
+            "owner",
+            "kind",
+            substr("rest", 1, instr("rest", ',') - 1),
+            substr("rest", instr("rest", ',') + 1),
+            "position" + 1
+        FROM "split"
+        WHERE "rest" <> ''
+    )
+SELECT "owner", "kind", "value", "position" FROM "split" WHERE "position" >= 0 AND "value" <> '';
+
+-- Tags used on photos or in relationships didn't always have a row of their own
+INSERT OR IGNORE INTO "tags" ("name")
+SELECT "value" FROM "split_values" WHERE "kind" <> 'person';
+
+INSERT OR IGNORE INTO "photo_tags" ("photo", "tag", "position")
+SELECT "owner", "value", "position" FROM "split_values" WHERE "kind" = 'tag';
+
+INSERT OR IGNORE INTO "photo_people" ("photo", "person", "position")
+SELECT "owner", "value", "position" FROM "split_values" WHERE "kind" = 'person';
+
+INSERT OR IGNORE INTO "tag_relations" ("tag", "kind", "related", "position")
+SELECT "owner", "kind", "value", "position" FROM "split_values" WHERE "kind" NOT IN ('tag', 'person');
+
+DROP TABLE "split_values";
+
+ALTER TABLE "photos" DROP COLUMN "tags";
+ALTER TABLE "photos" DROP COLUMN "people";
+ALTER TABLE "tags" DROP COLUMN "prereqs";
+ALTER TABLE "tags" DROP COLUMN "coreqs";
+ALTER TABLE "tags" DROP COLUMN "incompatible";
@@ -0,0 +1,3 @@
+ALTER TABLE "people" DROP COLUMN "photo_region";
+
+DROP TABLE photo_people_regions;
@@ -0,0 +1,15 @@
+-- Rectangles are normalized to the photo's size, measured from its top left corner
+CREATE TABLE
+    IF NOT EXISTS "photo_people_regions" (
+        "photo" TEXT NOT NULL REFERENCES "photos" ("name") ON DELETE CASCADE,
+        "person" TEXT NOT NULL,
+        "x" REAL NOT NULL,
+        "y" REAL NOT NULL,
+        "w" REAL NOT NULL,
+        "h" REAL NOT NULL,
+        PRIMARY KEY ("photo", "person")
+    );
+
+CREATE INDEX IF NOT EXISTS "photo_people_regions_person" ON "photo_people_regions" ("person");
+
+ALTER TABLE "people" ADD COLUMN "photo_region" TEXT;
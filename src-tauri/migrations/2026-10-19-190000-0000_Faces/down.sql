@@ -0,0 +1,3 @@
+DROP TABLE face_scans;
+
+DROP TABLE faces;
@@ -0,0 +1,22 @@
+-- Faces found by face detection that haven't been assigned to anyone yet. Rectangles are
+-- normalized like people regions.
+CREATE TABLE
+    IF NOT EXISTS "faces" (
+        "id" TEXT NOT NULL UNIQUE,
+        "photo" TEXT NOT NULL REFERENCES "photos" ("name") ON DELETE CASCADE,
+        "x" REAL NOT NULL,
+        "y" REAL NOT NULL,
+        "w" REAL NOT NULL,
+        "h" REAL NOT NULL,
+        "score" REAL NOT NULL,
+        PRIMARY KEY ("id")
+    );
+
+CREATE INDEX IF NOT EXISTS "faces_photo" ON "faces" ("photo");
+
+-- Photos that face detection has already looked at, so they are skipped next time
+CREATE TABLE
+    IF NOT EXISTS "face_scans" (
+        "photo" TEXT NOT NULL REFERENCES "photos" ("name") ON DELETE CASCADE,
+        PRIMARY KEY ("photo")
+    );
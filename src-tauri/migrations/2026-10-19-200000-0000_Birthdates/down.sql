@@ -0,0 +1,3 @@
+ALTER TABLE "people" DROP COLUMN "birthdate_precision";
+
+ALTER TABLE "people" DROP COLUMN "birthdate";
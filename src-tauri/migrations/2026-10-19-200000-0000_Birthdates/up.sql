@@ -0,0 +1,5 @@
+-- Birthdates are stored as YYYY-MM-DD. When only the year is known, the precision is "Year" and
+-- the date is the first of January.
+ALTER TABLE "people" ADD COLUMN "birthdate" TEXT;
+
+ALTER TABLE "people" ADD COLUMN "birthdate_precision" TEXT;
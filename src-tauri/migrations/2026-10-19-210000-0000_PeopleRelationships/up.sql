@@ -0,0 +1,11 @@
+-- Each row reads as "related is person's kind", and is stored alongside its inverse so either
+-- person can be looked up directly
+CREATE TABLE
+    IF NOT EXISTS "people_relationships" (
+        "person" TEXT NOT NULL REFERENCES "people" ("id") ON DELETE CASCADE,
+        "kind" TEXT NOT NULL,
+        "related" TEXT NOT NULL REFERENCES "people" ("id") ON DELETE CASCADE,
+        PRIMARY KEY ("person", "kind", "related")
+    );
+
+CREATE INDEX IF NOT EXISTS "people_relationships_related" ON "people_relationships" ("related");
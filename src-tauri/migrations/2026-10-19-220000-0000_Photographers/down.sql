@@ -0,0 +1,43 @@
+DROP TABLE cameras;
+
+-- SQLite can't drop a column with a foreign key, so the photos table is rebuilt
+CREATE TABLE
+    "photos_old" (
+        "name" TEXT NOT NULL UNIQUE,
+        "asset_path" TEXT NOT NULL,
+        "title" TEXT,
+        "description" TEXT,
+        "is_duplicate" INTEGER,
+        "rating" INTEGER,
+        "location" TEXT,
+        "thumbnail" TEXT,
+        "photo_group" TEXT,
+        "date" TEXT,
+        "hide_thumbnail" INTEGER,
+        "photographer" TEXT,
+        "metadata_date" TEXT,
+        "metadata_location" TEXT,
+        PRIMARY KEY ("name")
+    );
+
+INSERT INTO "photos_old"
+SELECT
+    "name",
+    "asset_path",
+    "title",
+    "description",
+    "is_duplicate",
+    "rating",
+    "location",
+    "thumbnail",
+    "photo_group",
+    "date",
+    "hide_thumbnail",
+    coalesce("photographer", "photographer_name"),
+    "metadata_date",
+    "metadata_location"
+FROM "photos";
+
+DROP TABLE "photos";
+
+ALTER TABLE "photos_old" RENAME TO "photos";
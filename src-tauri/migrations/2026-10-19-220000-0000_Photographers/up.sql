@@ -0,0 +1,45 @@
+-- Photographers are people now. Names that don't match anyone are kept in photographer_name until
+-- they are resolved to a person.
+ALTER TABLE "photos" RENAME COLUMN "photographer" TO "photographer_name";
+
+ALTER TABLE "photos" ADD COLUMN "photographer" TEXT REFERENCES "people" ("id") ON DELETE SET NULL;
+
+-- Most photographers were already person ids, and the rest are matched by name
+UPDATE "photos"
+SET
+    "photographer" = "photographer_name"
+WHERE "photographer_name" IN (SELECT "id" FROM "people");
+
+UPDATE "photos"
+SET
+    "photographer" = (
+        SELECT "id" FROM "people"
+        WHERE upper("name") = upper(trim("photos"."photographer_name"))
+        LIMIT 1
+    )
+WHERE "photographer" IS NULL AND "photographer_name" IS NOT NULL;
+
+UPDATE "photos" SET "photographer_name" = NULL WHERE "photographer" IS NOT NULL;
+
+UPDATE "autotag_rules"
+SET
+    "photographer" = (
+        SELECT "id" FROM "people"
+        WHERE upper("name") = upper(trim("autotag_rules"."photographer"))
+        LIMIT 1
+    )
+WHERE "photographer" NOT IN (SELECT "id" FROM "people")
+    AND EXISTS (
+        SELECT 1 FROM "people"
+        WHERE upper("name") = upper(trim("autotag_rules"."photographer"))
+    );
+
+-- The serial number of the camera body, from EXIF, so photos can get a default photographer
+ALTER TABLE "photos" ADD COLUMN "camera_serial" TEXT;
+
+CREATE TABLE
+    IF NOT EXISTS "cameras" (
+        "serial" TEXT NOT NULL UNIQUE,
+        "photographer" TEXT NOT NULL REFERENCES "people" ("id") ON DELETE CASCADE,
+        PRIMARY KEY ("serial")
+    );
@@ -0,0 +1,26 @@
+UPDATE "tags"
+SET
+    "aliases" = (SELECT group_concat("value", ',') FROM (SELECT "value" FROM json_each("tags"."aliases") ORDER BY "key"))
+WHERE "aliases" IS NOT NULL;
+
+UPDATE "tag_rules"
+SET
+    "tags" = (SELECT group_concat("value", ',') FROM (SELECT "value" FROM json_each("tag_rules"."tags") ORDER BY "key"))
+WHERE "tags" IS NOT NULL;
+
+UPDATE "autotag_rules"
+SET
+    "tags" = (SELECT group_concat("value", ',') FROM (SELECT "value" FROM json_each("autotag_rules"."tags") ORDER BY "key"))
+WHERE "tags" IS NOT NULL;
+
+UPDATE "autotag_rules"
+SET
+    "people" = (SELECT group_concat("value", ',') FROM (SELECT "value" FROM json_each("autotag_rules"."people") ORDER BY "key"))
+WHERE "people" IS NOT NULL;
+
+UPDATE "trips"
+SET
+    "shapes" = coalesce(
+        (SELECT group_concat("value", ',') FROM (SELECT "value" FROM json_each("trips"."shapes") ORDER BY "key")),
+        ''
+    );
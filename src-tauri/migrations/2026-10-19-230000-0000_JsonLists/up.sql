@@ -0,0 +1,64 @@
+-- Lists that are still kept in a single column are stored as JSON arrays instead of being joined
+-- with commas, so values can contain commas. Split the existing lists, keeping their order.
+CREATE TEMPORARY TABLE "split_values" AS
+WITH RECURSIVE
+    "split" ("owner", "kind", "value", "rest", "position") AS (
+        SELECT "name", 'alias', '', "aliases" || ',', -1 FROM "tags" WHERE "aliases" IS NOT NULL
+        UNION ALL
+        SELECT "id", 'rule_tag', '', "tags" || ',', -1 FROM "tag_rules" WHERE "tags" IS NOT NULL
+        UNION ALL
+        SELECT "id", 'autotag_tag', '', "tags" || ',', -1 FROM "autotag_rules" WHERE "tags" IS NOT NULL
+        UNION ALL
+        SELECT "id", 'autotag_person', '', "people" || ',', -1 FROM "autotag_rules" WHERE "people" IS NOT NULL
+        UNION ALL
+        SELECT "id", 'shape', '', "shapes" || ',', -1 FROM "trips"
+        UNION ALL
+        SELECT
+            "owner",
+            "kind",
+            substr("rest", 1, instr("rest", ',') - 1),
+            substr("rest", instr("rest", ',') + 1),
+            "position" + 1
+        FROM "split"
+        WHERE "rest" <> ''
+    )
+SELECT "owner", "kind", "value", "position" FROM "split" WHERE "position" >= 0 AND "value" <> '';
+
+-- Empty lists are NULL, except for trips where the column can't be
+UPDATE "tags"
+SET
+    "aliases" = (
+        SELECT json_group_array("value")
+        FROM (SELECT "value" FROM "split_values" WHERE "owner" = "tags"."name" AND "kind" = 'alias' ORDER BY "position")
+        HAVING count(*) > 0
+    );
+
+UPDATE "tag_rules"
+SET
+    "tags" = (
+        SELECT json_group_array("value")
+        FROM (SELECT "value" FROM "split_values" WHERE "owner" = "tag_rules"."id" AND "kind" = 'rule_tag' ORDER BY "position")
+        HAVING count(*) > 0
+    );
+
+UPDATE "autotag_rules"
+SET
+    "tags" = (
+        SELECT json_group_array("value")
+        FROM (SELECT "value" FROM "split_values" WHERE "owner" = "autotag_rules"."id" AND "kind" = 'autotag_tag' ORDER BY "position")
+        HAVING count(*) > 0
+    ),
+    "people" = (
+        SELECT json_group_array("value")
+        FROM (SELECT "value" FROM "split_values" WHERE "owner" = "autotag_rules"."id" AND "kind" = 'autotag_person' ORDER BY "position")
+        HAVING count(*) > 0
+    );
+
+UPDATE "trips"
+SET
+    "shapes" = (
+        SELECT json_group_array("value")
+        FROM (SELECT "value" FROM "split_values" WHERE "owner" = "trips"."id" AND "kind" = 'shape' ORDER BY "position")
+    );
+
+DROP TABLE "split_values";
@@ -6,11 +6,16 @@ use tauri::{AppHandle, Manager, Runtime};
 
 use crate::{
     app::{
+        graph::{get_graph as _get_graph, Graph, NodeType},
         initialize as _initialize, refresh as _refresh, remove_deleted as _remove_deleted,
-        search::{search_photos, Sort},
+        search::{
+            check_query as _check_query, explain_search as _explain_search, search_photos,
+            suggest_query as _suggest_query, QueryError, QuerySuggestion, SearchExplanation,
+            SortOrder,
+        },
         ApiError, LoadedPhotos,
     },
-    photos::PhotoDto,
+    photos::{api::emit_validation, PhotoDto},
 };
 
 #[tauri::command]
@@ -20,20 +25,66 @@ pub async fn initialize<R: Runtime>(
 ) -> Result<LoadedPhotos, ApiError> {
     debug!("Initializing with path {path}");
 
-    Ok(_initialize(&path, &app.path().app_data_dir()?)
+    let mut photo_load = _initialize(&path, &app.path().app_data_dir()?)
         .await
-        .with_context(|| format!("Failed to initialize Photo Manager at {path}"))?)
+        .with_context(|| format!("Failed to initialize Photo Manager at {path}"))?;
+    emit_validation(&app, std::mem::take(&mut photo_load.validation))?;
+    Ok(photo_load)
 }
 
 #[tauri::command]
 pub async fn photo_grid(query: Vec<String>, sort: String) -> Result<Vec<PhotoDto>, ApiError> {
-    Ok(search_photos(&query, Sort::from_str(&sort)?)
-        .await?
+    let results = search_photos(&query, SortOrder::from_str(&sort)?).await?;
+    Ok(results
+        .photos
         .iter()
-        .map(PhotoDto::from)
+        .map(|photo| {
+            let mut dto = PhotoDto::from(photo);
+            dto.group_size = photo
+                .photo_group
+                .as_ref()
+                .and_then(|group| results.group_sizes.get(group))
+                .copied();
+            dto
+        })
         .collect::<Vec<PhotoDto>>())
 }
 
+#[tauri::command]
+pub async fn explain_search(
+    query: Vec<String>,
+    sort: String,
+) -> Result<SearchExplanation, ApiError> {
+    Ok(_explain_search(&query, SortOrder::from_str(&sort)?)
+        .await
+        .with_context(|| format!("Could not explain search {}", query.join(",")))?)
+}
+
+/// Gets the graph of tags, people, places and trips. `node_types` limits the kinds of nodes, and
+/// `min_weight` hides co-occurrence edges between nodes that share fewer photos.
+#[tauri::command]
+pub async fn get_graph(
+    query: Option<Vec<String>>,
+    min_weight: Option<usize>,
+    node_types: Option<Vec<String>>,
+) -> Result<Graph, ApiError> {
+    let node_types = node_types
+        .map(|types| {
+            types
+                .iter()
+                .map(|t| NodeType::from_str(t))
+                .collect::<Result<Vec<NodeType>, _>>()
+        })
+        .transpose()?;
+    Ok(_get_graph(
+        query.as_ref(),
+        min_weight.unwrap_or(1),
+        node_types.as_deref(),
+    )
+    .await
+    .with_context(|| "Failed to build graph".to_string())?)
+}
+
 #[tauri::command]
 pub async fn remove_deleted(deleted: Vec<String>) -> Result<(), ApiError> {
     debug!(
@@ -46,10 +97,26 @@ pub async fn remove_deleted(deleted: Vec<String>) -> Result<(), ApiError> {
 }
 
 #[tauri::command]
-pub async fn refresh() -> Result<Vec<String>, ApiError> {
+pub async fn refresh<R: Runtime>(app: AppHandle<R>) -> Result<Vec<String>, ApiError> {
     debug!("Refreshing photos");
 
-    Ok(_refresh()
+    let photo_load = _refresh()
+        .await
+        .with_context(|| "Failed to refresh photos")?;
+    emit_validation(&app, photo_load.validation)?;
+    Ok(photo_load.removed)
+}
+
+#[tauri::command]
+pub async fn suggest_query(partial: String) -> Result<Vec<QuerySuggestion>, ApiError> {
+    Ok(_suggest_query(&partial)
+        .await
+        .with_context(|| format!("Could not get suggestions for {partial}"))?)
+}
+
+#[tauri::command]
+pub async fn check_query(query: Vec<String>) -> Result<Vec<QueryError>, ApiError> {
+    Ok(_check_query(&query)
         .await
-        .with_context(|| "Failed to refresh photos")?)
+        .with_context(|| "Could not check query".to_string())?)
 }
@@ -0,0 +1,330 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
+};
+
+use anyhow::Result;
+use diesel::QueryDsl;
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+use strum::{Display, EnumString};
+
+use crate::{
+    app::{
+        ensure_db, row_to_vec,
+        search::{search_photos, SortOrder},
+        DB,
+    },
+    models::{Photo, Shape},
+    people::PEOPLE,
+    photos::PHOTOS,
+    places::{LAYERS, PLACES},
+    schema::{shapes, trips},
+    tags::TAGS,
+};
+
+#[derive(Clone, Copy, Display, EnumString, Eq, Hash, PartialEq, Serialize)]
+pub enum NodeType {
+    #[strum(ascii_case_insensitive)]
+    Tag,
+    #[strum(ascii_case_insensitive)]
+    Person,
+    #[strum(ascii_case_insensitive)]
+    Photographer,
+    #[strum(ascii_case_insensitive)]
+    Place,
+    #[strum(ascii_case_insensitive)]
+    Layer,
+    #[strum(ascii_case_insensitive)]
+    Shape,
+    #[strum(ascii_case_insensitive)]
+    Trip,
+}
+
+impl NodeType {
+    /// Node ids are prefixed with their type, since a tag and a person can share a name.
+    fn node_id(&self, key: &str) -> String {
+        format!("{}:{key}", self.to_string().to_lowercase())
+    }
+}
+
+#[derive(Clone, Copy, Display, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum EdgeType {
+    /// Both nodes appear on the same photos; the weight is the number of photos
+    CoOccurrence,
+    Prereq,
+    Coreq,
+    Incompatible,
+    /// A place's layer
+    Layer,
+    /// The shape outlining a place
+    Shape,
+    /// A shape on a trip's route
+    Trip,
+}
+
+#[derive(Serialize)]
+pub struct GraphNode {
+    pub id: String,
+    pub node_type: NodeType,
+    /// The id of the tag, person, place, etc. that the node represents
+    pub key: String,
+    pub label: String,
+    /// The number of photos in scope that the node appears on
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+    pub edge_type: EdgeType,
+    pub weight: usize,
+}
+
+#[derive(Default, Serialize)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// Adds a node if it isn't in the graph yet and adds to its photo count, returning its id.
+fn add_node(
+    nodes: &mut BTreeMap<String, GraphNode>,
+    node_type: NodeType,
+    key: &str,
+    label: &str,
+    count: usize,
+) -> String {
+    let id = node_type.node_id(key);
+    nodes
+        .entry(id.clone())
+        .or_insert_with(|| GraphNode {
+            id: id.clone(),
+            node_type,
+            key: key.to_owned(),
+            label: label.to_owned(),
+            count: 0,
+        })
+        .count += count;
+    id
+}
+
+/// Builds a graph of the tags, people, photographers and places on photos, and the layers, shapes
+/// and trips they belong to. Photos can be limited with a search query, and node types with
+/// `node_types`. `min_weight` only applies to co-occurrence edges; relationships that were set
+/// explicitly are always included.
+pub async fn get_graph(
+    query: Option<&Vec<String>>,
+    min_weight: usize,
+    node_types: Option<&[NodeType]>,
+) -> Result<Graph> {
+    let included = |node_type: NodeType| node_types.is_none_or(|types| types.contains(&node_type));
+
+    let photos = match query {
+        Some(query) => {
+            search_photos(query, SortOrder::from_str("name")?)
+                .await?
+                .photos
+        }
+        None => PHOTOS
+            .lock()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<Photo>>(),
+    };
+    let places = PLACES.lock().await.clone();
+    let layers = LAYERS.lock().await.clone();
+    let people_names = PEOPLE
+        .lock()
+        .await
+        .values()
+        .map(|person| (person.id.clone(), person.name.clone()))
+        .collect::<HashMap<String, String>>();
+    let tags = TAGS.lock().await.clone();
+    ensure_db().await?;
+    let (shapes_data, trips_data) = {
+        let mut conn = DB.lock().await;
+        let conn = conn.as_mut().unwrap();
+        (
+            shapes::table.load::<Shape>(conn).await?,
+            trips::table
+                .select((trips::id, trips::name, trips::shapes))
+                .load::<(String, String, Option<String>)>(conn)
+                .await?,
+        )
+    };
+
+    let mut nodes = BTreeMap::<String, GraphNode>::new();
+    let mut co_occurrence = HashMap::<(String, String), usize>::new();
+    for photo in &photos {
+        let mut on_photo = Vec::<String>::new();
+        if included(NodeType::Tag) {
+            for tag in photo.tags() {
+                on_photo.push(add_node(&mut nodes, NodeType::Tag, &tag, &tag, 1));
+            }
+        }
+        if included(NodeType::Person) {
+            for person in photo.people() {
+                let label = people_names.get(&person).unwrap_or(&person);
+                on_photo.push(add_node(&mut nodes, NodeType::Person, &person, label, 1));
+            }
+        }
+        if included(NodeType::Photographer) {
+            if let Some(photographer) = photo
+                .photographer
+                .as_ref()
+                .or(photo.photographer_name.as_ref())
+            {
+                let label = people_names.get(photographer).unwrap_or(photographer);
+                on_photo.push(add_node(
+                    &mut nodes,
+                    NodeType::Photographer,
+                    photographer,
+                    label,
+                    1,
+                ));
+            }
+        }
+        if included(NodeType::Place) {
+            if let Some(place) = &photo.location {
+                let label = places.get(place).map(|p| &p.name).unwrap_or(place);
+                on_photo.push(add_node(&mut nodes, NodeType::Place, place, label, 1));
+            }
+        }
+        on_photo.sort();
+        on_photo.dedup();
+        for (i, a) in on_photo.iter().enumerate() {
+            for b in &on_photo[i + 1..] {
+                *co_occurrence.entry((a.clone(), b.clone())).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut edges = co_occurrence
+        .into_iter()
+        .filter(|(_, weight)| *weight >= min_weight.max(1))
+        .map(|((source, target), weight)| GraphEdge {
+            source,
+            target,
+            edge_type: EdgeType::CoOccurrence,
+            weight,
+        })
+        .collect::<Vec<GraphEdge>>();
+
+    // Without a query, the whole library is in scope, so unused tags and places still appear
+    if query.is_none() {
+        if included(NodeType::Tag) {
+            for tag in tags.keys() {
+                add_node(&mut nodes, NodeType::Tag, tag, tag, 0);
+            }
+        }
+        if included(NodeType::Place) {
+            for place in places.values() {
+                add_node(&mut nodes, NodeType::Place, &place.id, &place.name, 0);
+            }
+        }
+    }
+
+    if included(NodeType::Tag) {
+        for tag in tags.values() {
+            let source = NodeType::Tag.node_id(&tag.name);
+            if !nodes.contains_key(&source) {
+                continue;
+            }
+            for (edge_type, related) in [
+                (EdgeType::Prereq, tag.prereqs()),
+                (EdgeType::Coreq, tag.coreqs()),
+                (EdgeType::Incompatible, tag.incompatible()),
+            ] {
+                for other in related {
+                    let target = NodeType::Tag.node_id(&other);
+                    if nodes.contains_key(&target) {
+                        edges.push(GraphEdge {
+                            source: source.clone(),
+                            target,
+                            edge_type,
+                            weight: 1,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    // Layers and shapes are brought in by the places in scope, and trips by their shapes
+    let place_keys = nodes
+        .values()
+        .filter(|node| node.node_type == NodeType::Place)
+        .map(|node| node.key.clone())
+        .collect::<Vec<String>>();
+    let mut scoped_shapes = HashSet::<String>::new();
+    for place in place_keys.iter().filter_map(|key| places.get(key)) {
+        let source = NodeType::Place.node_id(&place.id);
+        if included(NodeType::Layer) {
+            if let Some(layer) = layers.get(&place.layer) {
+                let target = add_node(&mut nodes, NodeType::Layer, &layer.id, &layer.name, 0);
+                edges.push(GraphEdge {
+                    source: source.clone(),
+                    target,
+                    edge_type: EdgeType::Layer,
+                    weight: nodes[&source].count.max(1),
+                });
+            }
+        }
+        if let Some(shape) = place
+            .shape
+            .as_ref()
+            .and_then(|id| shapes_data.iter().find(|s| s.id == *id))
+        {
+            scoped_shapes.insert(shape.id.clone());
+            if included(NodeType::Shape) {
+                let target = add_node(&mut nodes, NodeType::Shape, &shape.id, &shape.name, 0);
+                edges.push(GraphEdge {
+                    source: source.clone(),
+                    target,
+                    edge_type: EdgeType::Shape,
+                    weight: 1,
+                });
+            }
+        }
+    }
+    if query.is_none() {
+        scoped_shapes.extend(shapes_data.iter().map(|shape| shape.id.clone()));
+    }
+    for (id, name, trip_shapes) in &trips_data {
+        let trip_shapes = row_to_vec(trip_shapes)
+            .into_iter()
+            .filter(|shape| scoped_shapes.contains(shape))
+            .collect::<Vec<String>>();
+        if trip_shapes.is_empty() && query.is_some() {
+            continue;
+        }
+        if included(NodeType::Trip) {
+            let target = add_node(&mut nodes, NodeType::Trip, id, name, 0);
+            if included(NodeType::Shape) {
+                for shape in trip_shapes
+                    .iter()
+                    .filter_map(|id| shapes_data.iter().find(|s| s.id == *id))
+                {
+                    let source = add_node(&mut nodes, NodeType::Shape, &shape.id, &shape.name, 0);
+                    edges.push(GraphEdge {
+                        source,
+                        target: target.clone(),
+                        edge_type: EdgeType::Trip,
+                        weight: 1,
+                    });
+                }
+            }
+        }
+    }
+
+    edges.sort_by(|a, b| {
+        (a.edge_type, &a.source, &a.target).cmp(&(b.edge_type, &b.source, &b.target))
+    });
+    Ok(Graph {
+        nodes: nodes.into_values().collect(),
+        edges,
+    })
+}
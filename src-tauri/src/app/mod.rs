@@ -12,31 +12,47 @@ use std::{
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, NaiveDateTime, Utc};
 use diesel::{
-    delete, dsl::update, insert_into, BoolExpressionMethods, Connection, ExpressionMethods,
-    QueryDsl, SqliteConnection,
+    delete, deserialize, dsl::update, insert_into, sql_query, sql_types::Text, sqlite::Sqlite,
+    BoolExpressionMethods, Connection, ExpressionMethods, QueryDsl, Queryable, SelectableHelper,
+    SqliteConnection,
+};
+use diesel_async::{
+    scoped_futures::ScopedFutureExt, sync_connection_wrapper::SyncConnectionWrapper,
+    AsyncConnection, RunQueryDsl,
 };
-use diesel_async::{sync_connection_wrapper::SyncConnectionWrapper, AsyncConnection, RunQueryDsl};
 use diesel_migrations::MigrationHarness;
 use exif::In;
 use log::{debug, error, info, warn};
 use regex::Regex;
 use rusty_pool::{JoinHandle, ThreadPool};
 use serde::{Serialize, Serializer};
+use tauri::async_runtime;
 use thiserror::Error;
 use tokio::{fs, sync::Mutex as AsyncMutex};
 use walkdir::WalkDir;
 
 use crate::{
-    models::{Layer, Person, Photo, Place, Tag},
-    people::{PEOPLE, PEOPLE_COUNTS, PHOTOGRAPHER_COUNTS},
+    autotag::run_autotag_rules,
+    models::{Layer, Person, PersonCategory, Photo, Place, Tag, TagCategory, TagRule},
+    people::{
+        categories::PEOPLE_CATEGORIES, photographers::apply_camera_photographers, PEOPLE,
+        PEOPLE_COUNTS, PHOTOGRAPHER_COUNTS,
+    },
     photos::{get_asset_path, PHOTOS, RAW, VALIDATION_CACHE, VIDEO},
     places::{LAYERS, LAYER_COUNTS, PLACES, PLACE_COUNTS},
-    schema::{layers, people, photos, places, tags},
-    tags::{validate_tags, TAGS, TAG_COUNTS},
+    schema::{layers, people, people_categories, photos, places, tag_categories, tag_rules, tags},
+    settings::{get_setting, set_setting, Settings},
+    tags::{
+        categories::TAG_CATEGORIES,
+        count_total_tags, revalidate_photos,
+        rules::{rule_context, TAG_RULES},
+        validate_photo_with, ValidationChange, TAGS, TAG_COUNTS, TAG_TOTAL_COUNTS,
+    },
     MIGRATIONS,
 };
 
 pub mod api;
+pub mod graph;
 pub mod search;
 
 pub const DATE_FORMAT: &str = "%F";
@@ -46,23 +62,40 @@ pub static DB: LazyLock<AsyncMutex<Option<SyncConnectionWrapper<SqliteConnection
 pub static OPEN_FOLDER: LazyLock<Mutex<Option<PathBuf>>> = LazyLock::new(|| Mutex::new(None));
 pub static THUMBNAIL_DIR: LazyLock<Mutex<Option<PathBuf>>> = LazyLock::new(|| Mutex::new(None));
 
+/// Reads a list kept in a single column as a JSON array.
 pub fn row_to_vec(row_text: &Option<String>) -> Vec<String> {
-    if row_text.is_none() {
+    let Some(row_text) = row_text else {
         return Vec::new();
-    }
-    let row_text = row_text.as_ref().unwrap();
-    let mut re = vec![];
-    if !row_text.is_empty() {
-        re = row_text.split(",").map(str::to_string).collect();
-    }
-    re
+    };
+    serde_json::from_str(row_text).unwrap_or_else(|err| {
+        warn!("Malformed list {row_text}: {err}");
+        Vec::new()
+    })
 }
 
+/// Writes a list to a single column as a JSON array, or NULL if it is empty.
 pub fn vec_to_row(row_vec: &[String]) -> Option<String> {
     if row_vec.is_empty() {
         None
     } else {
-        Some(row_vec.join(","))
+        serde_json::to_string(row_vec).ok()
+    }
+}
+
+/// A list kept in one of the join tables, loaded as a JSON array along with the row it belongs to.
+pub struct JsonList(Vec<String>);
+
+impl Queryable<Text, Sqlite> for JsonList {
+    type Row = String;
+
+    fn build(row: String) -> deserialize::Result<Self> {
+        Ok(JsonList(serde_json::from_str(&row)?))
+    }
+}
+
+impl From<JsonList> for Vec<String> {
+    fn from(value: JsonList) -> Self {
+        value.0
     }
 }
 
@@ -100,6 +133,9 @@ impl Serialize for ApiError {
 pub struct LoadedPhotos {
     removed: Vec<String>,
     new_photos: Vec<String>,
+    /// Photos whose validation result changed while loading, sent as an event instead
+    #[serde(skip)]
+    validation: Vec<ValidationChange>,
 }
 
 fn clean_thumbnail_path(path: &str) -> String {
@@ -129,6 +165,7 @@ pub async fn get_photo_targets(id: &String) -> Result<Vec<Photo>> {
     targets.push(
         photos::table
             .filter(photos::name.eq(id))
+            .select(Photo::as_select())
             .first::<Photo>(DB.lock().await.as_mut().unwrap())
             .await?,
     );
@@ -140,6 +177,7 @@ pub async fn get_photo_targets(id: &String) -> Result<Vec<Photo>> {
                     .eq(existing_group.as_ref().unwrap())
                     .and(photos::name.ne(id)),
             )
+            .select(Photo::as_select())
             .load::<Photo>(DB.lock().await.as_mut().unwrap())
             .await?
         {
@@ -154,6 +192,7 @@ pub async fn get_photo_targets(id: &String) -> Result<Vec<Photo>> {
         targets.push(
             photos::table
                 .filter(photos::name.eq(raw))
+                .select(Photo::as_select())
                 .first::<Photo>(DB.lock().await.as_mut().unwrap())
                 .await?,
         );
@@ -177,6 +216,28 @@ fn degrees_to_dec(input: &str) -> Result<f32> {
     Ok(digits[0] + (digits[1] / 60.0) + (digits[2] / 3600.0))
 }
 
+/// Reads the serial number of the camera body from a photo's EXIF data.
+fn camera_serial(exif: &exif::Exif) -> Option<String> {
+    let serial = exif.get_field(exif::Tag::BodySerialNumber, In::PRIMARY)?;
+    let exif::Value::Ascii(ref values) = serial.value else {
+        return None;
+    };
+    values
+        .iter()
+        .map(|value| String::from_utf8_lossy(value).trim().to_string())
+        .find(|value| !value.is_empty())
+}
+
+/// Opens a file and reads the camera serial from its EXIF data, if it has any.
+fn read_camera_serial(filename: &String) -> Option<String> {
+    let file_open = File::open(filename).ok()?;
+    let mut file_reader = BufReader::new(file_open);
+    let exif = exif::Reader::new()
+        .read_from_container(&mut file_reader)
+        .ok()?;
+    camera_serial(&exif)
+}
+
 // Reads EXIF/filesystem metadata and returns a filled Photo. Purely synchronous
 // so it can run safely inside a thread pool without holding async mutex guards.
 fn prepare_photo(_photo: &Photo) -> Result<Photo> {
@@ -206,6 +267,11 @@ fn prepare_photo(_photo: &Photo) -> Result<Photo> {
             }
         }
 
+        let serial = camera_serial(&exif);
+        if serial.is_some() {
+            photo.camera_serial = serial;
+        }
+
         let lat = exif.get_field(exif::Tag::GPSLatitude, In::PRIMARY);
         let lng = exif.get_field(exif::Tag::GPSLongitude, In::PRIMARY);
         if let (Some(lat), Some(lng)) = (lat, lng) {
@@ -357,7 +423,101 @@ fn generate_thumbnail(filename: &String, thumbnail_dir: &Path) -> Result<String>
     Ok(get_asset_path(&thumbnail_path_str))
 }
 
+/// Fills in the default photographers of the cameras that took newly loaded photos, then runs the
+/// auto-tagging rules on them. A broken rule shouldn't stop the folder from loading, so failures
+/// are only logged. Returns the photos whose validation result changed.
+async fn autotag_new_photos(new_photos: &[String]) -> Vec<ValidationChange> {
+    if new_photos.is_empty() {
+        return Vec::new();
+    }
+    let mut validation = apply_camera_photographers(new_photos)
+        .await
+        .unwrap_or_else(|err| {
+            warn!("Failed to set photographers from cameras: {err}");
+            Vec::new()
+        });
+    debug!(
+        "Running auto-tagging rules on {} new photos",
+        new_photos.len()
+    );
+    match run_autotag_rules(Some(new_photos), false).await {
+        Ok((_, changes)) => validation.extend(changes),
+        Err(err) => warn!("Failed to run auto-tagging rules: {err}"),
+    }
+    validation
+}
+
+/// Photos added before camera serials were read from EXIF don't have one, so read them once and
+/// give those photos their cameras' default photographers. Reading every file is slow, so a
+/// setting records that the pass has run and photos without a serial aren't checked again.
+/// Returns the photos whose validation result changed.
+async fn backfill_camera_serials() -> Result<Vec<ValidationChange>> {
+    if get_setting(Settings::CameraSerialsRead).await.is_ok() {
+        return Ok(Vec::new());
+    }
+    let missing = PHOTOS
+        .lock()
+        .await
+        .values()
+        .filter(|photo| photo.camera_serial.is_none() && !photo.is_video())
+        .map(|photo| photo.name.clone())
+        .collect::<Vec<String>>();
+    info!("Reading camera serials for {} photos", missing.len());
+    let serials = async_runtime::spawn_blocking(move || {
+        missing
+            .into_iter()
+            .filter_map(|name| read_camera_serial(&name).map(|serial| (name, serial)))
+            .collect::<Vec<(String, String)>>()
+    })
+    .await?;
+
+    if !serials.is_empty() {
+        let mut photos_cache = PHOTOS.lock().await;
+        let mut conn = DB.lock().await;
+        let conn = conn.as_mut().unwrap();
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            let serials = &serials;
+            async move {
+                for (name, serial) in serials {
+                    update(photos::table.filter(photos::name.eq(name)))
+                        .set(photos::camera_serial.eq(serial))
+                        .execute(conn)
+                        .await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await?;
+        for (name, serial) in &serials {
+            if let Some(photo) = photos_cache.get_mut(name) {
+                photo.camera_serial = Some(serial.clone());
+            }
+        }
+    }
+    set_setting(Settings::CameraSerialsRead, true.to_string()).await?;
+    debug!("Read {} camera serials", serials.len());
+
+    let names = serials
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<String>>();
+    apply_camera_photographers(&names).await
+}
+
 async fn load_photos() -> Result<LoadedPhotos> {
+    let mut photo_load = read_photos().await?;
+    photo_load.validation = backfill_camera_serials().await.unwrap_or_else(|err| {
+        warn!("Failed to read camera serials: {err}");
+        Vec::new()
+    });
+    photo_load
+        .validation
+        .extend(autotag_new_photos(&photo_load.new_photos).await);
+    Ok(photo_load)
+}
+
+async fn read_photos() -> Result<LoadedPhotos> {
     let path = {
         let guard = OPEN_FOLDER
             .lock()
@@ -385,7 +545,10 @@ async fn load_photos() -> Result<LoadedPhotos> {
     {
         let mut conn = DB.lock().await;
         let conn = conn.as_mut().unwrap();
-        let photo_load = photos::table.load::<Photo>(conn).await;
+        let photo_load = photos::table
+            .select(Photo::as_select())
+            .load::<Photo>(conn)
+            .await;
         if photo_load.is_err() {
             return Err(anyhow!(
                 "Failed to load photos from database: {}",
@@ -510,9 +673,13 @@ async fn load_photos() -> Result<LoadedPhotos> {
 
     debug!("Validating photos");
     let mut validations = Vec::new();
-    for photo in photos.values() {
-        let validation = validate_tags(&photo.tags()).await?;
-        validations.push((photo.name.clone(), validation));
+    let context = rule_context().await;
+    {
+        let tags = TAGS.lock().await;
+        for photo in photos.values() {
+            let validation = validate_photo_with(photo, &tags, &context);
+            validations.push((photo.name.clone(), validation));
+        }
     }
 
     {
@@ -528,6 +695,7 @@ async fn load_photos() -> Result<LoadedPhotos> {
     Ok(LoadedPhotos {
         removed: existing.keys().cloned().collect(),
         new_photos,
+        validation: Vec::new(),
     })
 }
 
@@ -564,6 +732,10 @@ pub async fn initialize(path: &String, app_dir: &Path) -> Result<LoadedPhotos> {
         ));
     }
     let mut conn = conn.unwrap();
+    // SQLite only enforces foreign keys, and cascades deletes to the join tables, when asked to
+    sql_query("PRAGMA foreign_keys = ON")
+        .execute(&mut conn)
+        .await?;
     debug!("Loaded async database connection from {db_path}");
 
     let thumbnail_dir = app_dir.join("thumbnails");
@@ -576,8 +748,16 @@ pub async fn initialize(path: &String, app_dir: &Path) -> Result<LoadedPhotos> {
 
     let layers_data = layers::table.load::<Layer>(&mut conn).await?;
     let places_data = places::table.load::<Place>(&mut conn).await?;
-    let tags_data = tags::table.load::<Tag>(&mut conn).await?;
+    let tags_data = tags::table
+        .select(Tag::as_select())
+        .load::<Tag>(&mut conn)
+        .await?;
     let people_data = people::table.load::<Person>(&mut conn).await?;
+    let rules_data = tag_rules::table.load::<TagRule>(&mut conn).await?;
+    let tag_categories_data = tag_categories::table.load::<TagCategory>(&mut conn).await?;
+    let people_categories_data = people_categories::table
+        .load::<PersonCategory>(&mut conn)
+        .await?;
 
     *DB.lock().await = Some(conn);
     *OPEN_FOLDER
@@ -586,94 +766,121 @@ pub async fn initialize(path: &String, app_dir: &Path) -> Result<LoadedPhotos> {
     *THUMBNAIL_DIR
         .lock()
         .map_err(|_| anyhow!("Lock is poisoned"))? = Some(thumbnail_dir);
+    *TAG_RULES.lock().await = rules_data
+        .into_iter()
+        .map(|rule| (rule.id.clone(), rule))
+        .collect::<HashMap<String, TagRule>>();
+    *TAG_CATEGORIES.lock().await = tag_categories_data
+        .into_iter()
+        .map(|category| (category.id.clone(), category))
+        .collect::<HashMap<String, TagCategory>>();
+    *PEOPLE_CATEGORIES.lock().await = people_categories_data
+        .into_iter()
+        .map(|category| (category.id.clone(), category))
+        .collect::<HashMap<String, PersonCategory>>();
+
+    let mut photo_load = read_photos().await?;
 
-    let photo_load = load_photos().await?;
-
-    let mut layers = LAYERS.lock().await;
-    let mut places = PLACES.lock().await;
-    let mut tags = TAGS.lock().await;
-    let mut people = PEOPLE.lock().await;
-    let loaded_photos = PHOTOS.lock().await;
-    let mut layer_counts = LAYER_COUNTS.lock().unwrap();
-    let mut place_counts = PLACE_COUNTS.lock().unwrap();
-    let mut tag_counts = TAG_COUNTS.lock().unwrap();
-    let mut people_counts = PEOPLE_COUNTS.lock().unwrap();
-    let mut photographer_counts = PHOTOGRAPHER_COUNTS.lock().unwrap();
-
-    *layers = HashMap::<String, Layer>::new();
-    *places = HashMap::<String, Place>::new();
-    *tags = HashMap::<String, Tag>::new();
-    *people = HashMap::<String, Person>::new();
-    *layer_counts = HashMap::<String, usize>::new();
-    *place_counts = HashMap::<String, usize>::new();
-    *tag_counts = HashMap::<String, usize>::new();
-    *people_counts = HashMap::<String, usize>::new();
-    *photographer_counts = HashMap::<String, usize>::new();
-
-    for layer in layers_data {
-        layer_counts.insert(layer.id.clone(), 0);
-        layers.insert(layer.id.clone(), layer);
-    }
-
-    for place in places_data {
-        place_counts.insert(place.id.clone(), 0);
-        if layer_counts.contains_key(&place.layer) {
-            *layer_counts.get_mut(&place.layer).unwrap() += 1;
+    {
+        let mut layers = LAYERS.lock().await;
+        let mut places = PLACES.lock().await;
+        let mut tags = TAGS.lock().await;
+        let mut people = PEOPLE.lock().await;
+        let loaded_photos = PHOTOS.lock().await;
+        let mut layer_counts = LAYER_COUNTS.lock().unwrap();
+        let mut place_counts = PLACE_COUNTS.lock().unwrap();
+        let mut tag_counts = TAG_COUNTS.lock().unwrap();
+        let mut people_counts = PEOPLE_COUNTS.lock().unwrap();
+        let mut photographer_counts = PHOTOGRAPHER_COUNTS.lock().unwrap();
+
+        *layers = HashMap::<String, Layer>::new();
+        *places = HashMap::<String, Place>::new();
+        *tags = HashMap::<String, Tag>::new();
+        *people = HashMap::<String, Person>::new();
+        *layer_counts = HashMap::<String, usize>::new();
+        *place_counts = HashMap::<String, usize>::new();
+        *tag_counts = HashMap::<String, usize>::new();
+        *people_counts = HashMap::<String, usize>::new();
+        *photographer_counts = HashMap::<String, usize>::new();
+
+        for layer in layers_data {
+            layer_counts.insert(layer.id.clone(), 0);
+            layers.insert(layer.id.clone(), layer);
         }
-        places.insert(place.id.clone(), place);
-    }
 
-    for tag in tags_data {
-        tag_counts.insert(tag.name.clone(), 0);
-        tags.insert(tag.name.clone(), tag);
-    }
+        for place in places_data {
+            place_counts.insert(place.id.clone(), 0);
+            if layer_counts.contains_key(&place.layer) {
+                *layer_counts.get_mut(&place.layer).unwrap() += 1;
+            }
+            places.insert(place.id.clone(), place);
+        }
 
-    for person in people_data {
-        people_counts.insert(person.id.clone(), 0);
-        people.insert(person.id.clone(), person);
-    }
+        for tag in tags_data {
+            tag_counts.insert(tag.name.clone(), 0);
+            tags.insert(tag.name.clone(), tag);
+        }
 
-    for photo in loaded_photos.values() {
-        for tag in photo.tags() {
-            if !tags.contains_key(&tag) {
-                tags.insert(tag.clone(), Tag::new(&tag));
-            }
-            if tag_counts.contains_key(&tag) {
-                *tag_counts.get_mut(&tag).unwrap() += 1;
-            } else {
-                tag_counts.insert(tag.clone(), 1);
-            }
+        for person in people_data {
+            people_counts.insert(person.id.clone(), 0);
+            people.insert(person.id.clone(), person);
         }
-        for person in photo.people() {
-            if people_counts.contains_key(&person) {
-                *people_counts.get_mut(&person).unwrap() += 1;
-            } else {
-                people_counts.insert(person.clone(), 1);
+
+        for photo in loaded_photos.values() {
+            for tag in photo.tags() {
+                if !tags.contains_key(&tag) {
+                    tags.insert(tag.clone(), Tag::new(&tag));
+                }
+                if tag_counts.contains_key(&tag) {
+                    *tag_counts.get_mut(&tag).unwrap() += 1;
+                } else {
+                    tag_counts.insert(tag.clone(), 1);
+                }
             }
-        }
-        if let Some(photographer) = &photo.photographer {
-            if photographer_counts.contains_key(photographer) {
-                *photographer_counts.get_mut(photographer).unwrap() += 1;
-            } else {
-                photographer_counts.insert(photographer.clone(), 1);
+            for person in photo.people() {
+                if people_counts.contains_key(&person) {
+                    *people_counts.get_mut(&person).unwrap() += 1;
+                } else {
+                    people_counts.insert(person.clone(), 1);
+                }
             }
-        }
-        if let Some(location) = &photo.location {
-            if places.contains_key(location) {
-                if place_counts.contains_key(location) {
-                    *place_counts.get_mut(location).unwrap() += 1;
+            if let Some(photographer) = &photo.photographer {
+                if photographer_counts.contains_key(photographer) {
+                    *photographer_counts.get_mut(photographer).unwrap() += 1;
                 } else {
-                    place_counts.insert(location.clone(), 1);
+                    photographer_counts.insert(photographer.clone(), 1);
+                }
+            }
+            if let Some(location) = &photo.location {
+                if places.contains_key(location) {
+                    if place_counts.contains_key(location) {
+                        *place_counts.get_mut(location).unwrap() += 1;
+                    } else {
+                        place_counts.insert(location.clone(), 1);
+                    }
+                } else {
+                    warn!(
+                        "Place referenced by photo {0} not found: {1}",
+                        photo.name, location
+                    );
                 }
-            } else {
-                warn!(
-                    "Place referenced by photo {0} not found: {1}",
-                    photo.name, location
-                );
             }
         }
+        *TAG_TOTAL_COUNTS.lock().unwrap() =
+            count_total_tags(loaded_photos.values().map(|photo| &photo.tags), &tags);
     }
 
+    let mut validation = backfill_camera_serials().await.unwrap_or_else(|err| {
+        warn!("Failed to read camera serials: {err}");
+        Vec::new()
+    });
+    // The rules can search by tag, place, and person, so wait until those caches are filled
+    validation.extend(autotag_new_photos(&photo_load.new_photos).await);
+
+    // Photos were validated before the tag and place caches were filled, so check them again now
+    validation.extend(revalidate_photos(None).await?);
+    photo_load.validation = validation;
+
     Ok(photo_load)
 }
 
@@ -688,7 +895,6 @@ pub async fn remove_deleted(deleted: &Vec<String>) -> Result<()> {
     Ok(())
 }
 
-pub async fn refresh() -> Result<Vec<String>> {
-    let photo_load = load_photos().await?;
-    Ok(photo_load.removed)
+pub async fn refresh() -> Result<LoadedPhotos> {
+    load_photos().await
 }
@@ -1,30 +1,118 @@
 use std::{
+    cmp::Ordering,
     collections::{HashMap, HashSet},
     fmt::{self, Display, Formatter},
+    fs,
     str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{anyhow, Context, Result};
 use chrono::NaiveDate;
 use diesel::{
-    debug_query, BoolExpressionMethods, ExpressionMethods, QueryDsl, TextExpressionMethods,
+    debug_query, dsl::not, expression::BoxableExpression, sql_types::Bool, sqlite::Sqlite,
+    BoolExpressionMethods, ExpressionMethods, QueryDsl, SelectableHelper, TextExpressionMethods,
 };
 use diesel_async::RunQueryDsl;
 use log::{debug, warn};
-use strum::{Display, EnumString};
+use serde::Serialize;
+use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
+use tauri::async_runtime;
 
 use crate::{
     app::{ensure_db, DATE_FORMAT, DB},
-    models::{Person, Photo},
-    schema::{people, photos},
+    models::{Person, PersonCategory, PersonRelationship, Photo, TagCategory},
+    people::{
+        categories::PEOPLE_CATEGORIES,
+        photographers::get_unresolved_photographers,
+        relationships::{get_relationships, parse_relative_term, relatives},
+        PEOPLE,
+    },
+    photos::VALIDATION_CACHE,
+    places::PLACES,
+    schema::{photo_people, photo_tags, photos},
+    settings::get_tag_canonicalization,
+    tags::{categories::TAG_CATEGORIES, descendants, resolve_tag, TAGS},
 };
 
+const MAX_SUGGESTIONS: usize = 20;
+
+/// Search term prefixes offered as completions, along with a short description of each.
+const TERM_PREFIXES: &[(&str, &str)] = &[
+    ("at:", "At the specified location"),
+    ("only:", "Only the specified person (and no one else)"),
+    ("by:", "Taken by the specified person"),
+    ("has:", "Has a value for the specified field"),
+    ("name:", "File path contains the specified text"),
+    ("rating=", "Rating equal to the given value"),
+    ("rating>=", "Rating at least the given value"),
+    ("rating<=", "Rating at most the given value"),
+    ("rating>", "Rating greater than the given value"),
+    ("rating<", "Rating less than the given value"),
+    ("of:", "Includes the specified person"),
+    (
+        "familyof:",
+        "Includes someone in the specified person's family",
+    ),
+    ("with:", "Includes all of the specified people"),
+    ("withany:", "Includes any of the specified people"),
+    (
+        "exactly:",
+        "Includes exactly the specified people and no one else",
+    ),
+    ("people=", "Number of people equal to the given value"),
+    ("people>=", "Number of people at least the given value"),
+    ("people<=", "Number of people at most the given value"),
+    ("people>", "Number of people greater than the given value"),
+    ("people<", "Number of people less than the given value"),
+    (
+        "category:",
+        "Includes someone in the specified people category",
+    ),
+    ("tagcat:", "Has a tag in the specified tag category"),
+    ("date:", "Taken on the given date"),
+    ("date>=", "Taken on or after the given date"),
+    ("date<=", "Taken on or before the given date"),
+    ("date>", "Taken after the given date"),
+    ("date<", "Taken before the given date"),
+    ("age:", "The of: person was the given age"),
+    ("age>=", "The of: person was at least the given age"),
+    ("age<=", "The of: person was at most the given age"),
+    ("age>", "The of: person was older than the given age"),
+    ("age<", "The of: person was younger than the given age"),
+    ("is:", "Only RAW, video, or duplicate files"),
+    (
+        "show:",
+        "Include photos that are normally hidden from results",
+    ),
+    ("expand:groups", "Show every matching photo in each group"),
+    ("sort:", "Set the sort order"),
+];
+
+const SORT_KEYS: &[&str] = &[
+    "date",
+    "name",
+    "rating",
+    "filedate",
+    "place",
+    "photographer",
+    "people",
+    "tags",
+    "size",
+];
+
 #[derive(PartialEq)]
 pub enum Sort {
     Date(bool),
     Name(bool),
     Rating(bool),
     FileDate(bool),
+    Place(bool),
+    Photographer(bool),
+    PeopleCount(bool),
+    TagCount(bool),
+    FileSize(bool),
+    Random(u64),
 }
 
 impl FromStr for Sort {
@@ -39,15 +127,34 @@ impl FromStr for Sort {
                 return Err(anyhow!("Invalid sorting: Expected format KEY_DIR"));
             }
             let key = key.unwrap().to_uppercase();
-            (key, dir.unwrap().to_uppercase() == "DESC")
+            (key, Some(dir.unwrap()))
         } else {
-            (s.to_uppercase(), false)
+            (s.to_uppercase(), None)
         };
+        let desc = sorting
+            .1
+            .map(|dir| dir.to_uppercase() == "DESC")
+            .unwrap_or(false);
         match sorting.0.as_str() {
-            "DATE" => Ok(Sort::Date(sorting.1)),
-            "NAME" => Ok(Sort::Name(sorting.1)),
-            "RATING" => Ok(Sort::Rating(sorting.1)),
-            "FILEDATE" => Ok(Sort::FileDate(sorting.1)),
+            "DATE" => Ok(Sort::Date(desc)),
+            "NAME" => Ok(Sort::Name(desc)),
+            "RATING" => Ok(Sort::Rating(desc)),
+            "FILEDATE" => Ok(Sort::FileDate(desc)),
+            "PLACE" => Ok(Sort::Place(desc)),
+            "PHOTOGRAPHER" => Ok(Sort::Photographer(desc)),
+            "PEOPLE" => Ok(Sort::PeopleCount(desc)),
+            "TAGS" => Ok(Sort::TagCount(desc)),
+            "SIZE" => Ok(Sort::FileSize(desc)),
+            // The direction of a random sort is its seed, so the same shuffle can be requested again
+            "RANDOM" => Ok(Sort::Random(match sorting.1 {
+                Some(seed) => seed
+                    .parse::<u64>()
+                    .with_context(|| format!("Invalid random seed: {seed}"))?,
+                None => SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default(),
+            })),
             _ => Err(anyhow!("Unknown sort key: {}", sorting.0)),
         }
     }
@@ -60,11 +167,238 @@ impl Display for Sort {
             Sort::Name(dir) => write!(f, "name{}", if *dir { " descending" } else { "" }),
             Sort::Rating(dir) => write!(f, "rating{}", if *dir { " descending" } else { "" }),
             Sort::FileDate(dir) => write!(f, "file_date{}", if *dir { " descending" } else { "" }),
+            Sort::Place(dir) => write!(f, "place{}", if *dir { " descending" } else { "" }),
+            Sort::Photographer(dir) => {
+                write!(f, "photographer{}", if *dir { " descending" } else { "" })
+            }
+            Sort::PeopleCount(dir) => {
+                write!(f, "people_count{}", if *dir { " descending" } else { "" })
+            }
+            Sort::TagCount(dir) => write!(f, "tag_count{}", if *dir { " descending" } else { "" }),
+            Sort::FileSize(dir) => write!(f, "file_size{}", if *dir { " descending" } else { "" }),
+            Sort::Random(seed) => write!(f, "random (seed {seed})"),
         }
     }
 }
 
-#[derive(Display, EnumString, PartialEq)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SortValue {
+    Number(i64),
+    Text(String),
+    Date(NaiveDate),
+}
+
+/// Place and person names needed to sort by place or photographer, and file sizes to sort by size.
+#[derive(Default)]
+struct SortNames {
+    places: HashMap<String, String>,
+    people: HashMap<String, String>,
+    file_sizes: HashMap<String, u64>,
+}
+
+/// FNV-1a, used for random sorting so that a seed keeps giving the same order. The standard
+/// library's default hasher can change between Rust releases.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl Sort {
+    fn is_descending(&self) -> bool {
+        match self {
+            Sort::Date(dir)
+            | Sort::Name(dir)
+            | Sort::Rating(dir)
+            | Sort::FileDate(dir)
+            | Sort::Place(dir)
+            | Sort::Photographer(dir)
+            | Sort::PeopleCount(dir)
+            | Sort::TagCount(dir)
+            | Sort::FileSize(dir) => *dir,
+            Sort::Random(_) => false,
+        }
+    }
+
+    fn value(&self, photo: &Photo, names: &SortNames) -> Option<SortValue> {
+        match self {
+            Sort::Date(_) => photo.date().map(SortValue::Date),
+            Sort::Name(_) => Some(SortValue::Text(photo.name.clone())),
+            Sort::Rating(_) => photo.rating.map(|r| SortValue::Number(r.into())),
+            Sort::FileDate(_) => photo.metadata_date().map(SortValue::Date),
+            Sort::Place(_) => photo.location.as_ref().map(|location| {
+                SortValue::Text(
+                    names
+                        .places
+                        .get(location)
+                        .unwrap_or(location)
+                        .to_lowercase(),
+                )
+            }),
+            Sort::Photographer(_) => photo
+                .photographer
+                .as_ref()
+                .or(photo.photographer_name.as_ref())
+                .map(|photographer| {
+                    SortValue::Text(
+                        names
+                            .people
+                            .get(photographer)
+                            .unwrap_or(photographer)
+                            .to_lowercase(),
+                    )
+                }),
+            Sort::PeopleCount(_) => Some(SortValue::Number(photo.people().len() as i64)),
+            Sort::TagCount(_) => Some(SortValue::Number(photo.tags().len() as i64)),
+            Sort::FileSize(_) => names
+                .file_sizes
+                .get(&photo.name)
+                .map(|size| SortValue::Number(*size as i64)),
+            Sort::Random(seed) => {
+                let hash = fnv1a(0xcbf29ce484222325, &seed.to_le_bytes());
+                Some(SortValue::Number(fnv1a(hash, photo.name.as_bytes()) as i64))
+            }
+        }
+    }
+}
+
+/// A list of sort keys, where each key breaks ties left by the keys before it.
+#[derive(PartialEq)]
+pub struct SortOrder {
+    keys: Vec<Sort>,
+    undated_first: bool,
+}
+
+impl FromStr for SortOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::prelude::v1::Result<Self, Self::Err> {
+        let mut keys = vec![];
+        let mut undated_first = false;
+        for key in s.split(",").map(str::trim).filter(|key| !key.is_empty()) {
+            match key.to_uppercase().as_str() {
+                "UNDATED_FIRST" => undated_first = true,
+                "UNDATED_LAST" => undated_first = false,
+                _ => keys.push(Sort::from_str(key)?),
+            }
+        }
+        if keys.is_empty() {
+            return Err(anyhow!("Invalid sorting: No sort keys given"));
+        }
+        Ok(SortOrder {
+            keys,
+            undated_first,
+        })
+    }
+}
+
+impl Display for SortOrder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            self.keys
+                .iter()
+                .map(Sort::to_string)
+                .collect::<Vec<String>>()
+                .join(", "),
+            if self.undated_first {
+                ", undated first"
+            } else {
+                ""
+            }
+        )
+    }
+}
+
+impl SortOrder {
+    async fn names(&self) -> SortNames {
+        let mut names = SortNames::default();
+        if self.keys.iter().any(|key| matches!(key, Sort::Place(_))) {
+            names.places = PLACES
+                .lock()
+                .await
+                .values()
+                .map(|place| (place.id.clone(), place.name.clone()))
+                .collect();
+        }
+        if self
+            .keys
+            .iter()
+            .any(|key| matches!(key, Sort::Photographer(_)))
+        {
+            names.people = PEOPLE
+                .lock()
+                .await
+                .values()
+                .map(|person| (person.id.clone(), person.name.clone()))
+                .collect();
+        }
+        names
+    }
+
+    /// Reads the sizes of the photos' files when sorting by size. This touches the disk for every
+    /// photo, so it runs off the async runtime.
+    async fn file_sizes(&self, photos: &[Photo]) -> Result<HashMap<String, u64>> {
+        if !self.keys.iter().any(|key| matches!(key, Sort::FileSize(_))) {
+            return Ok(HashMap::new());
+        }
+        let names = photos
+            .iter()
+            .map(|photo| photo.name.clone())
+            .collect::<Vec<String>>();
+        Ok(async_runtime::spawn_blocking(move || {
+            names
+                .into_iter()
+                .filter_map(|name| {
+                    let size = fs::metadata(&name).ok()?.len();
+                    Some((name, size))
+                })
+                .collect()
+        })
+        .await?)
+    }
+
+    /// Sorts the photos by each key in turn, falling back to the photo name so ties are stable.
+    /// Photos without a value for a key sort last in either direction, except undated photos which
+    /// sort first when `undated_first` is set.
+    fn sort(&self, photos: Vec<Photo>, names: &SortNames) -> Vec<Photo> {
+        let mut keyed = photos
+            .into_iter()
+            .map(|photo| {
+                (
+                    self.keys
+                        .iter()
+                        .map(|key| key.value(&photo, names))
+                        .collect::<Vec<Option<SortValue>>>(),
+                    photo,
+                )
+            })
+            .collect::<Vec<(Vec<Option<SortValue>>, Photo)>>();
+        keyed.sort_by(|a, b| {
+            for ((key, a_value), b_value) in self.keys.iter().zip(&a.0).zip(&b.0) {
+                let missing_first =
+                    self.undated_first && matches!(key, Sort::Date(_) | Sort::FileDate(_));
+                let ordering = match (a_value, b_value) {
+                    (None, None) => Ordering::Equal,
+                    (None, Some(_)) if missing_first => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (Some(_), None) if missing_first => Ordering::Greater,
+                    (Some(_), None) => Ordering::Less,
+                    (Some(a_value), Some(b_value)) if key.is_descending() => b_value.cmp(a_value),
+                    (Some(a_value), Some(b_value)) => a_value.cmp(b_value),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            a.1.name.cmp(&b.1.name)
+        });
+        keyed.into_iter().map(|(_, photo)| photo).collect()
+    }
+}
+
+#[derive(Display, EnumIter, EnumString, PartialEq)]
 enum HasTerm {
     Rating,
     Photographer,
@@ -74,14 +408,24 @@ enum HasTerm {
     Tags,
 }
 
-#[derive(Display, EnumString, PartialEq)]
+#[derive(Display, EnumIter, EnumString, PartialEq)]
 enum IsTerm {
     Raw,
     Video,
+    Duplicate,
+    Invalid,
+}
+
+#[derive(Display, EnumIter, EnumString, PartialEq)]
+enum ShowTerm {
+    All,
+    Duplicates,
+    Raws,
+    Groups,
 }
 
 #[derive(Display, EnumString, PartialEq)]
-enum CompOp {
+pub enum CompOp {
     Eq,
     Ge,
     Le,
@@ -89,6 +433,18 @@ enum CompOp {
     Lt,
 }
 
+impl CompOp {
+    pub fn compare<T: PartialOrd>(&self, a: &T, b: &T) -> bool {
+        match self {
+            CompOp::Eq => a == b,
+            CompOp::Ge => a >= b,
+            CompOp::Le => a <= b,
+            CompOp::Gt => a > b,
+            CompOp::Lt => a < b,
+        }
+    }
+}
+
 #[derive(PartialEq)]
 enum SearchTerm {
     At(String),
@@ -98,10 +454,19 @@ enum SearchTerm {
     Name(String),
     Rating(CompOp, i32),
     Of(String),
+    With(Vec<String>),
+    WithAny(Vec<String>),
+    Exactly(Vec<String>),
+    PeopleCount(CompOp, usize),
+    Category(String),
     Date(CompOp, NaiveDate),
+    /// The age of the people searched for with of:... when the photo was taken
+    Age(CompOp, u32),
     Is(IsTerm),
     Tag(String),
-    SortBy(Sort),
+    TagCategory(String),
+    SortBy(SortOrder),
+    Show(ShowTerm),
 }
 
 impl Display for SearchTerm {
@@ -110,18 +475,96 @@ impl Display for SearchTerm {
             SearchTerm::At(loc) => write!(f, "AT({loc})"),
             SearchTerm::By(p) => write!(f, "BY({p})"),
             SearchTerm::Date(op, date) => write!(f, "DATE({op},{date})"),
+            SearchTerm::Age(op, age) => write!(f, "AGE({op},{age})"),
             SearchTerm::Has(has) => write!(f, "HAS({has})"),
             SearchTerm::Is(is) => write!(f, "IS({is})"),
             SearchTerm::Name(name) => write!(f, "NAME({name})"),
             SearchTerm::Of(p) => write!(f, "OF({p})"),
+            SearchTerm::With(p) => write!(f, "WITH({})", p.join(",")),
+            SearchTerm::WithAny(p) => write!(f, "WITHANY({})", p.join(",")),
+            SearchTerm::Exactly(p) => write!(f, "EXACTLY({})", p.join(",")),
+            SearchTerm::PeopleCount(op, n) => write!(f, "PEOPLE({op},{n})"),
+            SearchTerm::Category(c) => write!(f, "CATEGORY({c})"),
             SearchTerm::Only(p) => write!(f, "ONLY({p})"),
             SearchTerm::Rating(op, r) => write!(f, "RATING({op},{r})"),
             SearchTerm::SortBy(sort) => write!(f, "SORTBY({sort})"),
             SearchTerm::Tag(tag) => write!(f, "TAG({tag})"),
+            SearchTerm::TagCategory(c) => write!(f, "TAGCAT({c})"),
+            SearchTerm::Show(show) => write!(f, "SHOW({show})"),
         }
     }
 }
 
+/// An invalid term in a search query.
+#[derive(Serialize)]
+pub struct QueryError {
+    pub index: usize,
+    pub term: String,
+    pub message: String,
+}
+
+/// A possible completion for a partially typed search term.
+#[derive(Serialize)]
+pub struct QuerySuggestion {
+    pub value: String,
+    pub description: String,
+}
+
+pub(crate) fn parse_rating(value: &str) -> Result<i32> {
+    value
+        .parse::<i32>()
+        .with_context(|| format!("Invalid rating: {value}"))
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(value, DATE_FORMAT)
+        .with_context(|| format!("Invalid date (expected YYYY-MM-DD): {value}"))
+}
+
+fn parse_age(value: &str) -> Result<u32> {
+    value
+        .parse::<u32>()
+        .with_context(|| format!("Invalid age: {value}"))
+}
+
+fn parse_people_count(value: &str) -> Result<usize> {
+    value
+        .parse::<usize>()
+        .with_context(|| format!("Invalid number of people: {value}"))
+}
+
+fn parse_people_list(value: &str) -> Result<Vec<String>> {
+    let people = value
+        .split(",")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<String>>();
+    if people.is_empty() {
+        return Err(anyhow!("Expected a comma-separated list of people"));
+    }
+    Ok(people)
+}
+
+/// Checks if the person ID refers to the person given in a search term, which may be either an ID
+/// or a name.
+fn is_person(id: &String, person: &str, people: &HashMap<String, Person>) -> bool {
+    id == person
+        || people
+            .get(id)
+            .map(|p| p.name.to_uppercase() == person.to_uppercase())
+            .unwrap_or(false)
+}
+
+/// Checks if a person search term refers to anyone, following relationships like children-of:...
+/// back to the person they start from.
+fn is_known_person(person: &str, people: &HashMap<String, Person>) -> bool {
+    match parse_relative_term(person) {
+        Some((_, base)) => is_known_person(base, people),
+        None => people.keys().any(|id| is_person(id, person, people)),
+    }
+}
+
 fn parse_term(term: &str) -> Result<(SearchTerm, bool)> {
     let negated = term.starts_with('-');
     let t = if negated { &term[1..] } else { term };
@@ -146,104 +589,526 @@ fn parse_term(term: &str) -> Result<(SearchTerm, bool)> {
         Ok((SearchTerm::Name(t[5..].to_ascii_lowercase()), negated))
     } else if up.starts_with("RATING<=") {
         Ok((
-            SearchTerm::Rating(CompOp::Le, t[8..].parse::<i32>()?),
+            SearchTerm::Rating(CompOp::Le, parse_rating(&t[8..])?),
             negated,
         ))
     } else if up.starts_with("RATING>=") {
         Ok((
-            SearchTerm::Rating(CompOp::Ge, t[8..].parse::<i32>()?),
+            SearchTerm::Rating(CompOp::Ge, parse_rating(&t[8..])?),
             negated,
         ))
     } else if up.starts_with("RATING<") {
         Ok((
-            SearchTerm::Rating(CompOp::Lt, t[7..].parse::<i32>()?),
+            SearchTerm::Rating(CompOp::Lt, parse_rating(&t[7..])?),
             negated,
         ))
     } else if up.starts_with("RATING>") {
         Ok((
-            SearchTerm::Rating(CompOp::Gt, t[7..].parse::<i32>()?),
+            SearchTerm::Rating(CompOp::Gt, parse_rating(&t[7..])?),
             negated,
         ))
     } else if up.starts_with("RATING=") {
         Ok((
-            SearchTerm::Rating(CompOp::Eq, t[7..].parse::<i32>()?),
+            SearchTerm::Rating(CompOp::Eq, parse_rating(&t[7..])?),
             negated,
         ))
     } else if up.starts_with("SORT:") || up.starts_with("SORT=") {
-        Ok((SearchTerm::SortBy(Sort::from_str(&t[5..])?), negated))
+        Ok((SearchTerm::SortBy(SortOrder::from_str(&t[5..])?), negated))
     } else if up.starts_with("ORDER:") || up.starts_with("ORDER=") {
-        Ok((SearchTerm::SortBy(Sort::from_str(&t[6..])?), negated))
+        Ok((SearchTerm::SortBy(SortOrder::from_str(&t[6..])?), negated))
     } else if up.starts_with("OF:") {
         let val = t[3..].to_string();
         Ok((SearchTerm::Of(val), negated))
-    } else if up.starts_with("DATE>=") {
+    } else if up.starts_with("FAMILYOF:") {
+        Ok((SearchTerm::Of(format!("family-of:{}", &t[9..])), negated))
+    } else if up.starts_with("WITH:") {
+        Ok((SearchTerm::With(parse_people_list(&t[5..])?), negated))
+    } else if up.starts_with("WITHANY:") {
+        Ok((SearchTerm::WithAny(parse_people_list(&t[8..])?), negated))
+    } else if up.starts_with("EXACTLY:") {
+        Ok((SearchTerm::Exactly(parse_people_list(&t[8..])?), negated))
+    } else if up.starts_with("PEOPLE>=") {
         Ok((
-            SearchTerm::Date(CompOp::Ge, NaiveDate::parse_from_str(&t[6..], DATE_FORMAT)?),
+            SearchTerm::PeopleCount(CompOp::Ge, parse_people_count(&t[8..])?),
             negated,
         ))
-    } else if up.starts_with("DATE<=") {
+    } else if up.starts_with("PEOPLE<=") {
         Ok((
-            SearchTerm::Date(CompOp::Le, NaiveDate::parse_from_str(&t[6..], DATE_FORMAT)?),
+            SearchTerm::PeopleCount(CompOp::Le, parse_people_count(&t[8..])?),
             negated,
         ))
-    } else if up.starts_with("DATE>") {
+    } else if up.starts_with("PEOPLE>") {
         Ok((
-            SearchTerm::Date(CompOp::Gt, NaiveDate::parse_from_str(&t[5..], DATE_FORMAT)?),
+            SearchTerm::PeopleCount(CompOp::Gt, parse_people_count(&t[7..])?),
             negated,
         ))
-    } else if up.starts_with("DATE<") {
+    } else if up.starts_with("PEOPLE<") {
         Ok((
-            SearchTerm::Date(CompOp::Lt, NaiveDate::parse_from_str(&t[5..], DATE_FORMAT)?),
+            SearchTerm::PeopleCount(CompOp::Lt, parse_people_count(&t[7..])?),
             negated,
         ))
-    } else if up.starts_with("DATE:") {
+    } else if up.starts_with("PEOPLE=") {
         Ok((
-            SearchTerm::Date(CompOp::Eq, NaiveDate::parse_from_str(&t[5..], DATE_FORMAT)?),
+            SearchTerm::PeopleCount(CompOp::Eq, parse_people_count(&t[7..])?),
             negated,
         ))
+    } else if up.starts_with("CATEGORY:") {
+        Ok((SearchTerm::Category(t[9..].to_string()), negated))
+    } else if up.starts_with("TAGCAT:") {
+        Ok((SearchTerm::TagCategory(t[7..].to_string()), negated))
+    } else if up.starts_with("DATE>=") {
+        Ok((SearchTerm::Date(CompOp::Ge, parse_date(&t[6..])?), negated))
+    } else if up.starts_with("DATE<=") {
+        Ok((SearchTerm::Date(CompOp::Le, parse_date(&t[6..])?), negated))
+    } else if up.starts_with("DATE>") {
+        Ok((SearchTerm::Date(CompOp::Gt, parse_date(&t[5..])?), negated))
+    } else if up.starts_with("DATE<") {
+        Ok((SearchTerm::Date(CompOp::Lt, parse_date(&t[5..])?), negated))
+    } else if up.starts_with("DATE:") {
+        Ok((SearchTerm::Date(CompOp::Eq, parse_date(&t[5..])?), negated))
+    } else if up.starts_with("AGE>=") {
+        Ok((SearchTerm::Age(CompOp::Ge, parse_age(&t[5..])?), negated))
+    } else if up.starts_with("AGE<=") {
+        Ok((SearchTerm::Age(CompOp::Le, parse_age(&t[5..])?), negated))
+    } else if up.starts_with("AGE>") {
+        Ok((SearchTerm::Age(CompOp::Gt, parse_age(&t[4..])?), negated))
+    } else if up.starts_with("AGE<") {
+        Ok((SearchTerm::Age(CompOp::Lt, parse_age(&t[4..])?), negated))
+    } else if up.starts_with("AGE:") {
+        Ok((SearchTerm::Age(CompOp::Eq, parse_age(&t[4..])?), negated))
     } else if let Some(qualifier) = up.strip_prefix("IS:") {
         match qualifier {
             "VIDEO" => Ok((SearchTerm::Is(IsTerm::Video), negated)),
             "RAW" => Ok((SearchTerm::Is(IsTerm::Raw), negated)),
+            "DUPLICATE" => Ok((SearchTerm::Is(IsTerm::Duplicate), negated)),
+            "INVALID" => Ok((SearchTerm::Is(IsTerm::Invalid), negated)),
             s => Err(anyhow!("Unknown IS: qualifier: {s}")),
         }
+    } else if let Some(qualifier) = up.strip_prefix("SHOW:") {
+        match qualifier {
+            "ALL" => Ok((SearchTerm::Show(ShowTerm::All), negated)),
+            "DUPLICATES" => Ok((SearchTerm::Show(ShowTerm::Duplicates), negated)),
+            "RAWS" => Ok((SearchTerm::Show(ShowTerm::Raws), negated)),
+            "GROUPS" => Ok((SearchTerm::Show(ShowTerm::Groups), negated)),
+            s => Err(anyhow!("Unknown SHOW: qualifier: {s}")),
+        }
+    } else if let Some(qualifier) = up.strip_prefix("EXPAND:") {
+        match qualifier {
+            "GROUPS" => Ok((SearchTerm::Show(ShowTerm::Groups), negated)),
+            s => Err(anyhow!("Unknown EXPAND: qualifier: {s}")),
+        }
     } else {
         Ok((SearchTerm::Tag(t.to_string()), negated))
     }
 }
 
-/// Performs a search of the photos using the given query.
-pub async fn search_photos(query: &Vec<String>, sort: Sort) -> Result<Vec<Photo>> {
-    debug!(
-        "Searching photos with query \"{0}\", sorted by {1}",
-        query.join(","),
-        sort
-    );
-    let mut unmet_terms = vec![];
-    let mut sort = sort;
-    let mut terms = vec![];
-    for term in query {
-        let parsed = parse_term(term).with_context(|| "Could not parse search term: {term}")?;
-        debug!(
-            "Parsed term: {0}{1}",
-            if parsed.1 { "NOT " } else { "" },
-            parsed.0,
+/// The tag a query term searches for and whether it is negated, if the term is a tag at all.
+pub fn tag_term(term: &str) -> Option<(String, bool)> {
+    match parse_term(term) {
+        Ok((SearchTerm::Tag(tag), negated)) => Some((tag, negated)),
+        _ => None,
+    }
+}
+
+/// Checks each term of a query, returning the terms that could not be parsed or that reference
+/// tags, places, or people that don't exist.
+pub async fn check_query(query: &[String]) -> Result<Vec<QueryError>> {
+    let steps = get_tag_canonicalization().await?;
+    let tag_categories = TAG_CATEGORIES.lock().await;
+    let places = PLACES.lock().await;
+    let tags = TAGS.lock().await;
+    let people = PEOPLE.lock().await;
+    let has_of = query
+        .iter()
+        .any(|term| matches!(parse_term(term), Ok((SearchTerm::Of(_), false))));
+    let mut errors = vec![];
+    for (index, term) in query.iter().enumerate() {
+        let message = match parse_term(term) {
+            Err(e) => Some(format!("{e:#}")),
+            Ok((SearchTerm::At(place), _)) if !places.contains_key(&place) => {
+                Some(format!("Unknown place: {place}"))
+            }
+            Ok((SearchTerm::Only(person), _)) if !people.contains_key(&person) => {
+                Some(format!("Unknown person: {person}"))
+            }
+            Ok((SearchTerm::Of(person), _)) if !is_known_person(&person, &people) => {
+                Some(format!("Unknown person: {person}"))
+            }
+            Ok((
+                SearchTerm::With(list) | SearchTerm::WithAny(list) | SearchTerm::Exactly(list),
+                _,
+            )) if list.iter().any(|person| !is_known_person(person, &people)) => Some(format!(
+                "Unknown person: {}",
+                list.iter()
+                    .filter(|person| !is_known_person(person, &people))
+                    .cloned()
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )),
+            Ok((SearchTerm::Age(_, _), _)) if !has_of => {
+                Some("Age terms need an of: person".to_string())
+            }
+            Ok((SearchTerm::Tag(tag), _))
+                if !tags.contains_key(&resolve_tag(&tag, &tags, &steps)) =>
+            {
+                Some(format!("Unknown tag or search term: {tag}"))
+            }
+            Ok((SearchTerm::TagCategory(category), _))
+                if !tag_categories
+                    .values()
+                    .any(|c| is_tag_category(c, &category)) =>
+            {
+                Some(format!("Unknown tag category: {category}"))
+            }
+            Ok(_) => None,
+        };
+        if let Some(message) = message {
+            errors.push(QueryError {
+                index,
+                term: term.clone(),
+                message,
+            });
+        }
+    }
+    Ok(errors)
+}
+
+/// Returns completions for a partially typed search term.
+pub async fn suggest_query(partial: &str) -> Result<Vec<QuerySuggestion>> {
+    let negated = partial.starts_with('-');
+    let t = if negated { &partial[1..] } else { partial };
+    let up = t.to_uppercase();
+    // Candidates are (completed term, description, matched text)
+    let mut candidates = Vec::<(String, String, String)>::new();
+
+    if let Some(qualifier) = up.strip_prefix("HAS:") {
+        for has in HasTerm::iter() {
+            let value = has.to_string().to_lowercase();
+            candidates.push((
+                format!("has:{value}"),
+                format!("Photos that have a {value} value"),
+                value,
+            ));
+        }
+        candidates.retain(|c| c.2.to_uppercase().starts_with(qualifier));
+    } else if let Some(qualifier) = up.strip_prefix("IS:") {
+        for is in IsTerm::iter() {
+            let value = is.to_string().to_lowercase();
+            candidates.push((format!("is:{value}"), format!("Only {value} files"), value));
+        }
+        candidates.retain(|c| c.2.to_uppercase().starts_with(qualifier));
+    } else if let Some(qualifier) = up.strip_prefix("SHOW:") {
+        for show in ShowTerm::iter() {
+            let value = show.to_string().to_lowercase();
+            candidates.push((
+                format!("show:{value}"),
+                match show {
+                    ShowTerm::All => "Show duplicates, grouped raws, and every photo in each group",
+                    ShowTerm::Duplicates => "Show photos marked as duplicates",
+                    ShowTerm::Raws => "Show raws that are grouped with another photo",
+                    ShowTerm::Groups => "Show every matching photo in each group",
+                }
+                .to_string(),
+                value,
+            ));
+        }
+        candidates.retain(|c| c.2.to_uppercase().starts_with(qualifier));
+    } else if let Some(qualifier) = up
+        .strip_prefix("SORT:")
+        .or_else(|| up.strip_prefix("ORDER:"))
+    {
+        let prefix = &t[..=t.find(':').unwrap_or_default()];
+        for key in SORT_KEYS {
+            for (suffix, description) in [("", "ascending"), ("_desc", "descending")] {
+                let value = format!("{key}{suffix}");
+                candidates.push((
+                    format!("{prefix}{value}"),
+                    format!("Sort by {key} {description}"),
+                    value,
+                ));
+            }
+        }
+        candidates.push((
+            format!("{prefix}random"),
+            "Shuffle in a random order".to_string(),
+            "random".to_string(),
+        ));
+        candidates.retain(|c| c.2.to_uppercase().starts_with(qualifier));
+    } else if let Some(qualifier) = up.strip_prefix("AT:") {
+        for place in PLACES.lock().await.values() {
+            if place.name.to_uppercase().contains(qualifier)
+                || place.id.to_uppercase().starts_with(qualifier)
+            {
+                candidates.push((
+                    format!("at:{}", place.id),
+                    format!("Place: {}", place.name),
+                    place.name.clone(),
+                ));
+            }
+        }
+    } else if let Some(qualifier) = up.strip_prefix("CATEGORY:") {
+        for category in PEOPLE_CATEGORIES.lock().await.values() {
+            if category.name.to_uppercase().contains(qualifier) {
+                candidates.push((
+                    format!("category:{}", category.name),
+                    "People category".to_string(),
+                    category.name.clone(),
+                ));
+            }
+        }
+    } else if let Some(qualifier) = up.strip_prefix("TAGCAT:") {
+        for category in TAG_CATEGORIES.lock().await.values() {
+            if category.name.to_uppercase().contains(qualifier)
+                || category.id.to_uppercase().starts_with(qualifier)
+            {
+                candidates.push((
+                    format!("tagcat:{}", category.id),
+                    format!("Tag category: {}", category.name),
+                    category.name.clone(),
+                ));
+            }
+        }
+    } else if let Some(qualifier) = up
+        .strip_prefix("OF:")
+        .or_else(|| up.strip_prefix("FAMILYOF:"))
+        .or_else(|| up.strip_prefix("ONLY:"))
+        .or_else(|| up.strip_prefix("BY:"))
+        .or_else(|| up.strip_prefix("WITH:"))
+        .or_else(|| up.strip_prefix("WITHANY:"))
+        .or_else(|| up.strip_prefix("EXACTLY:"))
+    {
+        // List terms complete the last person in the list, and relationships like children-of:...
+        // complete the person they start from
+        let qualifier = qualifier.rsplit([',', ':']).next().unwrap_or_default();
+        let prefix = &t[..=t.rfind([',', ':']).unwrap_or_default()];
+        // Unresolved photographer names come from the photos, which have to be locked first
+        let unresolved = if up.starts_with("BY:") {
+            get_unresolved_photographers().await?
+        } else {
+            vec![]
+        };
+        let people = PEOPLE.lock().await;
+        for person in people.values() {
+            if person.name.to_uppercase().contains(qualifier)
+                || person.id.to_uppercase().starts_with(qualifier)
+            {
+                candidates.push((
+                    format!("{prefix}{}", person.id),
+                    format!("Person: {}", person.name),
+                    person.name.clone(),
+                ));
+            }
+        }
+        // Photographers that aren't people in the database can still be searched by name
+        for photographer in unresolved {
+            if photographer.name.to_uppercase().contains(qualifier) {
+                candidates.push((
+                    format!("by:{}", photographer.name),
+                    "Photographer".to_string(),
+                    photographer.name,
+                ));
+            }
+        }
+    } else {
+        for (prefix, description) in TERM_PREFIXES {
+            if prefix.to_uppercase().starts_with(&up) {
+                candidates.push((
+                    prefix.to_string(),
+                    description.to_string(),
+                    prefix.to_string(),
+                ));
+            }
+        }
+        for tag in TAGS.lock().await.keys() {
+            if tag.to_uppercase().contains(&up) {
+                candidates.push((tag.clone(), "Tag".to_string(), tag.clone()));
+            }
+        }
+    }
+
+    // Prefer candidates that start with what has been typed, then sort alphabetically
+    let typed = up.rsplit([':', ',']).next().unwrap_or_default().to_string();
+    candidates.sort_by_cached_key(|c| (!c.2.to_uppercase().starts_with(&typed), c.2.clone()));
+
+    Ok(candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(value, description, _)| QuerySuggestion {
+            value: if negated { format!("-{value}") } else { value },
+            description,
+        })
+        .collect())
+}
+
+/// A parsed search term, and where in the search it was applied.
+#[derive(Serialize)]
+pub struct ExplainedTerm {
+    pub term: String,
+    pub parsed: String,
+    pub negated: bool,
+    /// One of "sql", "memory" (filtered after loading from the database), "sort", or "option"
+    pub stage: String,
+}
+
+/// The number of photos remaining after a stage of the search.
+#[derive(Serialize)]
+pub struct SearchStage {
+    pub stage: String,
+    pub remaining: usize,
+}
+
+/// A photo returned by the SQL query that was filtered out afterwards.
+#[derive(Serialize)]
+pub struct SearchExclusion {
+    pub photo: String,
+    pub reason: String,
+}
+
+/// A description of how a search was performed, for debugging surprising results.
+#[derive(Default, Serialize)]
+pub struct SearchExplanation {
+    pub terms: Vec<ExplainedTerm>,
+    pub sql: String,
+    pub sort: String,
+    pub stages: Vec<SearchStage>,
+    pub exclusions: Vec<SearchExclusion>,
+}
+
+/// Data needed to evaluate search terms, either to resolve names before building the SQL query or
+/// to check terms that can't be expressed in SQL.
+struct TermContext {
+    people: HashMap<String, Person>,
+    categories: Vec<PersonCategory>,
+    /// Each searched person, by id or name, along with the ids that match it
+    person_ids: HashMap<String, Vec<String>>,
+    /// Each searched tag along with its descendants, any of which counts as having the tag
+    tags: HashMap<String, Vec<String>>,
+    /// Each searched tag category along with the tags in it and their descendants
+    tag_categories: HashMap<String, Vec<String>>,
+    /// Photos that currently break the tag rules
+    invalid: HashSet<String>,
+    /// The ids of the people searched for with of:..., whose ages age terms compare
+    age_people: Vec<String>,
+}
+
+/// Which of the rules that normally hide photos from the results are in effect.
+struct SearchOptions {
+    hide_duplicates: bool,
+    hide_grouped_raws: bool,
+    collapse_groups: bool,
+}
+
+/// Photos matching a search, along with the number of matching photos in each collapsed group.
+pub struct SearchResults {
+    pub photos: Vec<Photo>,
+    pub group_sizes: HashMap<String, usize>,
+}
+
+/// Why a photo returned by the SQL query was left out of the results.
+enum Exclusion {
+    GroupedRaw,
+    Group,
+    Term(usize),
+}
+
+type PhotoFilter = Box<dyn BoxableExpression<photos::table, Sqlite, SqlType = Bool>>;
+
+fn is_sql_term(term: &SearchTerm) -> bool {
+    matches!(
+        term,
+        SearchTerm::At(_)
+            | SearchTerm::Only(_)
+            | SearchTerm::By(_)
+            | SearchTerm::Has(_)
+            | SearchTerm::Name(_)
+            | SearchTerm::Rating(_, _)
+            | SearchTerm::Of(_)
+            | SearchTerm::With(_)
+            | SearchTerm::WithAny(_)
+            | SearchTerm::Exactly(_)
+            | SearchTerm::Tag(_)
+            | SearchTerm::TagCategory(_)
+    )
+}
+
+/// Gets the ids of the people that a person search term may refer to. The term itself is always
+/// included, so that people who were removed can still be found by id. Terms like children-of:...
+/// are replaced by the relatives of the person they start from.
+fn person_ids(
+    person: &str,
+    people: &HashMap<String, Person>,
+    relationships: &[PersonRelationship],
+) -> Vec<String> {
+    if let Some((kinds, base)) = parse_relative_term(person) {
+        return relatives(
+            relationships,
+            &person_ids(base, people, relationships),
+            &kinds,
         );
-        terms.push(parsed);
     }
-    let needs_people = terms.iter().any(|term| matches!(term.0, SearchTerm::Of(_)));
+    let mut ids = people
+        .keys()
+        .filter(|id| is_person(id, person, people))
+        .cloned()
+        .collect::<Vec<String>>();
+    if !ids.iter().any(|id| id == person) {
+        ids.push(person.to_owned());
+    }
+    ids
+}
+
+/// Matches photos with any of the given people.
+fn has_person(ids: Vec<String>) -> PhotoFilter {
+    Box::new(
+        photos::name.eq_any(
+            photo_people::table
+                .filter(photo_people::person.eq_any(ids))
+                .select(photo_people::photo),
+        ),
+    )
+}
 
-    // Construct a SQL statement using terms that require no additional processing (is:..., at:..., only:..., by:..., has:...)
-    let mut statement = photos::table
-        .filter(
+/// Matches photos with any of the given tags.
+fn has_tag(tags: Vec<String>) -> PhotoFilter {
+    Box::new(
+        photos::name.eq_any(
+            photo_tags::table
+                .filter(photo_tags::tag.eq_any(tags))
+                .select(photo_tags::photo),
+        ),
+    )
+}
+
+/// Constructs a SQL statement using the terms that can be expressed in SQL (at:..., only:...,
+/// by:..., has:..., name:..., rating..., people and tags). People and tags are resolved with the
+/// context first, so that names, aliases and descendants are matched as well.
+fn sql_statement<'a>(
+    terms: &'a [(SearchTerm, bool)],
+    names: Option<&'a [String]>,
+    hide_duplicates: bool,
+    context: &TermContext,
+) -> photos::BoxedQuery<'a, Sqlite> {
+    let ids = |person: &String| {
+        context
+            .person_ids
+            .get(person)
+            .cloned()
+            .unwrap_or_else(|| vec![person.clone()])
+    };
+    let mut statement = photos::table.into_boxed();
+    if let Some(names) = names {
+        statement = statement.filter(photos::name.eq_any(names));
+    }
+    if hide_duplicates {
+        statement = statement.filter(
             photos::is_duplicate
                 .eq(0)
                 .or(photos::is_duplicate.is_null()),
-        )
-        .into_boxed();
-    for term in terms {
-        let negated = term.1;
-        match term.0 {
+        );
+    }
+    for (term, negated) in terms {
+        let negated = *negated;
+        match term {
             SearchTerm::At(location) => {
                 if negated {
                     statement = statement.filter(photos::location.ne(location));
@@ -252,17 +1117,102 @@ pub async fn search_photos(query: &Vec<String>, sort: Sort) -> Result<Vec<Photo>
                 }
             }
             SearchTerm::Only(person) => {
+                let only = photos::name
+                    .eq_any(
+                        photo_people::table
+                            .filter(photo_people::person.eq(person))
+                            .select(photo_people::photo),
+                    )
+                    .and(
+                        photos::name.ne_all(
+                            photo_people::table
+                                .filter(photo_people::person.ne(person))
+                                .select(photo_people::photo),
+                        ),
+                    );
+                if negated {
+                    statement = statement.filter(not(only));
+                } else {
+                    statement = statement.filter(only);
+                }
+            }
+            SearchTerm::Of(person) => {
+                let of = has_person(ids(person));
+                if negated {
+                    statement = statement.filter(not(of));
+                } else {
+                    statement = statement.filter(of);
+                }
+            }
+            SearchTerm::With(list) | SearchTerm::WithAny(list) | SearchTerm::Exactly(list) => {
+                let mut filter = list
+                    .iter()
+                    .map(|person| has_person(ids(person)))
+                    .reduce(|a, b| -> PhotoFilter {
+                        if matches!(term, SearchTerm::WithAny(_)) {
+                            Box::new(a.or(b))
+                        } else {
+                            Box::new(a.and(b))
+                        }
+                    })
+                    .unwrap_or_else(|| Box::new(diesel::dsl::sql::<Bool>("1")));
+                if let SearchTerm::Exactly(_) = term {
+                    // Everyone present also has to be listed
+                    let listed = list.iter().flat_map(ids).collect::<Vec<String>>();
+                    filter = Box::new(
+                        filter.and(
+                            photos::name.ne_all(
+                                photo_people::table
+                                    .filter(photo_people::person.ne_all(listed))
+                                    .select(photo_people::photo),
+                            ),
+                        ),
+                    );
+                }
+                if negated {
+                    statement = statement.filter(not(filter));
+                } else {
+                    statement = statement.filter(filter);
+                }
+            }
+            SearchTerm::Tag(tag) => {
+                let tags = context
+                    .tags
+                    .get(tag)
+                    .cloned()
+                    .unwrap_or_else(|| vec![tag.clone()]);
+                if negated {
+                    statement = statement.filter(not(has_tag(tags)));
+                } else {
+                    statement = statement.filter(has_tag(tags));
+                }
+            }
+            SearchTerm::TagCategory(category) => {
+                let tags = context
+                    .tag_categories
+                    .get(category)
+                    .cloned()
+                    .unwrap_or_default();
                 if negated {
-                    statement = statement.filter(photos::people.ne(person));
+                    statement = statement.filter(not(has_tag(tags)));
                 } else {
-                    statement = statement.filter(photos::people.eq(person));
+                    statement = statement.filter(has_tag(tags));
                 }
             }
+            // Photos have either a photographer or an unresolved name, never both
             SearchTerm::By(photographer) => {
                 if negated {
-                    statement = statement.filter(photos::photographer.ne(photographer));
+                    statement = statement.filter(
+                        photos::photographer
+                            .ne(photographer)
+                            .or(photos::photographer_name.ne(photographer)),
+                    );
                 } else {
-                    statement = statement.filter(photos::photographer.eq(photographer));
+                    statement = statement.filter(
+                        photos::photographer
+                            .eq(photographer)
+                            .or(photos::photographer_name.eq(photographer)),
+                    );
                 }
             }
             SearchTerm::Has(has) => match has {
@@ -275,9 +1225,17 @@ pub async fn search_photos(query: &Vec<String>, sort: Sort) -> Result<Vec<Photo>
                 }
                 HasTerm::Photographer => {
                     if negated {
-                        statement = statement.filter(photos::photographer.is_null());
+                        statement = statement.filter(
+                            photos::photographer
+                                .is_null()
+                                .and(photos::photographer_name.is_null()),
+                        );
                     } else {
-                        statement = statement.filter(photos::photographer.is_not_null());
+                        statement = statement.filter(
+                            photos::photographer
+                                .is_not_null()
+                                .or(photos::photographer_name.is_not_null()),
+                        );
                     }
                 }
                 HasTerm::Date => {
@@ -295,17 +1253,19 @@ pub async fn search_photos(query: &Vec<String>, sort: Sort) -> Result<Vec<Photo>
                     }
                 }
                 HasTerm::People => {
+                    let tagged = photo_people::table.select(photo_people::photo);
                     if negated {
-                        statement = statement.filter(photos::people.is_null());
+                        statement = statement.filter(photos::name.ne_all(tagged));
                     } else {
-                        statement = statement.filter(photos::people.is_not_null());
+                        statement = statement.filter(photos::name.eq_any(tagged));
                     }
                 }
                 HasTerm::Tags => {
+                    let tagged = photo_tags::table.select(photo_tags::photo);
                     if negated {
-                        statement = statement.filter(photos::tags.is_null());
+                        statement = statement.filter(photos::name.ne_all(tagged));
                     } else {
-                        statement = statement.filter(photos::tags.is_not_null());
+                        statement = statement.filter(photos::name.eq_any(tagged));
                     }
                 }
             },
@@ -316,180 +1276,471 @@ pub async fn search_photos(query: &Vec<String>, sort: Sort) -> Result<Vec<Photo>
                     statement = statement.filter(photos::name.like(format!("%{name}%")));
                 }
             }
-            SearchTerm::Rating(op, rating) => match op {
-                CompOp::Le => {
-                    if negated {
-                        statement = statement.filter(photos::rating.gt(rating));
-                    } else {
-                        statement = statement.filter(photos::rating.le(rating));
+            SearchTerm::Rating(op, rating) => {
+                let rating = *rating;
+                match op {
+                    CompOp::Le => {
+                        if negated {
+                            statement = statement.filter(photos::rating.gt(rating));
+                        } else {
+                            statement = statement.filter(photos::rating.le(rating));
+                        }
                     }
-                }
-                CompOp::Ge => {
-                    if negated {
-                        statement = statement.filter(photos::rating.lt(rating));
-                    } else {
-                        statement = statement.filter(photos::rating.ge(rating));
+                    CompOp::Ge => {
+                        if negated {
+                            statement = statement.filter(photos::rating.lt(rating));
+                        } else {
+                            statement = statement.filter(photos::rating.ge(rating));
+                        }
                     }
-                }
-                CompOp::Lt => {
-                    if negated {
-                        statement = statement.filter(photos::rating.ge(rating));
-                    } else {
-                        statement = statement.filter(photos::rating.lt(rating));
+                    CompOp::Lt => {
+                        if negated {
+                            statement = statement.filter(photos::rating.ge(rating));
+                        } else {
+                            statement = statement.filter(photos::rating.lt(rating));
+                        }
                     }
-                }
-                CompOp::Gt => {
-                    if negated {
-                        statement = statement.filter(photos::rating.le(rating));
-                    } else {
-                        statement = statement.filter(photos::rating.gt(rating));
+                    CompOp::Gt => {
+                        if negated {
+                            statement = statement.filter(photos::rating.le(rating));
+                        } else {
+                            statement = statement.filter(photos::rating.gt(rating));
+                        }
                     }
-                }
-                CompOp::Eq => {
-                    if negated {
-                        statement = statement.filter(photos::rating.ne(rating));
-                    } else {
-                        statement = statement.filter(photos::rating.eq(rating));
+                    CompOp::Eq => {
+                        if negated {
+                            statement = statement.filter(photos::rating.ne(rating));
+                        } else {
+                            statement = statement.filter(photos::rating.eq(rating));
+                        }
                     }
                 }
-            },
-            SearchTerm::SortBy(value) => {
-                sort = value;
             }
             _ => {
-                unmet_terms.push(term);
+                warn!("Term cannot be applied in SQL: {term}");
             }
         }
     }
+    statement
+}
+
+/// Whether a tag category search term refers to a category, by id or by name.
+fn is_tag_category(category: &TagCategory, term: &str) -> bool {
+    category.id.eq_ignore_ascii_case(term) || category.name.eq_ignore_ascii_case(term)
+}
+
+/// Checks a photo against a term that requires additional processing (date:..., category:..., etc.)
+fn meets_term(photo: &Photo, term: &SearchTerm, negated: bool, context: &TermContext) -> bool {
+    let people = &context.people;
+    match term {
+        SearchTerm::PeopleCount(op, count) => op.compare(&photo.people().len(), count) ^ negated,
+        SearchTerm::Category(category) => {
+            let category = category.to_uppercase();
+            photo.people().iter().any(|id| {
+                people
+                    .get(id)
+                    .and_then(|p| context.categories.iter().find(|c| c.id == p.category))
+                    .map(|c| c.id.to_uppercase() == category || c.name.to_uppercase() == category)
+                    .unwrap_or(false)
+            }) ^ negated
+        }
+        SearchTerm::Date(op, date) => {
+            if let Some(pd) = &photo.date() {
+                op.compare(pd, date) ^ negated
+            } else {
+                false
+            }
+        }
+        SearchTerm::Age(op, age) => {
+            let Some(date) = photo.date() else {
+                return false;
+            };
+            let ages = context
+                .age_people
+                .iter()
+                .filter(|id| photo.people.contains(id))
+                .filter_map(|id| people.get(id)?.age_at(&date))
+                .collect::<Vec<u32>>();
+            if ages.is_empty() {
+                return false;
+            }
+            ages.iter().any(|a| op.compare(a, age)) ^ negated
+        }
+        SearchTerm::Is(is) => {
+            (match is {
+                IsTerm::Video => photo.is_video(),
+                IsTerm::Raw => photo.is_raw(),
+                IsTerm::Duplicate => photo.is_duplicate.unwrap_or(0) == 1,
+                IsTerm::Invalid => context.invalid.contains(&photo.name),
+            }) ^ negated
+        }
+        _ => {
+            warn!("Unexpected term: {term}");
+            true
+        }
+    }
+}
+
+/// Performs a search of the photos using the given query.
+pub async fn search_photos(query: &Vec<String>, sort: SortOrder) -> Result<SearchResults> {
+    run_search(query, sort, None, None).await
+}
+
+/// Performs a search of only the named photos, leaving the rest of the library out of the query.
+pub async fn search_photos_in(
+    query: &Vec<String>,
+    sort: SortOrder,
+    names: &[String],
+) -> Result<SearchResults> {
+    run_search(query, sort, Some(names), None).await
+}
+
+/// Performs a search of the photos, describing each step of the search along the way.
+pub async fn explain_search(query: &Vec<String>, sort: SortOrder) -> Result<SearchExplanation> {
+    let mut explanation = SearchExplanation::default();
+    run_search(query, sort, None, Some(&mut explanation)).await?;
+    Ok(explanation)
+}
 
+async fn run_search(
+    query: &Vec<String>,
+    sort: SortOrder,
+    names: Option<&[String]>,
+    mut explanation: Option<&mut SearchExplanation>,
+) -> Result<SearchResults> {
     debug!(
-        "Constructed SQL query for search: {}",
-        debug_query(&statement)
+        "Searching photos with query \"{0}\", sorted by {1}",
+        query.join(","),
+        sort
     );
+    let mut sql_terms = vec![];
+    let mut unmet_terms = vec![];
+    let mut sort = sort;
+    let mut options = SearchOptions {
+        hide_duplicates: true,
+        hide_grouped_raws: true,
+        collapse_groups: true,
+    };
+    for term in query {
+        let parsed =
+            parse_term(term).with_context(|| format!("Could not parse search term: {term}"))?;
+        debug!(
+            "Parsed term: {0}{1}",
+            if parsed.1 { "NOT " } else { "" },
+            parsed.0,
+        );
+        if let Some(explanation) = explanation.as_mut() {
+            explanation.terms.push(ExplainedTerm {
+                term: term.clone(),
+                parsed: parsed.0.to_string(),
+                negated: parsed.1,
+                stage: if matches!(parsed.0, SearchTerm::SortBy(_)) {
+                    "sort"
+                } else if matches!(parsed.0, SearchTerm::Show(_)) {
+                    "option"
+                } else if is_sql_term(&parsed.0) {
+                    "sql"
+                } else {
+                    "memory"
+                }
+                .to_string(),
+            });
+        }
+        if let SearchTerm::SortBy(value) = parsed.0 {
+            sort = value;
+        } else if let SearchTerm::Show(show) = parsed.0 {
+            match show {
+                ShowTerm::All => {
+                    options.hide_duplicates = false;
+                    options.hide_grouped_raws = false;
+                    options.collapse_groups = false;
+                }
+                ShowTerm::Duplicates => options.hide_duplicates = false,
+                ShowTerm::Raws => options.hide_grouped_raws = false,
+                ShowTerm::Groups => options.collapse_groups = false,
+            }
+        } else if is_sql_term(&parsed.0) {
+            sql_terms.push(parsed);
+        } else {
+            // Searching for duplicates wouldn't find anything if they were filtered out
+            if parsed == (SearchTerm::Is(IsTerm::Duplicate), false) {
+                options.hide_duplicates = false;
+            }
+            unmet_terms.push(parsed);
+        }
+    }
+    let needs_people = sql_terms.iter().chain(&unmet_terms).any(|term| {
+        matches!(
+            term.0,
+            SearchTerm::Of(_)
+                | SearchTerm::With(_)
+                | SearchTerm::WithAny(_)
+                | SearchTerm::Exactly(_)
+                | SearchTerm::Category(_)
+                | SearchTerm::Age(_, _)
+        )
+    });
+    let needs_categories = unmet_terms
+        .iter()
+        .any(|term| matches!(term.0, SearchTerm::Category(_)));
+
+    // Gather names for sorting before locking the database, since the place and people caches are
+    // locked before the database elsewhere
+    let mut sort_names = sort.names().await;
+    let categories = if needs_categories {
+        PEOPLE_CATEGORIES
+            .lock()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<PersonCategory>>()
+    } else {
+        vec![]
+    };
+    let people = if needs_people {
+        PEOPLE.lock().await.clone()
+    } else {
+        HashMap::new()
+    };
+    let searched_people = sql_terms
+        .iter()
+        .flat_map(|(term, _)| match term {
+            SearchTerm::Of(person) => std::slice::from_ref(person),
+            SearchTerm::With(list) | SearchTerm::WithAny(list) | SearchTerm::Exactly(list) => {
+                list.as_slice()
+            }
+            _ => &[],
+        })
+        .collect::<Vec<&String>>();
+    let relationships = if searched_people
+        .iter()
+        .any(|person| parse_relative_term(person).is_some())
+    {
+        get_relationships(None).await?
+    } else {
+        vec![]
+    };
+    let mut person_ids = HashMap::<String, Vec<String>>::new();
+    for person in searched_people {
+        person_ids.insert(
+            person.clone(),
+            self::person_ids(person, &people, &relationships),
+        );
+    }
+    let age_people = sql_terms
+        .iter()
+        .filter_map(|term| match term {
+            (SearchTerm::Of(person), false) => person_ids.get(person),
+            _ => None,
+        })
+        .flatten()
+        .cloned()
+        .collect::<Vec<String>>();
+    let invalid = if unmet_terms
+        .iter()
+        .any(|term| term.0 == SearchTerm::Is(IsTerm::Invalid))
+    {
+        VALIDATION_CACHE
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, validation)| !validation.is_valid)
+            .map(|(photo, _)| photo.clone())
+            .collect::<HashSet<String>>()
+    } else {
+        HashSet::new()
+    };
+    let mut term_tags = HashMap::<String, Vec<String>>::new();
+    let mut term_tag_categories = HashMap::<String, Vec<String>>::new();
+    if sql_terms
+        .iter()
+        .any(|term| matches!(term.0, SearchTerm::Tag(_) | SearchTerm::TagCategory(_)))
+    {
+        let steps = get_tag_canonicalization().await?;
+        let tag_categories = TAG_CATEGORIES.lock().await.clone();
+        let tags = TAGS.lock().await;
+        for (term, _) in &sql_terms {
+            if let SearchTerm::Tag(tag) = term {
+                let resolved = resolve_tag(tag, &tags, &steps);
+                let mut matching = descendants(&resolved, &tags);
+                matching.push(resolved);
+                term_tags.insert(tag.clone(), matching);
+            } else if let SearchTerm::TagCategory(category) = term {
+                let mut matching = Vec::<String>::new();
+                for tag in tags.values().filter(|tag| {
+                    tag.category
+                        .as_ref()
+                        .and_then(|c| tag_categories.get(c))
+                        .is_some_and(|c| is_tag_category(c, category))
+                }) {
+                    for t in descendants(&tag.name, &tags)
+                        .into_iter()
+                        .chain([tag.name.clone()])
+                    {
+                        if !matching.contains(&t) {
+                            matching.push(t);
+                        }
+                    }
+                }
+                term_tag_categories.insert(category.clone(), matching);
+            }
+        }
+    }
+
+    let context = TermContext {
+        people,
+        categories,
+        person_ids,
+        tags: term_tags,
+        tag_categories: term_tag_categories,
+        invalid,
+        age_people,
+    };
+    let statement = sql_statement(&sql_terms, names, options.hide_duplicates, &context);
+    let sql = debug_query(&statement).to_string();
+    debug!("Constructed SQL query for search: {sql}");
 
     ensure_db().await?;
     let mut conn = DB.lock().await;
     let conn = conn.as_mut().unwrap();
-    let photo_records = statement.load::<Photo>(conn).await?;
+    let photo_records = statement
+        .select(Photo::as_select())
+        .load::<Photo>(conn)
+        .await?;
     debug!("Query returned {} photos", photo_records.len());
 
-    // Terms that require additional processing and iterating over the photos (date:..., of:..., any tags)
-    let people = if needs_people {
-        Some(
-            people::table
-                .load::<Person>(conn)
-                .await?
-                .into_iter()
-                .map(|p| (p.id.clone(), p))
-                .collect::<HashMap<String, Person>>(),
-        )
+    if let Some(explanation) = explanation.as_mut() {
+        let with_duplicates = sql_statement(&sql_terms, names, false, &context)
+            .count()
+            .get_result::<i64>(conn)
+            .await?;
+        explanation.sql = sql;
+        explanation.sort = sort.to_string();
+        explanation.stages.push(SearchStage {
+            stage: "SQL terms".to_string(),
+            remaining: with_duplicates as usize,
+        });
+        explanation.stages.push(SearchStage {
+            stage: if options.hide_duplicates {
+                "Duplicate filter"
+            } else {
+                "Duplicate filter (disabled)"
+            }
+            .to_string(),
+            remaining: photo_records.len(),
+        });
+    }
+
+    let raw_name_map = if options.hide_grouped_raws {
+        photo_records
+            .iter()
+            .filter_map(|p| p.grouped_raw())
+            .collect::<HashSet<String>>()
     } else {
-        None
+        HashSet::new()
     };
 
-    let raw_name_map = photo_records
-        .iter()
-        .filter_map(|p| p.grouped_raw())
-        .collect::<HashSet<String>>();
-
-    let mut encountered_groups = HashSet::<String>::new();
+    // The number of matching photos in each group, where only the first is included in the results
+    let mut group_sizes = HashMap::<String, usize>::new();
 
-    let mut results = photo_records
-        .into_iter()
-        .filter(|photo| {
-            let photo_tags = photo.tags();
-            let photo_people = photo.people();
-            let photo_date = photo.date();
+    let mut results = vec![];
+    let mut exclusions = vec![];
+    for photo in photo_records {
+        let mut exclusion = if photo.is_raw() && raw_name_map.contains(&photo.name) {
+            Some(Exclusion::GroupedRaw)
+        } else {
+            unmet_terms
+                .iter()
+                .position(|(term, negated)| !meets_term(&photo, term, *negated, &context))
+                .map(Exclusion::Term)
+        };
 
-            if photo.is_raw() && raw_name_map.contains(&photo.name) {
-                return false;
-            }
+        // Claim the group only after confirming this photo passes all terms,
+        // so later photos in the same group can still be candidates if this one fails.
+        if exclusion.is_none() && options.collapse_groups {
             if let Some(group) = &photo.photo_group {
-                if encountered_groups.contains(group) {
-                    return false;
+                let size = group_sizes.entry(group.clone()).or_insert(0);
+                *size += 1;
+                if *size > 1 {
+                    exclusion = Some(Exclusion::Group);
                 }
             }
+        }
 
-            let mut meets_terms = true;
-            for term in &unmet_terms {
-                let negated = term.1;
-                meets_terms = meets_terms
-                    && match &term.0 {
-                        SearchTerm::Of(person) => {
-                            let mut found = photo_people.contains(person);
-                            if !found {
-                                let name = person.to_uppercase();
-                                found = photo_people.iter().any(|id| {
-                                    people
-                                        .as_ref()
-                                        .unwrap()
-                                        .get(id)
-                                        .map(|p| p.name.to_uppercase() == name)
-                                        .unwrap_or(false)
-                                });
-                            }
-                            found ^ negated
-                        }
-                        SearchTerm::Date(op, date) => {
-                            if let Some(pd) = &photo_date {
-                                (match op {
-                                    CompOp::Eq => pd == date,
-                                    CompOp::Ge => pd >= date,
-                                    CompOp::Le => pd <= date,
-                                    CompOp::Gt => pd > date,
-                                    CompOp::Lt => pd < date,
-                                }) ^ negated
-                            } else {
-                                false
-                            }
-                        }
-                        SearchTerm::Is(is) => {
-                            (match is {
-                                IsTerm::Video => photo.is_video(),
-                                IsTerm::Raw => photo.is_raw(),
-                            }) ^ negated
-                        }
-                        SearchTerm::Tag(tag) => photo_tags.contains(tag) ^ negated,
-                        _ => {
-                            warn!("Unexpected term: {}", term.0);
-                            true
-                        }
-                    };
+        if let Some(exclusion) = exclusion {
+            if explanation.is_some() {
+                exclusions.push((photo.name, exclusion));
             }
+        } else {
+            results.push(photo);
+        }
+    }
 
-            // Claim the group only after confirming this photo passes all terms,
-            // so later photos in the same group can still be candidates if this one fails.
-            if meets_terms {
-                if let Some(group) = &photo.photo_group {
-                    encountered_groups.insert(group.clone());
-                }
+    if let Some(explanation) = explanation.as_mut() {
+        // Each photo is attributed to the first stage that excluded it, in the order they are checked
+        let mut remaining = results.len() + exclusions.len();
+        let mut stages = vec![(
+            if options.hide_grouped_raws {
+                "Grouped raw suppression"
+            } else {
+                "Grouped raw suppression (disabled)"
             }
-
-            meets_terms
-        })
-        .collect::<Vec<Photo>>();
-
-    if match sort {
-        Sort::Name(dir) => {
-            results.sort_by(|a, b| a.name.cmp(&b.name));
-            dir
+            .to_string(),
+            exclusions
+                .iter()
+                .filter(|e| matches!(e.1, Exclusion::GroupedRaw))
+                .count(),
+        )];
+        for (index, (term, negated)) in unmet_terms.iter().enumerate() {
+            stages.push((
+                format!("{}{term}", if *negated { "NOT " } else { "" }),
+                exclusions
+                    .iter()
+                    .filter(|e| matches!(e.1, Exclusion::Term(i) if i == index))
+                    .count(),
+            ));
         }
-        Sort::Rating(dir) => {
-            results.sort_by_cached_key(|p| p.rating);
-            dir
-        }
-        Sort::Date(dir) => {
-            results.sort_by_cached_key(|p| p.date());
-            dir
-        }
-        Sort::FileDate(dir) => {
-            results.sort_by_cached_key(|p| p.metadata_date());
-            dir
+        stages.push((
+            if options.collapse_groups {
+                "Group collapsing"
+            } else {
+                "Group collapsing (disabled)"
+            }
+            .to_string(),
+            exclusions
+                .iter()
+                .filter(|e| matches!(e.1, Exclusion::Group))
+                .count(),
+        ));
+        for (stage, excluded) in stages {
+            remaining -= excluded;
+            explanation.stages.push(SearchStage { stage, remaining });
         }
-    } {
-        results.reverse();
+        explanation.exclusions = exclusions
+            .into_iter()
+            .map(|(photo, exclusion)| SearchExclusion {
+                photo,
+                reason: match exclusion {
+                    Exclusion::GroupedRaw => "RAW file grouped with another photo".to_string(),
+                    Exclusion::Group => "Another photo in its group was already shown".to_string(),
+                    Exclusion::Term(index) => {
+                        let (term, negated) = &unmet_terms[index];
+                        format!(
+                            "Did not match term {}{term}",
+                            if *negated { "NOT " } else { "" }
+                        )
+                    }
+                },
+            })
+            .collect();
     }
 
+    sort_names.file_sizes = sort.file_sizes(&results).await?;
+    let results = sort.sort(results, &sort_names);
+
     debug!("Search returned {} photos", results.len());
-    Ok(results)
+    Ok(SearchResults {
+        photos: results,
+        group_sizes,
+    })
 }
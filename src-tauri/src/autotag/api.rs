@@ -0,0 +1,124 @@
+use anyhow::Context;
+use log::debug;
+use tauri::{AppHandle, Runtime};
+
+use crate::{
+    app::{vec_to_row, ApiError},
+    autotag::{
+        create_autotag_rule as _create_autotag_rule, delete_autotag_rule as _delete_autotag_rule,
+        get_autotag_rules as _get_autotag_rules, run_autotag_rules as _run_autotag_rules,
+        update_autotag_rule as _update_autotag_rule, AutotagChange,
+    },
+    models::AutotagRule,
+    photos::api::emit_validation,
+};
+
+#[derive(serde::Serialize)]
+pub struct AutotagRuleDto {
+    pub id: String,
+    pub name: String,
+    pub query: Vec<String>,
+    pub tags: Vec<String>,
+    pub people: Vec<String>,
+    pub photographer: Option<String>,
+    pub location: Option<String>,
+}
+
+impl From<&AutotagRule> for AutotagRuleDto {
+    fn from(value: &AutotagRule) -> Self {
+        Self {
+            id: value.id.clone(),
+            name: value.name.clone(),
+            query: value.query(),
+            tags: value.tags(),
+            people: value.people(),
+            photographer: value.photographer.clone(),
+            location: value.location.clone(),
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_autotag_rules() -> Result<Vec<AutotagRuleDto>, ApiError> {
+    Ok(_get_autotag_rules()
+        .await
+        .with_context(|| "Failed to get auto-tagging rules".to_string())?
+        .iter()
+        .map(AutotagRuleDto::from)
+        .collect())
+}
+
+#[tauri::command]
+pub async fn create_autotag_rule(
+    id: String,
+    name: String,
+    query: Vec<String>,
+    tags: Vec<String>,
+    people: Vec<String>,
+    photographer: Option<String>,
+    location: Option<String>,
+) -> Result<(), ApiError> {
+    debug!("Creating auto-tagging rule {name}");
+
+    Ok(_create_autotag_rule(&AutotagRule {
+        id,
+        name: name.clone(),
+        query: query.join("\n"),
+        tags: vec_to_row(&tags),
+        people: vec_to_row(&people),
+        photographer,
+        location,
+    })
+    .await
+    .with_context(|| format!("Could not create auto-tagging rule {name}"))?)
+}
+
+#[tauri::command]
+pub async fn update_autotag_rule(
+    id: String,
+    name: String,
+    query: Vec<String>,
+    tags: Vec<String>,
+    people: Vec<String>,
+    photographer: Option<String>,
+    location: Option<String>,
+) -> Result<(), ApiError> {
+    debug!("Updating auto-tagging rule {id}");
+
+    Ok(_update_autotag_rule(&AutotagRule {
+        id: id.clone(),
+        name,
+        query: query.join("\n"),
+        tags: vec_to_row(&tags),
+        people: vec_to_row(&people),
+        photographer,
+        location,
+    })
+    .await
+    .with_context(|| format!("Could not update auto-tagging rule {id}"))?)
+}
+
+#[tauri::command]
+pub async fn delete_autotag_rule(rule: String) -> Result<(), ApiError> {
+    debug!("Deleting auto-tagging rule {rule}");
+
+    Ok(_delete_autotag_rule(&rule)
+        .await
+        .with_context(|| format!("Could not delete auto-tagging rule {rule}"))?)
+}
+
+/// Runs the auto-tagging rules against the whole library. With `dry_run`, returns the changes
+/// that would be made without saving them.
+#[tauri::command]
+pub async fn run_autotag_rules<R: Runtime>(
+    app: AppHandle<R>,
+    dry_run: bool,
+) -> Result<Vec<AutotagChange>, ApiError> {
+    debug!("Running auto-tagging rules (dry run: {dry_run})");
+
+    let (changes, validation) = _run_autotag_rules(None, dry_run)
+        .await
+        .with_context(|| "Failed to run auto-tagging rules".to_string())?;
+    emit_validation(&app, validation)?;
+    Ok(changes)
+}
@@ -0,0 +1,305 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Result};
+use diesel::{
+    dsl::{delete, insert_into, update},
+    ExpressionMethods, QueryDsl,
+};
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+
+use crate::{
+    app::{
+        ensure_db, row_to_vec,
+        search::{check_query, search_photos, search_photos_in, SortOrder},
+        DB,
+    },
+    models::AutotagRule,
+    people::PEOPLE,
+    photos::PHOTOS,
+    places::PLACES,
+    schema::autotag_rules,
+    tags::ValidationChange,
+};
+
+pub mod api;
+
+/// The most photos a limited run searches at once
+const SEARCH_CHUNK_SIZE: usize = 10000;
+
+/// The changes that auto-tagging rules make (or would make, in a dry run) to one photo.
+#[derive(Serialize)]
+pub struct AutotagChange {
+    pub photo: String,
+    /// The names of the rules responsible for the changes
+    pub rules: Vec<String>,
+    pub tags: Vec<String>,
+    pub people: Vec<String>,
+    pub photographer: Option<String>,
+    pub location: Option<String>,
+}
+
+impl AutotagChange {
+    fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+            && self.people.is_empty()
+            && self.photographer.is_none()
+            && self.location.is_none()
+    }
+}
+
+impl AutotagRule {
+    /// Query terms are stored one per line, since terms can contain commas and spaces
+    pub fn query(&self) -> Vec<String> {
+        self.query.lines().map(str::to_string).collect()
+    }
+
+    pub fn tags(&self) -> Vec<String> {
+        row_to_vec(&self.tags)
+    }
+
+    pub fn people(&self) -> Vec<String> {
+        row_to_vec(&self.people)
+    }
+}
+
+/// Checks that a rule's query can be searched and that it does something.
+async fn check_rule(rule: &AutotagRule) -> Result<()> {
+    let query = rule.query();
+    if query.is_empty() {
+        return Err(anyhow!("Rule {} has no query", rule.name));
+    }
+    if let Some(error) = check_query(&query).await?.into_iter().next() {
+        return Err(anyhow!("Invalid term {}: {}", error.term, error.message));
+    }
+    if rule.tags().is_empty()
+        && rule.people().is_empty()
+        && rule.photographer.is_none()
+        && rule.location.is_none()
+    {
+        return Err(anyhow!("Rule {} has no actions", rule.name));
+    }
+    if let Some(photographer) = &rule.photographer {
+        if !PEOPLE.lock().await.contains_key(photographer) {
+            return Err(anyhow!("Person {photographer} not found"));
+        }
+    }
+    if let Some(location) = &rule.location {
+        if !PLACES.lock().await.contains_key(location) {
+            return Err(anyhow!("Place {location} not found"));
+        }
+    }
+    Ok(())
+}
+
+pub async fn get_autotag_rules() -> Result<Vec<AutotagRule>> {
+    ensure_db().await?;
+    Ok(autotag_rules::table
+        .order(autotag_rules::name)
+        .load::<AutotagRule>(DB.lock().await.as_mut().unwrap())
+        .await?)
+}
+
+pub async fn create_autotag_rule(rule: &AutotagRule) -> Result<()> {
+    check_rule(rule).await?;
+    ensure_db().await?;
+    insert_into(autotag_rules::table)
+        .values(rule.clone())
+        .execute(DB.lock().await.as_mut().unwrap())
+        .await?;
+    Ok(())
+}
+
+pub async fn update_autotag_rule(rule: &AutotagRule) -> Result<()> {
+    check_rule(rule).await?;
+    ensure_db().await?;
+    let updated = update(autotag_rules::table.filter(autotag_rules::id.eq(&rule.id)))
+        .set((
+            autotag_rules::name.eq(&rule.name),
+            autotag_rules::query.eq(&rule.query),
+            autotag_rules::tags.eq(&rule.tags),
+            autotag_rules::people.eq(&rule.people),
+            autotag_rules::photographer.eq(&rule.photographer),
+            autotag_rules::location.eq(&rule.location),
+        ))
+        .execute(DB.lock().await.as_mut().unwrap())
+        .await?;
+    if updated == 0 {
+        return Err(anyhow!("Rule {} not found", rule.id));
+    }
+    Ok(())
+}
+
+pub async fn delete_autotag_rule(rule: &String) -> Result<()> {
+    ensure_db().await?;
+    delete(autotag_rules::table.filter(autotag_rules::id.eq(rule)))
+        .execute(DB.lock().await.as_mut().unwrap())
+        .await?;
+    Ok(())
+}
+
+/// Runs the auto-tagging rules, optionally limited to the given photos. Tags and people are added
+/// to what the photo already has, while the photographer and location are only filled in when
+/// the photo doesn't have one yet. In a dry run the changes are returned without being saved.
+/// Also returns the photos whose validation result changed.
+pub async fn run_autotag_rules(
+    limit: Option<&[String]>,
+    dry_run: bool,
+) -> Result<(Vec<AutotagChange>, Vec<ValidationChange>)> {
+    let rules = get_autotag_rules().await?;
+    // Rules from before photographers were people can name someone who isn't in the database
+    let people = PEOPLE
+        .lock()
+        .await
+        .keys()
+        .cloned()
+        .collect::<HashSet<String>>();
+    let mut changes = BTreeMap::<String, AutotagChange>::new();
+    for rule in &rules {
+        let mut query = rule.query();
+        // Rules should apply to every matching photo, including ones that searches normally hide
+        if !query.iter().any(|t| t.to_uppercase().starts_with("SHOW:")) {
+            query.push("show:all".to_string());
+        }
+        let matched = match limit {
+            Some(limit) => {
+                let mut photos = Vec::new();
+                // SQLite limits how many values one statement can bind
+                for names in limit.chunks(SEARCH_CHUNK_SIZE) {
+                    photos.extend(
+                        search_photos_in(&query, SortOrder::from_str("name")?, names)
+                            .await?
+                            .photos,
+                    );
+                }
+                photos
+            }
+            None => {
+                search_photos(&query, SortOrder::from_str("name")?)
+                    .await?
+                    .photos
+            }
+        };
+        for photo in matched {
+            let change = changes
+                .entry(photo.name.clone())
+                .or_insert_with(|| AutotagChange {
+                    photo: photo.name.clone(),
+                    rules: vec![],
+                    tags: vec![],
+                    people: vec![],
+                    photographer: None,
+                    location: None,
+                });
+            let mut applied = false;
+            let existing_tags = photo.tags();
+            for tag in rule.tags() {
+                if !existing_tags.contains(&tag) && !change.tags.contains(&tag) {
+                    change.tags.push(tag);
+                    applied = true;
+                }
+            }
+            let existing_people = photo.people();
+            for person in rule.people() {
+                if !existing_people.contains(&person) && !change.people.contains(&person) {
+                    change.people.push(person);
+                    applied = true;
+                }
+            }
+            if photo.photographer.is_none()
+                && change.photographer.is_none()
+                && rule
+                    .photographer
+                    .as_ref()
+                    .is_some_and(|photographer| people.contains(photographer))
+            {
+                change.photographer = rule.photographer.clone();
+                applied = true;
+            }
+            if photo.location.is_none() && change.location.is_none() && rule.location.is_some() {
+                change.location = rule.location.clone();
+                applied = true;
+            }
+            if applied {
+                change.rules.push(rule.name.clone());
+            }
+        }
+    }
+    let changes = changes
+        .into_values()
+        .filter(|change| !change.is_empty())
+        .collect::<Vec<AutotagChange>>();
+
+    // Only the latest result for each photo matters
+    let mut validation = BTreeMap::<String, ValidationChange>::new();
+    if !dry_run {
+        let mut photos = PHOTOS.lock().await;
+        for change in &changes {
+            let Some(photo) = photos.get_mut(&change.photo) else {
+                continue;
+            };
+            if !change.tags.is_empty() {
+                let mut tags = photo.tags();
+                tags.extend(change.tags.clone());
+                let result = photo.set_photo_tags(&change.photo, &tags).await?;
+                validation.insert(
+                    change.photo.clone(),
+                    ValidationChange {
+                        photo: change.photo.clone(),
+                        validation: result,
+                    },
+                );
+            }
+            if !change.people.is_empty() {
+                let mut people = photo.people();
+                people.extend(change.people.clone());
+                validation.extend(
+                    photo
+                        .set_photo_people(&change.photo, &people)
+                        .await?
+                        .into_iter()
+                        .map(|change| (change.photo.clone(), change)),
+                );
+            }
+            // An earlier change to another photo in the group may have filled these in already
+            if change.photographer.is_some() && photo.photographer.is_none() {
+                validation.extend(
+                    photo
+                        .set_photographer(&change.photo, &change.photographer)
+                        .await?
+                        .into_iter()
+                        .map(|change| (change.photo.clone(), change)),
+                );
+            }
+            if change.location.is_some() && photo.location.is_none() {
+                validation.extend(
+                    photo
+                        .set_photo_location(&change.photo, &change.location)
+                        .await?
+                        .into_iter()
+                        .map(|change| (change.photo.clone(), change)),
+                );
+            }
+            // The setters change the whole group in the database, so the cached copies of the
+            // other photos in it have to follow before their own changes are applied
+            let updated = photo.clone();
+            if let Some(group) = &updated.photo_group {
+                for other in photos
+                    .values_mut()
+                    .filter(|other| other.photo_group.as_ref() == Some(group))
+                {
+                    other.tags = updated.tags.clone();
+                    other.people = updated.people.clone();
+                    other.photographer = updated.photographer.clone();
+                    other.photographer_name = updated.photographer_name.clone();
+                    other.location = updated.location.clone();
+                }
+            }
+        }
+    }
+
+    Ok((changes, validation.into_values().collect()))
+}
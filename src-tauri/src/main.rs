@@ -11,15 +11,28 @@ use log::LevelFilter;
 use tokio::fs;
 
 use crate::{
-    app::api::{initialize, photo_grid, refresh, remove_deleted},
+    app::api::{
+        check_query, explain_search, get_graph, initialize, photo_grid, refresh, remove_deleted,
+        suggest_query,
+    },
+    autotag::api::{
+        create_autotag_rule, delete_autotag_rule, get_autotag_rules, run_autotag_rules,
+        update_autotag_rule,
+    },
     people::api::{
-        create_person, create_person_category, get_people, get_people_categories,
-        set_person_category, set_person_name, set_person_photo,
+        add_relationship, assign_face, clear_photo_regions, create_person, create_person_category,
+        delete_person, delete_person_category, detect_faces, dismiss_face, export_gedcom,
+        export_photo_regions, export_vcard, get_birthdays, get_cameras, get_family_tree,
+        get_people, get_people_categories, get_people_stats, get_photo_regions, get_relationships,
+        get_unassigned_faces, get_unresolved_photographers, import_gedcom, import_photo_regions,
+        import_vcard, merge_people, remove_relationship, resolve_photographer,
+        set_camera_photographer, set_person_birthdate, set_person_category, set_person_name,
+        set_person_photo, set_photo_region, suggest_people, update_person_category,
     },
     photos::api::{
-        get_group, get_grouped_raw, set_photo_date, set_photo_desc, set_photo_group,
-        set_photo_hide_thumbnail, set_photo_is_duplicate, set_photo_location, set_photo_people,
-        set_photo_rating, set_photo_tags, set_photo_title, set_photographer,
+        get_group, get_grouped_raw, get_invalid_photos, set_photo_date, set_photo_desc,
+        set_photo_group, set_photo_hide_thumbnail, set_photo_is_duplicate, set_photo_location,
+        set_photo_people, set_photo_rating, set_photo_tags, set_photo_title, set_photographer,
     },
     places::api::{
         create_layer, create_place, create_shape, create_trip, delete_layer, delete_place,
@@ -27,14 +40,21 @@ use crate::{
         set_layer_name, set_place_category, set_place_layer, set_place_name, set_place_position,
         set_place_shape, set_shape_layer, set_shape_name, set_shape_points,
     },
-    settings::api::{add_color, get_colors, get_theme, promote_color, set_theme},
+    settings::api::{
+        add_color, get_colors, get_tag_canonicalization, get_theme, promote_color,
+        set_tag_canonicalization, set_theme,
+    },
     tags::api::{
-        get_tags, set_tag_color, set_tag_coreqs, set_tag_incompatible, set_tag_prereqs,
-        validate_photo,
+        create_tag_category, create_tag_rule, delete_tag, delete_tag_category, delete_tag_rule,
+        find_similar_tags, get_tag_categories, get_tag_rules, get_tag_subtree, get_tags,
+        merge_tags, rename_tag, resolve_tags, set_tag_aliases, set_tag_category, set_tag_color,
+        set_tag_coreqs, set_tag_incompatible, set_tag_parent, set_tag_prereqs, suggest_tags,
+        update_tag_category, update_tag_rule, validate_photo,
     },
 };
 
 mod app;
+mod autotag;
 mod models;
 mod people;
 mod photos;
@@ -80,11 +100,36 @@ async fn main() {
             set_person_name,
             set_person_category,
             set_person_photo,
+            delete_person,
+            merge_people,
             get_people,
             get_people_categories,
+            update_person_category,
+            delete_person_category,
+            get_photo_regions,
+            set_photo_region,
+            clear_photo_regions,
+            import_photo_regions,
+            export_photo_regions,
+            detect_faces,
+            get_unassigned_faces,
+            assign_face,
+            dismiss_face,
+            set_person_birthdate,
+            get_birthdays,
+            get_relationships,
+            add_relationship,
+            remove_relationship,
+            get_family_tree,
+            import_gedcom,
+            export_gedcom,
             initialize,
             photo_grid,
+            explain_search,
+            get_graph,
             remove_deleted,
+            suggest_query,
+            check_query,
             set_photo_title,
             set_photo_desc,
             set_photographer,
@@ -98,6 +143,7 @@ async fn main() {
             set_photo_hide_thumbnail,
             get_grouped_raw,
             get_group,
+            get_invalid_photos,
             refresh,
             get_layers,
             get_shapes,
@@ -124,13 +170,46 @@ async fn main() {
             set_tag_prereqs,
             set_tag_coreqs,
             set_tag_incompatible,
+            set_tag_parent,
+            set_tag_aliases,
+            set_tag_category,
+            get_tag_categories,
+            create_tag_category,
+            update_tag_category,
+            delete_tag_category,
+            resolve_tags,
+            find_similar_tags,
+            rename_tag,
+            merge_tags,
+            delete_tag,
             get_tags,
+            get_tag_subtree,
+            get_tag_rules,
+            create_tag_rule,
+            update_tag_rule,
+            delete_tag_rule,
             validate_photo,
+            suggest_tags,
+            get_autotag_rules,
+            create_autotag_rule,
+            update_autotag_rule,
+            delete_autotag_rule,
+            run_autotag_rules,
             get_theme,
             set_theme,
             get_colors,
             promote_color,
             add_color,
+            get_tag_canonicalization,
+            set_tag_canonicalization,
+            get_unresolved_photographers,
+            resolve_photographer,
+            get_cameras,
+            set_camera_photographer,
+            get_people_stats,
+            suggest_people,
+            import_vcard,
+            export_vcard,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,10 +1,53 @@
-use diesel::{Insertable, Queryable, Selectable};
+use diesel::{
+    dsl::sql, expression::SqlLiteral, sql_types::Text, Insertable, Queryable, Selectable,
+};
 use serde::Serialize;
 
+use crate::app::JsonList;
 use crate::schema::{
-    layers, people, people_categories, photo_groups, photos, places, settings, shapes, tags, trips,
+    autotag_rules, cameras, faces, layers, people, people_categories, people_relationships,
+    photo_groups, photo_people_regions, photos, places, settings, shapes, tag_categories,
+    tag_rules, tags, trips,
 };
 
+/// Selects a list from one of the join tables as a JSON array, in the order it was saved.
+fn join_list(column: &str, table: &str, filter: &str) -> SqlLiteral<Text> {
+    sql::<Text>(&format!(
+        r#"(SELECT json_group_array("{column}") FROM (SELECT "{column}" FROM "{table}" WHERE {filter} ORDER BY "position"))"#
+    ))
+}
+
+#[derive(Clone, Insertable, Queryable, Selectable)]
+#[diesel(table_name = autotag_rules)]
+pub struct AutotagRule {
+    pub id: String,
+    pub name: String,
+    pub query: String,
+    pub tags: Option<String>,
+    pub people: Option<String>,
+    pub photographer: Option<String>,
+    pub location: Option<String>,
+}
+
+#[derive(Clone, Insertable, Queryable, Selectable, Serialize)]
+#[diesel(table_name = cameras)]
+pub struct Camera {
+    pub serial: String,
+    pub photographer: String,
+}
+
+#[derive(Clone, Insertable, Queryable, Selectable, Serialize)]
+#[diesel(table_name = faces)]
+pub struct Face {
+    pub id: String,
+    pub photo: String,
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+    pub score: f32,
+}
+
 #[derive(Clone, Insertable, Queryable, Selectable)]
 #[diesel(table_name = layers)]
 pub struct Layer {
@@ -20,9 +63,14 @@ pub struct Person {
     pub name: String,
     pub photo: Option<String>,
     pub category: String,
+    /// The part of the photo to use as the person's avatar, as "x,y,w,h"
+    pub photo_region: Option<String>,
+    pub birthdate: Option<String>,
+    /// "Year" or "Date"
+    pub birthdate_precision: Option<String>,
 }
 
-#[derive(Insertable, Queryable, Selectable, Serialize)]
+#[derive(Clone, Insertable, Queryable, Selectable)]
 #[diesel(table_name = people_categories)]
 pub struct PersonCategory {
     pub id: String,
@@ -30,6 +78,27 @@ pub struct PersonCategory {
     pub color: String,
 }
 
+#[derive(Clone, Insertable, Queryable, Selectable)]
+#[diesel(table_name = photo_people_regions)]
+pub struct PhotoPeopleRegion {
+    pub photo: String,
+    pub person: String,
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+#[derive(Clone, Insertable, Queryable, Selectable, Serialize)]
+#[diesel(table_name = people_relationships)]
+pub struct PersonRelationship {
+    pub person: String,
+    /// What the related person is to this person: "Parent", "Child", "Sibling", "Partner" or
+    /// "Friend"
+    pub kind: String,
+    pub related: String,
+}
+
 #[derive(Clone, Queryable, Selectable, Serialize)]
 #[diesel(table_name = photo_groups)]
 pub struct PhotoGroup {
@@ -44,18 +113,33 @@ pub struct Photo {
     pub asset_path: String,
     pub title: Option<String>,
     pub description: Option<String>,
-    pub tags: Option<String>,
+    #[diesel(
+        select_expression = join_list("tag", "photo_tags", r#""photo" = "photos"."name""#),
+        select_expression_type = SqlLiteral<Text>,
+        deserialize_as = JsonList,
+        skip_insertion
+    )]
+    pub tags: Vec<String>,
     pub is_duplicate: Option<i32>,
     pub rating: Option<i32>,
     pub location: Option<String>,
     pub thumbnail: Option<String>,
     pub photo_group: Option<String>,
     pub date: Option<String>,
-    pub people: Option<String>,
+    #[diesel(
+        select_expression = join_list("person", "photo_people", r#""photo" = "photos"."name""#),
+        select_expression_type = SqlLiteral<Text>,
+        deserialize_as = JsonList,
+        skip_insertion
+    )]
+    pub people: Vec<String>,
     pub hide_thumbnail: Option<i32>,
-    pub photographer: Option<String>,
+    /// A photographer who hasn't been matched to a person yet
+    pub photographer_name: Option<String>,
     pub metadata_date: Option<String>,
     pub metadata_location: Option<String>,
+    pub photographer: Option<String>,
+    pub camera_serial: Option<String>,
 }
 
 #[derive(Clone, Insertable, Queryable, Selectable)]
@@ -92,9 +176,63 @@ pub struct Shape {
 pub struct Tag {
     pub name: String,
     pub color: Option<String>,
-    pub prereqs: Option<String>,
-    pub coreqs: Option<String>,
-    pub incompatible: Option<String>,
+    #[diesel(
+        select_expression = join_list(
+            "related",
+            "tag_relations",
+            r#""tag" = "tags"."name" AND "kind" = 'Prereq'"#
+        ),
+        select_expression_type = SqlLiteral<Text>,
+        deserialize_as = JsonList,
+        skip_insertion
+    )]
+    pub prereqs: Vec<String>,
+    #[diesel(
+        select_expression = join_list(
+            "related",
+            "tag_relations",
+            r#""tag" = "tags"."name" AND "kind" = 'Coreq'"#
+        ),
+        select_expression_type = SqlLiteral<Text>,
+        deserialize_as = JsonList,
+        skip_insertion
+    )]
+    pub coreqs: Vec<String>,
+    #[diesel(
+        select_expression = join_list(
+            "related",
+            "tag_relations",
+            r#""tag" = "tags"."name" AND "kind" = 'Incompatible'"#
+        ),
+        select_expression_type = SqlLiteral<Text>,
+        deserialize_as = JsonList,
+        skip_insertion
+    )]
+    pub incompatible: Vec<String>,
+    pub parent: Option<String>,
+    pub aliases: Option<String>,
+    pub category: Option<String>,
+}
+
+#[derive(Clone, Insertable, Queryable, Selectable)]
+#[diesel(table_name = tag_categories)]
+pub struct TagCategory {
+    pub id: String,
+    pub name: String,
+    pub color: String,
+    pub sort_order: i32,
+    pub max_per_photo: Option<i32>,
+}
+
+#[derive(Clone, Insertable, Queryable, Selectable)]
+#[diesel(table_name = tag_rules)]
+pub struct TagRule {
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    pub tags: Option<String>,
+    pub field: Option<String>,
+    pub condition: Option<String>,
 }
 
 #[derive(Clone, Insertable, Queryable, Selectable)]
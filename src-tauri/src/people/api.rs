@@ -1,14 +1,51 @@
 use anyhow::Context;
 use log::debug;
+use tauri::{AppHandle, Manager, Runtime};
 
 use crate::{
     app::ApiError,
-    models::PersonCategory,
+    models::{Face, PersonCategory, PersonRelationship},
     people::{
-        create_person as _create_person, create_person_category as _create_person_category,
-        get_people as _get_people, get_people_categories as _get_people_categories, PersonDto,
-        PEOPLE,
+        birthdays::{get_birthdays as _get_birthdays, Birthdate, Birthday},
+        categories::{
+            create_person_category as _create_person_category,
+            delete_person_category as _delete_person_category,
+            get_people_categories as _get_people_categories, get_person_category_counts,
+            update_person_category as _update_person_category, PersonCategoryDto,
+        },
+        create_person as _create_person, delete_person as _delete_person,
+        faces::{
+            assign_face as _assign_face, detect_faces as _detect_faces,
+            dismiss_face as _dismiss_face, get_unassigned_faces as _get_unassigned_faces,
+        },
+        gedcom::{export_gedcom as _export_gedcom, import_gedcom as _import_gedcom, GedcomImport},
+        get_people as _get_people, merge_people as _merge_people,
+        photographers::{
+            get_cameras as _get_cameras,
+            get_unresolved_photographers as _get_unresolved_photographers,
+            resolve_photographer as _resolve_photographer,
+            set_camera_photographer as _set_camera_photographer, CameraDto, UnresolvedPhotographer,
+        },
+        regions::{
+            clear_photo_regions as _clear_photo_regions,
+            export_photo_regions as _export_photo_regions, get_photo_regions as _get_photo_regions,
+            import_photo_regions as _import_photo_regions, set_photo_region as _set_photo_region,
+            PersonRegion, Region, RegionImport,
+        },
+        relationships::{
+            add_relationship as _add_relationship, get_family_tree as _get_family_tree,
+            get_relationships as _get_relationships, remove_relationship as _remove_relationship,
+            FamilyTree, RelationshipKind,
+        },
+        stats::{get_people_stats as _get_people_stats, PeopleStats},
+        suggestions::{suggest_people as _suggest_people, PersonSuggestion},
+        vcard::{
+            export_vcard as _export_vcard, import_vcard as _import_vcard, DuplicatePolicy,
+            VcardImport,
+        },
+        PersonDto, PEOPLE,
     },
+    photos::api::emit_validation,
 };
 
 #[tauri::command]
@@ -74,7 +111,11 @@ pub async fn set_person_category(person: String, value: String) -> Result<(), Ap
 }
 
 #[tauri::command]
-pub async fn set_person_photo(person: String, value: Option<String>) -> Result<(), ApiError> {
+pub async fn set_person_photo(
+    person: String,
+    value: Option<String>,
+    region: Option<Region>,
+) -> Result<(), ApiError> {
     debug!(
         "Setting person {person} photo to {}",
         value.as_ref().unwrap_or(&"NULL".to_string())
@@ -88,7 +129,7 @@ pub async fn set_person_photo(person: String, value: Option<String>) -> Result<(
 
     target
         .unwrap()
-        .set_person_photo(&person, &value)
+        .set_person_photo(&person, &value, &region)
         .await
         .with_context(|| {
             format!(
@@ -100,6 +141,79 @@ pub async fn set_person_photo(person: String, value: Option<String>) -> Result<(
     Ok(())
 }
 
+#[tauri::command]
+pub async fn get_photo_regions(photo: String) -> Result<Vec<PersonRegion>, ApiError> {
+    Ok(_get_photo_regions(&photo)
+        .await
+        .with_context(|| format!("Could not get regions of photo {photo}"))?
+        .iter()
+        .map(PersonRegion::from)
+        .collect::<Vec<PersonRegion>>())
+}
+
+#[tauri::command]
+pub async fn set_photo_region(
+    photo: String,
+    person: String,
+    region: Region,
+) -> Result<(), ApiError> {
+    debug!("Setting region of person {person} in photo {photo}");
+
+    Ok(_set_photo_region(&photo, &person, &region)
+        .await
+        .with_context(|| format!("Could not set region of person {person} in photo {photo}"))?)
+}
+
+#[tauri::command]
+pub async fn clear_photo_regions(photo: String, person: Option<String>) -> Result<(), ApiError> {
+    debug!("Clearing regions in photo {photo}");
+
+    Ok(_clear_photo_regions(&photo, person.as_ref())
+        .await
+        .with_context(|| format!("Could not clear regions in photo {photo}"))?)
+}
+
+#[tauri::command]
+pub async fn import_photo_regions<R: Runtime>(
+    app: AppHandle<R>,
+    photo: String,
+) -> Result<RegionImport, ApiError> {
+    debug!("Importing regions from the metadata of photo {photo}");
+
+    let (import, changes) = _import_photo_regions(&photo)
+        .await
+        .with_context(|| format!("Could not import regions of photo {photo}"))?;
+    emit_validation(&app, changes)?;
+    Ok(import)
+}
+
+#[tauri::command]
+pub async fn export_photo_regions(photo: String) -> Result<String, ApiError> {
+    debug!("Exporting regions of photo {photo}");
+
+    Ok(_export_photo_regions(&photo)
+        .await
+        .with_context(|| format!("Could not export regions of photo {photo}"))?)
+}
+
+#[tauri::command]
+pub async fn delete_person(person: String) -> Result<(), ApiError> {
+    debug!("Deleting person {person}");
+
+    Ok(_delete_person(&person)
+        .await
+        .with_context(|| format!("Could not delete person {person}"))?)
+}
+
+#[tauri::command]
+pub async fn merge_people(from: String, into: String) -> Result<(), ApiError> {
+    debug!("Merging person {from} into {into}");
+
+    Ok(_merge_people(&from, &into)
+        .await
+        .with_context(|| format!("Could not merge person {from} into {into}"))?)
+}
+
 #[tauri::command]
 pub async fn get_people() -> Result<Vec<PersonDto>, ApiError> {
     Ok(_get_people()
@@ -111,8 +225,290 @@ pub async fn get_people() -> Result<Vec<PersonDto>, ApiError> {
 }
 
 #[tauri::command]
-pub async fn get_people_categories() -> Result<Vec<PersonCategory>, ApiError> {
+pub async fn update_person_category(
+    id: String,
+    name: String,
+    color: String,
+) -> Result<(), ApiError> {
+    debug!("Updating people category {id}");
+
+    Ok(_update_person_category(PersonCategory {
+        id: id.clone(),
+        name,
+        color,
+    })
+    .await
+    .with_context(|| format!("Could not update people category {id}"))?)
+}
+
+#[tauri::command]
+pub async fn delete_person_category(category: String, reassign: String) -> Result<(), ApiError> {
+    debug!("Deleting people category {category}, moving its people to {reassign}");
+
+    Ok(_delete_person_category(&category, &reassign)
+        .await
+        .with_context(|| format!("Could not delete people category {category}"))?)
+}
+
+#[tauri::command]
+pub async fn get_people_categories() -> Result<Vec<PersonCategoryDto>, ApiError> {
+    let counts = get_person_category_counts(&*PEOPLE.lock().await);
     Ok(_get_people_categories()
         .await
-        .with_context(|| "Could not get people categories".to_string())?)
+        .with_context(|| "Could not get people categories".to_string())?
+        .iter()
+        .map(|category| {
+            let mut dto = PersonCategoryDto::from(category);
+            dto.count = counts.get(&category.id).copied().unwrap_or(0);
+            dto
+        })
+        .collect::<Vec<PersonCategoryDto>>())
+}
+
+#[tauri::command]
+pub async fn detect_faces<R: Runtime>(
+    app: AppHandle<R>,
+    photos: Option<Vec<String>>,
+) -> Result<(), ApiError> {
+    debug!(
+        "Starting face detection in {} photos",
+        photos
+            .as_ref()
+            .map(|photos| photos.len().to_string())
+            .unwrap_or("unscanned".to_string())
+    );
+
+    Ok(_detect_faces(&app, photos.as_ref())
+        .await
+        .with_context(|| "Could not start face detection".to_string())?)
+}
+
+#[tauri::command]
+pub async fn get_unassigned_faces(photo: Option<String>) -> Result<Vec<Face>, ApiError> {
+    Ok(_get_unassigned_faces(photo.as_ref())
+        .await
+        .with_context(|| "Could not get unassigned faces".to_string())?)
+}
+
+#[tauri::command]
+pub async fn assign_face<R: Runtime>(
+    app: AppHandle<R>,
+    face: String,
+    person: String,
+) -> Result<Region, ApiError> {
+    debug!("Assigning face {face} to person {person}");
+
+    let (region, changes) = _assign_face(&face, &person)
+        .await
+        .with_context(|| format!("Could not assign face {face} to person {person}"))?;
+    emit_validation(&app, changes)?;
+    Ok(region)
+}
+
+#[tauri::command]
+pub async fn dismiss_face(face: String) -> Result<(), ApiError> {
+    debug!("Dismissing face {face}");
+
+    Ok(_dismiss_face(&face)
+        .await
+        .with_context(|| format!("Could not dismiss face {face}"))?)
+}
+
+#[tauri::command]
+pub async fn set_person_birthdate(
+    person: String,
+    value: Option<Birthdate>,
+) -> Result<(), ApiError> {
+    debug!(
+        "Setting person {person} birthdate to {}",
+        value
+            .as_ref()
+            .map(|value| value.date.clone())
+            .unwrap_or("NULL".to_string())
+    );
+
+    let mut people = PEOPLE.lock().await;
+    let target = people.get_mut(&person);
+    if target.is_none() {
+        return Err(ApiError::NotFound(format!("Person {person} not found!")));
+    }
+
+    target
+        .unwrap()
+        .set_person_birthdate(&person, &value)
+        .await
+        .with_context(|| format!("Could not set person {person} birthdate"))?;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_birthdays(days: Option<u32>) -> Result<Vec<Birthday>, ApiError> {
+    Ok(_get_birthdays(days.unwrap_or(7))
+        .await
+        .with_context(|| "Could not get birthdays".to_string())?)
+}
+
+#[tauri::command]
+pub async fn get_relationships(
+    person: Option<String>,
+) -> Result<Vec<PersonRelationship>, ApiError> {
+    Ok(_get_relationships(person.as_ref())
+        .await
+        .with_context(|| "Could not get relationships".to_string())?)
+}
+
+#[tauri::command]
+pub async fn add_relationship(
+    person: String,
+    kind: RelationshipKind,
+    related: String,
+) -> Result<(), ApiError> {
+    debug!("Adding {related} as {kind} of {person}");
+
+    Ok(_add_relationship(&person, kind, &related)
+        .await
+        .with_context(|| format!("Could not add {related} as {kind} of {person}"))?)
+}
+
+#[tauri::command]
+pub async fn remove_relationship(
+    person: String,
+    kind: RelationshipKind,
+    related: String,
+) -> Result<(), ApiError> {
+    debug!("Removing {related} as {kind} of {person}");
+
+    Ok(_remove_relationship(&person, kind, &related)
+        .await
+        .with_context(|| format!("Could not remove {related} as {kind} of {person}"))?)
+}
+
+#[tauri::command]
+pub async fn get_family_tree(person: String, depth: Option<usize>) -> Result<FamilyTree, ApiError> {
+    Ok(_get_family_tree(&person, depth)
+        .await
+        .with_context(|| format!("Could not get family tree of {person}"))?)
+}
+
+#[tauri::command]
+pub async fn import_gedcom(path: String, category: String) -> Result<GedcomImport, ApiError> {
+    debug!("Importing people from GEDCOM file {path}");
+
+    Ok(_import_gedcom(&path, &category)
+        .await
+        .with_context(|| format!("Could not import GEDCOM file {path}"))?)
+}
+
+#[tauri::command]
+pub async fn export_gedcom(path: String, person: Option<String>) -> Result<(), ApiError> {
+    debug!("Exporting people to GEDCOM file {path}");
+
+    Ok(_export_gedcom(&path, person.as_ref())
+        .await
+        .with_context(|| format!("Could not export GEDCOM file {path}"))?)
+}
+
+#[tauri::command]
+pub async fn get_unresolved_photographers() -> Result<Vec<UnresolvedPhotographer>, ApiError> {
+    Ok(_get_unresolved_photographers()
+        .await
+        .with_context(|| "Could not get unresolved photographers".to_string())?)
+}
+
+#[tauri::command]
+pub async fn resolve_photographer<R: Runtime>(
+    app: AppHandle<R>,
+    name: String,
+    person: String,
+) -> Result<usize, ApiError> {
+    debug!("Resolving photographer {name} to {person}");
+
+    let (count, changes) = _resolve_photographer(&name, &person)
+        .await
+        .with_context(|| format!("Could not resolve photographer {name} to {person}"))?;
+    emit_validation(&app, changes)?;
+    Ok(count)
+}
+
+#[tauri::command]
+pub async fn get_cameras() -> Result<Vec<CameraDto>, ApiError> {
+    Ok(_get_cameras()
+        .await
+        .with_context(|| "Could not get cameras".to_string())?)
+}
+
+#[tauri::command]
+pub async fn set_camera_photographer<R: Runtime>(
+    app: AppHandle<R>,
+    serial: String,
+    person: Option<String>,
+    apply: Option<bool>,
+) -> Result<usize, ApiError> {
+    debug!(
+        "Setting camera {serial} photographer to {}",
+        person.as_ref().unwrap_or(&"NULL".to_string())
+    );
+
+    let (count, changes) =
+        _set_camera_photographer(&serial, person.as_ref(), apply.unwrap_or(false))
+            .await
+            .with_context(|| format!("Could not set camera {serial} photographer"))?;
+    emit_validation(&app, changes)?;
+    Ok(count)
+}
+
+/// Gets who appears with whom for a person or everyone, optionally limited to a search. `limit`
+/// is the number of pairs, places and photographers to return.
+#[tauri::command]
+pub async fn get_people_stats(
+    person: Option<String>,
+    query: Option<Vec<String>>,
+    limit: Option<usize>,
+) -> Result<PeopleStats, ApiError> {
+    Ok(
+        _get_people_stats(person.as_ref(), query.as_ref(), limit.unwrap_or(10))
+            .await
+            .with_context(|| "Could not get people statistics".to_string())?,
+    )
+}
+
+/// Suggests people who might be in a photo, with the reasons for each. `hours` is how close in
+/// time other photos have to be to count.
+#[tauri::command]
+pub async fn suggest_people(
+    photo: String,
+    hours: Option<u32>,
+    limit: Option<usize>,
+) -> Result<Vec<PersonSuggestion>, ApiError> {
+    Ok(
+        _suggest_people(&photo, hours.unwrap_or(24), limit.unwrap_or(10))
+            .await
+            .with_context(|| format!("Could not suggest people for {photo}"))?,
+    )
+}
+
+#[tauri::command]
+pub async fn import_vcard<R: Runtime>(
+    app: AppHandle<R>,
+    path: String,
+    category: String,
+    duplicates: DuplicatePolicy,
+) -> Result<VcardImport, ApiError> {
+    debug!("Importing people from vCard file {path}");
+
+    Ok(
+        _import_vcard(&path, &category, duplicates, &app.path().app_data_dir()?)
+            .await
+            .with_context(|| format!("Could not import vCard file {path}"))?,
+    )
+}
+
+#[tauri::command]
+pub async fn export_vcard(path: String, category: Option<String>) -> Result<(), ApiError> {
+    debug!("Exporting people to vCard file {path}");
+
+    Ok(_export_vcard(&path, category.as_ref())
+        .await
+        .with_context(|| format!("Could not export vCard file {path}"))?)
 }
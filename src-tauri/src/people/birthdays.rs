@@ -0,0 +1,182 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{Datelike, NaiveDate};
+use diesel::{dsl::update, ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+use crate::{
+    app::{ensure_db, DATE_FORMAT, DB},
+    models::Person,
+    people::PEOPLE,
+    photos::PHOTOS,
+    schema::people,
+};
+
+#[derive(Clone, Copy, Deserialize, Display, EnumString, PartialEq, Serialize)]
+pub enum BirthdatePrecision {
+    /// Only the year is known
+    Year,
+    Date,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Birthdate {
+    /// YYYY-MM-DD, or just YYYY when only the year is known
+    pub date: String,
+    pub precision: BirthdatePrecision,
+}
+
+impl Birthdate {
+    fn parse(&self) -> Result<NaiveDate> {
+        match self.precision {
+            BirthdatePrecision::Year => {
+                let year = self.date.get(..4).unwrap_or(&self.date);
+                year.parse::<i32>()
+                    .ok()
+                    .and_then(|year| NaiveDate::from_ymd_opt(year, 1, 1))
+                    .ok_or_else(|| anyhow!("Invalid birth year (expected YYYY): {}", self.date))
+            }
+            BirthdatePrecision::Date => NaiveDate::parse_from_str(&self.date, DATE_FORMAT)
+                .with_context(|| format!("Invalid birthdate (expected YYYY-MM-DD): {}", self.date)),
+        }
+    }
+}
+
+/// A person's birthday in a year, with the photos of them taken near it.
+#[derive(Serialize)]
+pub struct BirthdayYear {
+    pub year: i32,
+    pub age: u32,
+    pub photos: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct Birthday {
+    pub person: String,
+    pub name: String,
+    pub birthdate: String,
+    pub years: Vec<BirthdayYear>,
+}
+
+/// The date of a birthday in a year. Anyone born on the 29th of February celebrates on the 28th
+/// in other years.
+fn anniversary(birthdate: &NaiveDate, year: i32) -> Option<NaiveDate> {
+    birthdate
+        .with_year(year)
+        .or_else(|| NaiveDate::from_ymd_opt(year, 2, 28))
+}
+
+impl Person {
+    pub fn birthdate(&self) -> Option<(NaiveDate, BirthdatePrecision)> {
+        let date = NaiveDate::parse_from_str(self.birthdate.as_ref()?, DATE_FORMAT).ok()?;
+        let precision = self
+            .birthdate_precision
+            .as_ref()
+            .and_then(|precision| BirthdatePrecision::from_str(precision).ok())
+            .unwrap_or(BirthdatePrecision::Date);
+        Some((date, precision))
+    }
+
+    /// The person's age in whole years on a date. When only the birth year is known, the age is
+    /// the difference between the years, so it may be one year too high before their birthday.
+    pub fn age_at(&self, date: &NaiveDate) -> Option<u32> {
+        let (birthdate, precision) = self.birthdate()?;
+        if date.year() < birthdate.year() {
+            return None;
+        }
+        match precision {
+            BirthdatePrecision::Year => Some((date.year() - birthdate.year()) as u32),
+            BirthdatePrecision::Date => date.years_since(birthdate),
+        }
+    }
+
+    pub async fn set_person_birthdate(
+        &mut self,
+        person: &String,
+        value: &Option<Birthdate>,
+    ) -> Result<()> {
+        let (date, precision) = match value {
+            Some(value) => (
+                Some(value.parse()?.format(DATE_FORMAT).to_string()),
+                Some(value.precision.to_string()),
+            ),
+            None => (None, None),
+        };
+        ensure_db().await?;
+        update(people::table.filter(people::id.eq(person)))
+            .set((
+                people::birthdate.eq(&date),
+                people::birthdate_precision.eq(&precision),
+            ))
+            .execute(DB.lock().await.as_mut().unwrap())
+            .await?;
+        self.birthdate = date;
+        self.birthdate_precision = precision;
+
+        Ok(())
+    }
+}
+
+/// Lists the photos of each person taken within `days` of their birthday, by year. Only people
+/// whose full birthdate is known are included, in the order their birthdays fall in the year.
+pub async fn get_birthdays(days: u32) -> Result<Vec<Birthday>> {
+    let photos = PHOTOS.lock().await;
+    let people = PEOPLE.lock().await;
+    let mut birthdays = Vec::<(NaiveDate, Birthday)>::new();
+    for person in people.values() {
+        let Some((birthdate, BirthdatePrecision::Date)) = person.birthdate() else {
+            continue;
+        };
+        let mut years = BTreeMap::<i32, Vec<(NaiveDate, String)>>::new();
+        for photo in photos.values() {
+            let Some(date) = photo.date() else {
+                continue;
+            };
+            if !photo.people.contains(&person.id) {
+                continue;
+            }
+            // The nearest birthday may be in the year before or after, around New Year
+            let nearest = (date.year() - 1..=date.year() + 1)
+                .filter(|year| *year >= birthdate.year())
+                .filter_map(|year| Some((year, anniversary(&birthdate, year)?)))
+                .min_by_key(|(_, birthday)| (date - *birthday).num_days().abs());
+            if let Some((year, birthday)) = nearest {
+                if (date - birthday).num_days().unsigned_abs() <= u64::from(days) {
+                    years
+                        .entry(year)
+                        .or_default()
+                        .push((date, photo.name.clone()));
+                }
+            }
+        }
+        birthdays.push((
+            birthdate,
+            Birthday {
+                person: person.id.clone(),
+                name: person.name.clone(),
+                birthdate: birthdate.format(DATE_FORMAT).to_string(),
+                years: years
+                    .into_iter()
+                    .map(|(year, mut photos)| {
+                        photos.sort();
+                        BirthdayYear {
+                            year,
+                            age: (year - birthdate.year()) as u32,
+                            photos: photos.into_iter().map(|(_, photo)| photo).collect(),
+                        }
+                    })
+                    .collect(),
+            },
+        ));
+    }
+    birthdays.sort_by(|(a, a_birthday), (b, b_birthday)| {
+        (a.month(), a.day(), &a_birthday.name).cmp(&(b.month(), b.day(), &b_birthday.name))
+    });
+    Ok(birthdays
+        .into_iter()
+        .map(|(_, birthday)| birthday)
+        .collect())
+}
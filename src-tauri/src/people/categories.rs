@@ -0,0 +1,144 @@
+use std::{collections::HashMap, sync::LazyLock};
+use tokio::sync::Mutex as AsyncMutex;
+
+use anyhow::{anyhow, Result};
+use diesel::{
+    dsl::{delete, insert_into, update},
+    ExpressionMethods, QueryDsl,
+};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use serde::Serialize;
+
+use crate::{
+    app::{ensure_db, DB},
+    models::{Person, PersonCategory},
+    people::{PEOPLE, PEOPLE_COUNTS},
+    schema::{people, people_categories},
+};
+
+pub static PEOPLE_CATEGORIES: LazyLock<AsyncMutex<HashMap<String, PersonCategory>>> =
+    LazyLock::new(|| AsyncMutex::new(HashMap::new()));
+
+pub async fn get_people_categories() -> Result<Vec<PersonCategory>> {
+    let mut categories = PEOPLE_CATEGORIES
+        .lock()
+        .await
+        .values()
+        .cloned()
+        .collect::<Vec<PersonCategory>>();
+    categories.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(categories)
+}
+
+/// Sums the photo counts of the people in each category.
+pub fn get_person_category_counts(people: &HashMap<String, Person>) -> HashMap<String, usize> {
+    let people_counts = PEOPLE_COUNTS.lock().unwrap();
+    let mut counts = HashMap::<String, usize>::new();
+    for person in people.values() {
+        *counts.entry(person.category.clone()).or_insert(0) +=
+            people_counts.get(&person.id).copied().unwrap_or(0);
+    }
+    counts
+}
+
+pub async fn create_person_category(id: &str, name: &str, color: &str) -> Result<()> {
+    ensure_db().await?;
+    let mut categories = PEOPLE_CATEGORIES.lock().await;
+    if categories.contains_key(id) {
+        return Err(anyhow!("People category {id} already exists"));
+    }
+    let category = PersonCategory {
+        id: id.to_owned(),
+        name: name.to_owned(),
+        color: color.to_owned(),
+    };
+    insert_into(people_categories::table)
+        .values(category.clone())
+        .execute(DB.lock().await.as_mut().unwrap())
+        .await?;
+    categories.insert(id.to_owned(), category);
+
+    Ok(())
+}
+
+pub async fn update_person_category(category: PersonCategory) -> Result<()> {
+    ensure_db().await?;
+    let mut categories = PEOPLE_CATEGORIES.lock().await;
+    if !categories.contains_key(&category.id) {
+        return Err(anyhow!("People category {} not found", category.id));
+    }
+    update(people_categories::table.filter(people_categories::id.eq(&category.id)))
+        .set((
+            people_categories::name.eq(&category.name),
+            people_categories::color.eq(&category.color),
+        ))
+        .execute(DB.lock().await.as_mut().unwrap())
+        .await?;
+    categories.insert(category.id.clone(), category);
+
+    Ok(())
+}
+
+/// Deletes a people category. Everyone in it is moved to another category, since every person
+/// has to belong to one.
+pub async fn delete_person_category(category: &String, reassign: &String) -> Result<()> {
+    if category == reassign {
+        return Err(anyhow!(
+            "Cannot move the people in category {category} into itself"
+        ));
+    }
+    ensure_db().await?;
+    let mut categories = PEOPLE_CATEGORIES.lock().await;
+    if !categories.contains_key(category) {
+        return Err(anyhow!("People category {category} not found"));
+    }
+    if !categories.contains_key(reassign) {
+        return Err(anyhow!("People category {reassign} not found"));
+    }
+    let mut people_cache = PEOPLE.lock().await;
+    let mut conn = DB.lock().await;
+    let conn = conn.as_mut().unwrap();
+    conn.transaction::<_, anyhow::Error, _>(|conn| {
+        async move {
+            update(people::table.filter(people::category.eq(category)))
+                .set(people::category.eq(reassign))
+                .execute(conn)
+                .await?;
+            delete(people_categories::table.filter(people_categories::id.eq(category)))
+                .execute(conn)
+                .await?;
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    for person in people_cache.values_mut() {
+        if person.category == *category {
+            person.category = reassign.clone();
+        }
+    }
+    categories.remove(category);
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct PersonCategoryDto {
+    pub id: String,
+    pub name: String,
+    pub color: String,
+    /// The sum of the photo counts of the people in this category
+    pub count: usize,
+}
+
+impl From<&PersonCategory> for PersonCategoryDto {
+    fn from(value: &PersonCategory) -> Self {
+        Self {
+            id: value.id.clone(),
+            name: value.name.clone(),
+            color: value.color.clone(),
+            count: 0,
+        }
+    }
+}
@@ -0,0 +1,381 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use diesel::{
+    dsl::{delete, insert_into, insert_or_ignore_into},
+    ExpressionMethods, QueryDsl,
+};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use log::{debug, info, warn};
+use serde::Serialize;
+use tauri::{async_runtime, AppHandle, Emitter, Manager, Runtime};
+use tokio::sync::mpsc::unbounded_channel;
+
+use crate::{
+    app::{ensure_db, DB},
+    models::Face,
+    people::{
+        regions::{get_photo_regions, write_region, Region},
+        PEOPLE,
+    },
+    photos::PHOTOS,
+    schema::{face_scans, faces},
+    tags::ValidationChange,
+};
+
+/// The SeetaFace frontal face model, bundled from src-tauri/models
+const MODEL: &str = "models/seeta_fd_frontal_v1.0.bin";
+
+/// Detected faces that mostly cover a region someone is already marked in are skipped
+const MAX_OVERLAP: f32 = 0.5;
+
+/// Set while a detection job is running, since only one can run at a time
+static DETECTING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Serialize)]
+pub struct FaceDetectionProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
+#[derive(Clone, Serialize)]
+pub struct FaceDetectionFinished {
+    /// The number of faces found
+    pub found: usize,
+    pub error: Option<String>,
+}
+
+struct DetectedFace {
+    region: Region,
+    score: f32,
+}
+
+#[cfg(feature = "face-detection")]
+struct FaceDetector(Box<dyn rustface::Detector>);
+
+#[cfg(feature = "face-detection")]
+impl FaceDetector {
+    fn new(model: &Path) -> Result<Self> {
+        let model = model
+            .to_str()
+            .ok_or_else(|| anyhow!("Invalid face detection model path"))?;
+        let mut detector = rustface::create_detector(model)
+            .map_err(|err| anyhow!("Could not load face detection model: {err}"))?;
+        detector.set_min_face_size(20);
+        detector.set_score_thresh(2.0);
+        detector.set_pyramid_scale_factor(0.8);
+        detector.set_slide_window_step(4, 4);
+        Ok(Self(detector))
+    }
+
+    fn detect(&mut self, photo: &str) -> Result<Vec<DetectedFace>> {
+        let image = image::open(photo)?.to_luma8();
+        let (width, height) = image.dimensions();
+        let data = rustface::ImageData::new(image.as_raw(), width, height);
+        Ok(self
+            .0
+            .detect(&data)
+            .iter()
+            .filter_map(|face| {
+                // Faces at the edge of the photo can extend past it
+                let bbox = face.bbox();
+                let x = bbox.x().max(0) as f32;
+                let y = bbox.y().max(0) as f32;
+                let right = (bbox.x() + bbox.width() as i32).min(width as i32) as f32;
+                let bottom = (bbox.y() + bbox.height() as i32).min(height as i32) as f32;
+                let region = Region {
+                    x: x / width as f32,
+                    y: y / height as f32,
+                    w: (right - x) / width as f32,
+                    h: (bottom - y) / height as f32,
+                };
+                region.check().ok()?;
+                Some(DetectedFace {
+                    region,
+                    score: face.score() as f32,
+                })
+            })
+            .collect())
+    }
+}
+
+#[cfg(not(feature = "face-detection"))]
+struct FaceDetector;
+
+#[cfg(not(feature = "face-detection"))]
+impl FaceDetector {
+    fn new(_model: &Path) -> Result<Self> {
+        Err(anyhow!("Face detection is not enabled in this build"))
+    }
+
+    fn detect(&mut self, _photo: &str) -> Result<Vec<DetectedFace>> {
+        Ok(Vec::new())
+    }
+}
+
+/// How much of the smaller of two regions the other one covers.
+fn overlap(a: &Region, b: &Region) -> f32 {
+    let w = (a.x + a.w).min(b.x + b.w) - a.x.max(b.x);
+    let h = (a.y + a.h).min(b.y + b.h) - a.y.max(b.y);
+    if w <= 0.0 || h <= 0.0 {
+        return 0.0;
+    }
+    (w * h) / (a.w * a.h).min(b.w * b.h)
+}
+
+/// Replaces the unassigned faces in a photo with newly detected ones, and marks the photo as
+/// scanned. Returns the number of faces saved.
+async fn save_faces(photo: &String, detected: Vec<DetectedFace>) -> Result<usize> {
+    let regions = get_photo_regions(photo).await?;
+    let rows = detected
+        .into_iter()
+        .filter(|face| {
+            !regions.iter().any(|row| {
+                let region = Region {
+                    x: row.x,
+                    y: row.y,
+                    w: row.w,
+                    h: row.h,
+                };
+                overlap(&face.region, &region) > MAX_OVERLAP
+            })
+        })
+        .enumerate()
+        .map(|(i, face)| Face {
+            id: format!("{photo}:{i}"),
+            photo: photo.clone(),
+            x: face.region.x,
+            y: face.region.y,
+            w: face.region.w,
+            h: face.region.h,
+            score: face.score,
+        })
+        .collect::<Vec<Face>>();
+    let found = rows.len();
+
+    let mut conn = DB.lock().await;
+    let conn = conn.as_mut().unwrap();
+    conn.transaction::<_, anyhow::Error, _>(|conn| {
+        async move {
+            delete(faces::table.filter(faces::photo.eq(photo)))
+                .execute(conn)
+                .await?;
+            for row in rows {
+                insert_into(faces::table).values(row).execute(conn).await?;
+            }
+            insert_or_ignore_into(face_scans::table)
+                .values(face_scans::photo.eq(photo))
+                .execute(conn)
+                .await?;
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+    Ok(found)
+}
+
+async fn run_detection<R: Runtime>(
+    app: &AppHandle<R>,
+    model: PathBuf,
+    targets: Vec<String>,
+) -> Result<usize> {
+    let total = targets.len();
+    let (sender, mut receiver) = unbounded_channel::<(String, Result<Vec<DetectedFace>>)>();
+    // Detection is slow, so it runs on its own thread and sends each photo's faces back here
+    let worker = async_runtime::spawn_blocking(move || -> Result<()> {
+        let mut detector = FaceDetector::new(&model)?;
+        for photo in targets {
+            let detected = detector.detect(&photo);
+            if sender.send((photo, detected)).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    });
+
+    let mut done = 0;
+    let mut found = 0;
+    while let Some((photo, detected)) = receiver.recv().await {
+        done += 1;
+        match detected {
+            Ok(detected) => match save_faces(&photo, detected).await {
+                Ok(count) => found += count,
+                Err(err) => warn!("Could not save faces found in {photo}: {err}"),
+            },
+            Err(err) => warn!("Could not detect faces in {photo}: {err}"),
+        }
+        app.emit(
+            "face-detection-progress",
+            FaceDetectionProgress { done, total },
+        )?;
+    }
+    worker.await??;
+    Ok(found)
+}
+
+/// Starts looking for faces in the background. Without a list of photos, every photo that hasn't
+/// been scanned yet is included. Photos that are listed are scanned again, replacing their
+/// unassigned faces. Progress is sent with "face-detection-progress" events, followed by a
+/// "face-detection-finished" event.
+///
+/// Detection is not available in standard builds. The model isn't distributed with the source, so
+/// it has to be downloaded into `models` and the app built with the `face-detection` feature; see
+/// `models/README.md`. Otherwise this returns an error straight away.
+pub async fn detect_faces<R: Runtime>(
+    app: &AppHandle<R>,
+    photos: Option<&Vec<String>>,
+) -> Result<()> {
+    if !cfg!(feature = "face-detection") {
+        return Err(anyhow!(
+            "Face detection is not available in this build; it needs the face-detection feature"
+        ));
+    }
+    let model = app.path().resource_dir()?.join(MODEL);
+    if !model.exists() {
+        return Err(anyhow!(
+            "Face detection model not found at {}",
+            model.display()
+        ));
+    }
+    if DETECTING.swap(true, Ordering::SeqCst) {
+        return Err(anyhow!("Face detection is already running"));
+    }
+
+    let targets = async {
+        ensure_db().await?;
+        let library = PHOTOS.lock().await;
+        let scanned = match photos {
+            Some(_) => HashSet::new(),
+            None => face_scans::table
+                .select(face_scans::photo)
+                .load::<String>(DB.lock().await.as_mut().unwrap())
+                .await?
+                .into_iter()
+                .collect::<HashSet<String>>(),
+        };
+        let mut targets = match photos {
+            Some(photos) => photos
+                .iter()
+                .filter_map(|photo| library.get(photo))
+                .collect::<Vec<_>>(),
+            None => library
+                .values()
+                .filter(|photo| !scanned.contains(&photo.name))
+                .collect::<Vec<_>>(),
+        }
+        .into_iter()
+        .filter(|photo| !photo.is_video())
+        .map(|photo| photo.name.clone())
+        .collect::<Vec<String>>();
+        targets.sort();
+        Ok::<_, anyhow::Error>(targets)
+    }
+    .await;
+    let targets = match targets {
+        Ok(targets) => targets,
+        Err(err) => {
+            DETECTING.store(false, Ordering::SeqCst);
+            return Err(err);
+        }
+    };
+
+    info!("Detecting faces in {} photos", targets.len());
+    let app = app.clone();
+    async_runtime::spawn(async move {
+        let result = run_detection(&app, model, targets).await;
+        DETECTING.store(false, Ordering::SeqCst);
+        let finished = match result {
+            Ok(found) => {
+                info!("Face detection found {found} faces");
+                FaceDetectionFinished { found, error: None }
+            }
+            Err(err) => {
+                warn!("Face detection failed: {err}");
+                FaceDetectionFinished {
+                    found: 0,
+                    error: Some(err.to_string()),
+                }
+            }
+        };
+        if let Err(err) = app.emit("face-detection-finished", finished) {
+            warn!("Could not send face detection results: {err}");
+        }
+    });
+    Ok(())
+}
+
+/// Gets the detected faces that haven't been assigned to anyone, in one photo or all of them.
+pub async fn get_unassigned_faces(photo: Option<&String>) -> Result<Vec<Face>> {
+    ensure_db().await?;
+    let mut query = faces::table.into_boxed();
+    if let Some(photo) = photo {
+        query = query.filter(faces::photo.eq(photo));
+    }
+    Ok(query
+        .order((faces::photo, faces::x))
+        .load::<Face>(DB.lock().await.as_mut().unwrap())
+        .await?)
+}
+
+async fn get_face(face: &String) -> Result<Face> {
+    ensure_db().await?;
+    faces::table
+        .filter(faces::id.eq(face))
+        .load::<Face>(DB.lock().await.as_mut().unwrap())
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Face {face} not found"))
+}
+
+/// Marks a detected face as a person, adding them to the photo if they aren't in it yet. Returns
+/// the person's region in the photo and the photos whose validation result changed.
+pub async fn assign_face(
+    face: &String,
+    person: &String,
+) -> Result<(Region, Vec<ValidationChange>)> {
+    let face = get_face(face).await?;
+    let region = Region {
+        x: face.x,
+        y: face.y,
+        w: face.w,
+        h: face.h,
+    };
+    let mut changes = Vec::new();
+    {
+        let mut photos = PHOTOS.lock().await;
+        let target = photos
+            .get_mut(&face.photo)
+            .ok_or_else(|| anyhow!("Photo {} not found", face.photo))?;
+        if !PEOPLE.lock().await.contains_key(person) {
+            return Err(anyhow!("Person {person} not found"));
+        }
+        let mut people = target.people();
+        if !people.contains(person) {
+            debug!("Adding {person} to {} from a detected face", face.photo);
+            people.push(person.clone());
+            changes = target.set_photo_people(&face.photo, &people).await?;
+        }
+    }
+    write_region(&face.photo, person, &region).await?;
+    dismiss_face(&face.id).await?;
+    Ok((region, changes))
+}
+
+/// Removes a detected face without assigning it. The photo stays scanned, so the face won't come
+/// back unless the photo is scanned again.
+pub async fn dismiss_face(face: &String) -> Result<()> {
+    ensure_db().await?;
+    let deleted = delete(faces::table.filter(faces::id.eq(face)))
+        .execute(DB.lock().await.as_mut().unwrap())
+        .await?;
+    if deleted == 0 {
+        return Err(anyhow!("Face {face} not found"));
+    }
+    Ok(())
+}
@@ -0,0 +1,431 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, NaiveDate};
+use log::debug;
+use serde::Serialize;
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::people::{
+    birthdays::{Birthdate, BirthdatePrecision},
+    create_person,
+    relationships::{add_relationship, get_family_tree, get_relationships, RelationshipKind},
+    PEOPLE,
+};
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+#[derive(Default, Serialize)]
+pub struct GedcomImport {
+    /// People in the file that weren't in the database yet
+    pub created: usize,
+    /// People in the file that were matched to existing people by id or name
+    pub matched: usize,
+    pub relationships: usize,
+}
+
+/// A line of a GEDCOM file: its level, cross-reference id (like @I1@), tag and value
+struct GedcomLine<'a> {
+    level: usize,
+    xref: Option<&'a str>,
+    tag: &'a str,
+    value: &'a str,
+}
+
+fn parse_line(line: &str) -> Option<GedcomLine<'_>> {
+    let line = line.trim_start_matches('\u{feff}').trim();
+    let (level, rest) = line.split_once(' ')?;
+    let level = level.parse::<usize>().ok()?;
+    let (xref, rest) = match rest.strip_prefix('@') {
+        Some(_) => {
+            let (xref, rest) = rest.split_once(' ')?;
+            (Some(xref), rest)
+        }
+        None => (None, rest),
+    };
+    let (tag, value) = rest.split_once(' ').unwrap_or((rest, ""));
+    Some(GedcomLine {
+        level,
+        xref,
+        tag,
+        value,
+    })
+}
+
+/// Converts a GEDCOM date, like "3 MAR 1990", "MAR 1990", "ABT 1990" or "1990", to a birthdate.
+/// Anything less than a full date only keeps the year.
+fn parse_date(value: &str) -> Option<Birthdate> {
+    let parts = value
+        .split_whitespace()
+        .filter(|part| !["ABT", "EST", "CAL", "BEF", "AFT"].contains(&part.to_uppercase().as_str()))
+        .collect::<Vec<&str>>();
+    let year = parts.last()?.parse::<i32>().ok()?;
+    if let [day, month, _] = parts[..] {
+        let month = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(month))? as u32 + 1;
+        if let Some(date) = NaiveDate::from_ymd_opt(year, month, day.parse().ok()?) {
+            return Some(Birthdate {
+                date: date.format("%F").to_string(),
+                precision: BirthdatePrecision::Date,
+            });
+        }
+    }
+    Some(Birthdate {
+        date: format!("{year:04}"),
+        precision: BirthdatePrecision::Year,
+    })
+}
+
+fn format_date(date: &NaiveDate, precision: BirthdatePrecision) -> String {
+    match precision {
+        BirthdatePrecision::Year => format!("{:04}", date.year()),
+        BirthdatePrecision::Date => format!(
+            "{} {} {:04}",
+            date.day(),
+            MONTHS[date.month0() as usize],
+            date.year()
+        ),
+    }
+}
+
+#[derive(Default)]
+struct Individual {
+    name: Option<String>,
+    birthdate: Option<Birthdate>,
+    /// The id the person had when they were exported from Photo Manager
+    refn: Option<String>,
+    /// Other individuals this one is associated with, and how
+    associations: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+struct Family {
+    partners: Vec<String>,
+    children: Vec<String>,
+}
+
+/// Adds the people and family relationships in a GEDCOM file. People are matched to existing
+/// people by the id they were exported with or by name, and anyone new is created in `category`.
+/// Existing birthdates are kept.
+pub async fn import_gedcom(path: &String, category: &str) -> Result<GedcomImport> {
+    let contents = fs::read_to_string(path).await?;
+    let mut individuals = BTreeMap::<String, Individual>::new();
+    let mut families = Vec::<Family>::new();
+    // The record and level 1 tag that the current line belongs to
+    let mut record: Option<(String, &str)> = None;
+    let mut parent_tag = "";
+    for line in contents.lines().filter_map(parse_line) {
+        match line.level {
+            0 => {
+                record = line.xref.map(|xref| (xref.to_owned(), line.tag));
+                match line.tag {
+                    "INDI" => {
+                        individuals.insert(
+                            line.xref.unwrap_or_default().to_owned(),
+                            Individual::default(),
+                        );
+                    }
+                    "FAM" => families.push(Family::default()),
+                    _ => {}
+                }
+            }
+            1 => {
+                parent_tag = line.tag;
+                match (&record, line.tag) {
+                    (Some((xref, "INDI")), "NAME") => {
+                        let individual = individuals.get_mut(xref).unwrap();
+                        if individual.name.is_none() {
+                            // Surnames are marked with slashes, e.g. Alice /Smith/
+                            individual.name = Some(
+                                line.value
+                                    .replace('/', " ")
+                                    .split_whitespace()
+                                    .collect::<Vec<&str>>()
+                                    .join(" "),
+                            );
+                        }
+                    }
+                    (Some((xref, "INDI")), "REFN") => {
+                        individuals.get_mut(xref).unwrap().refn = Some(line.value.to_owned());
+                    }
+                    (Some((xref, "INDI")), "ASSO") => {
+                        individuals
+                            .get_mut(xref)
+                            .unwrap()
+                            .associations
+                            .push((line.value.to_owned(), String::new()));
+                    }
+                    (Some((_, "FAM")), "HUSB" | "WIFE") => {
+                        families
+                            .last_mut()
+                            .unwrap()
+                            .partners
+                            .push(line.value.to_owned());
+                    }
+                    (Some((_, "FAM")), "CHIL") => {
+                        families
+                            .last_mut()
+                            .unwrap()
+                            .children
+                            .push(line.value.to_owned());
+                    }
+                    _ => {}
+                }
+            }
+            2 => match (&record, parent_tag, line.tag) {
+                (Some((xref, "INDI")), "BIRT", "DATE") => {
+                    individuals.get_mut(xref).unwrap().birthdate = parse_date(line.value);
+                }
+                (Some((xref, "INDI")), "ASSO", "RELA") => {
+                    if let Some(association) =
+                        individuals.get_mut(xref).unwrap().associations.last_mut()
+                    {
+                        association.1 = line.value.to_owned();
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    debug!(
+        "Read {} individuals and {} families from {path}",
+        individuals.len(),
+        families.len()
+    );
+
+    let mut result = GedcomImport::default();
+    let mut ids = HashMap::<String, String>::new();
+    for (xref, individual) in &individuals {
+        let Some(name) = &individual.name else {
+            continue;
+        };
+        let existing = {
+            let people = PEOPLE.lock().await;
+            individual
+                .refn
+                .as_ref()
+                .filter(|refn| people.contains_key(*refn))
+                .cloned()
+                .or_else(|| {
+                    people
+                        .values()
+                        .find(|person| person.name.to_uppercase() == name.to_uppercase())
+                        .map(|person| person.id.clone())
+                })
+        };
+        let id = match existing {
+            Some(id) => {
+                result.matched += 1;
+                id
+            }
+            None => {
+                let id = Uuid::new_v4().to_string();
+                create_person(&id, name, category).await?;
+                result.created += 1;
+                id
+            }
+        };
+        if let Some(birthdate) = &individual.birthdate {
+            let mut people = PEOPLE.lock().await;
+            let person = people.get_mut(&id).unwrap();
+            if person.birthdate.is_none() {
+                person
+                    .set_person_birthdate(&id, &Some(birthdate.clone()))
+                    .await?;
+            }
+        }
+        ids.insert(xref.clone(), id);
+    }
+
+    let mut relationships = Vec::<(&String, RelationshipKind, &String)>::new();
+    for family in &families {
+        let partners = family
+            .partners
+            .iter()
+            .filter_map(|p| ids.get(p))
+            .collect::<Vec<_>>();
+        let children = family
+            .children
+            .iter()
+            .filter_map(|c| ids.get(c))
+            .collect::<Vec<_>>();
+        for (i, partner) in partners.iter().enumerate() {
+            for other in &partners[i + 1..] {
+                relationships.push((partner, RelationshipKind::Partner, other));
+            }
+            for child in &children {
+                relationships.push((child, RelationshipKind::Parent, partner));
+            }
+        }
+        for (i, child) in children.iter().enumerate() {
+            for other in &children[i + 1..] {
+                relationships.push((child, RelationshipKind::Sibling, other));
+            }
+        }
+    }
+    for (xref, individual) in &individuals {
+        for (other, relation) in &individual.associations {
+            if let (Some(person), Some(other), Ok(kind)) = (
+                ids.get(xref),
+                ids.get(other),
+                RelationshipKind::from_str(relation),
+            ) {
+                relationships.push((person, kind, other));
+            }
+        }
+    }
+    for (person, kind, related) in relationships {
+        if person != related {
+            add_relationship(person, kind, related).await?;
+            result.relationships += 1;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Writes people and their relationships to a GEDCOM 5.5.1 file. With a person, only their family
+/// tree is written. Since people have no recorded sex, partners are listed as HUSB then WIFE in
+/// alphabetical order, and friends are written as associations.
+pub async fn export_gedcom(path: &String, person: Option<&String>) -> Result<()> {
+    let included = match person {
+        Some(person) => Some(
+            get_family_tree(person, None)
+                .await?
+                .people
+                .into_iter()
+                .map(|member| member.id)
+                .collect::<BTreeSet<String>>(),
+        ),
+        None => None,
+    };
+    let relationships = get_relationships(None)
+        .await?
+        .into_iter()
+        .filter(|r| {
+            included.as_ref().is_none_or(|included| {
+                included.contains(&r.person) && included.contains(&r.related)
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut people = PEOPLE
+        .lock()
+        .await
+        .values()
+        .filter(|p| {
+            included
+                .as_ref()
+                .is_none_or(|included| included.contains(&p.id))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    if people.is_empty() {
+        return Err(anyhow!("There are no people to export"));
+    }
+    people.sort_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+    let xrefs = people
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (p.id.clone(), format!("@I{}@", i + 1)))
+        .collect::<HashMap<String, String>>();
+    let related = |person: &String, kind: RelationshipKind| {
+        relationships
+            .iter()
+            .filter(|r| r.person == *person && r.kind == kind.to_string())
+            .map(|r| r.related.clone())
+            .collect::<BTreeSet<String>>()
+    };
+
+    // Children are grouped into families by their set of parents, and partners without children
+    // get a family of their own. Siblings who share no parents are put in a family without any.
+    let mut families = BTreeMap::<Vec<String>, BTreeSet<String>>::new();
+    for person in &people {
+        let parents = related(&person.id, RelationshipKind::Parent);
+        if !parents.is_empty() {
+            families
+                .entry(parents.into_iter().collect())
+                .or_default()
+                .insert(person.id.clone());
+        }
+    }
+    for person in &people {
+        for partner in related(&person.id, RelationshipKind::Partner) {
+            if person.id < partner
+                && !families
+                    .keys()
+                    .any(|parents| parents.contains(&person.id) && parents.contains(&partner))
+            {
+                families.insert(vec![person.id.clone(), partner], BTreeSet::new());
+            }
+        }
+    }
+    let mut families = families.into_iter().collect::<Vec<_>>();
+    for person in &people {
+        for sibling in related(&person.id, RelationshipKind::Sibling) {
+            if families
+                .iter()
+                .any(|(_, children)| children.contains(&person.id) && children.contains(&sibling))
+            {
+                continue;
+            }
+            match families.iter_mut().find(|(parents, children)| {
+                parents.is_empty() && (children.contains(&person.id) || children.contains(&sibling))
+            }) {
+                Some((_, children)) => {
+                    children.insert(person.id.clone());
+                    children.insert(sibling);
+                }
+                None => families.push((Vec::new(), BTreeSet::from([person.id.clone(), sibling]))),
+            }
+        }
+    }
+
+    let mut lines = vec![
+        "0 HEAD".to_string(),
+        "1 SOUR photo-manager".to_string(),
+        "1 GEDC".to_string(),
+        "2 VERS 5.5.1".to_string(),
+        "2 FORM LINEAGE-LINKED".to_string(),
+        "1 CHAR UTF-8".to_string(),
+    ];
+    for person in &people {
+        lines.push(format!("0 {} INDI", xrefs[&person.id]));
+        lines.push(format!("1 NAME {}", person.name));
+        lines.push(format!("1 REFN {}", person.id));
+        if let Some((date, precision)) = person.birthdate() {
+            lines.push("1 BIRT".to_string());
+            lines.push(format!("2 DATE {}", format_date(&date, precision)));
+        }
+        for (i, (parents, children)) in families.iter().enumerate() {
+            if children.contains(&person.id) {
+                lines.push(format!("1 FAMC @F{}@", i + 1));
+            }
+            if parents.contains(&person.id) {
+                lines.push(format!("1 FAMS @F{}@", i + 1));
+            }
+        }
+        for friend in related(&person.id, RelationshipKind::Friend) {
+            lines.push(format!("1 ASSO {}", xrefs[&friend]));
+            lines.push(format!("2 RELA {}", RelationshipKind::Friend));
+        }
+    }
+    for (i, (parents, children)) in families.iter().enumerate() {
+        lines.push(format!("0 @F{}@ FAM", i + 1));
+        for (tag, parent) in ["HUSB", "WIFE"].iter().zip(parents) {
+            lines.push(format!("1 {tag} {}", xrefs[parent]));
+        }
+        for child in children {
+            lines.push(format!("1 CHIL {}", xrefs[child]));
+        }
+    }
+    lines.push("0 TRLR".to_string());
+
+    fs::write(path, lines.join("\n") + "\n").await?;
+    Ok(())
+}
@@ -4,21 +4,40 @@ use std::{
 };
 use tokio::sync::Mutex as AsyncMutex;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use diesel::{
-    dsl::{insert_into, update},
-    ExpressionMethods, QueryDsl,
+    dsl::{delete, insert_into, insert_or_ignore_into, update},
+    BoolExpressionMethods, ExpressionMethods, QueryDsl,
 };
-use diesel_async::RunQueryDsl;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
 use serde::Serialize;
 
 use crate::{
-    app::{ensure_db, DB},
-    models::{Person, PersonCategory},
-    schema::{people, people_categories},
+    app::{ensure_db, row_to_vec, vec_to_row, DATE_FORMAT, DB},
+    models::{AutotagRule, Person, PersonRelationship},
+    people::{
+        birthdays::{Birthdate, BirthdatePrecision},
+        regions::Region,
+    },
+    photos::{write_photo_people, PHOTOS, VALIDATION_CACHE},
+    schema::{
+        autotag_rules, cameras, people, people_relationships, photo_people, photo_people_regions,
+        photos,
+    },
+    tags::{rules::rule_context, validate_photo_with, TAGS},
 };
 
 pub mod api;
+pub mod birthdays;
+pub mod categories;
+pub mod faces;
+pub mod gedcom;
+pub mod photographers;
+pub mod regions;
+pub mod relationships;
+pub mod stats;
+pub mod suggestions;
+pub mod vcard;
 
 pub static PEOPLE: LazyLock<AsyncMutex<HashMap<String, Person>>> =
     LazyLock::new(|| AsyncMutex::new(HashMap::new()));
@@ -35,6 +54,9 @@ pub async fn create_person(id: &str, name: &str, category: &str) -> Result<()> {
         name: name.to_owned(),
         photo: None,
         category: category.to_owned(),
+        photo_region: None,
+        birthdate: None,
+        birthdate_precision: None,
     };
     insert_into(people::table)
         .values(new_person.clone())
@@ -45,20 +67,6 @@ pub async fn create_person(id: &str, name: &str, category: &str) -> Result<()> {
     Ok(())
 }
 
-pub async fn create_person_category(id: &str, name: &str, color: &str) -> Result<()> {
-    ensure_db().await?;
-    insert_into(people_categories::table)
-        .values(PersonCategory {
-            id: id.to_owned(),
-            name: name.to_owned(),
-            color: color.to_owned(),
-        })
-        .execute(DB.lock().await.as_mut().unwrap())
-        .await?;
-
-    Ok(())
-}
-
 pub async fn get_people() -> Result<Vec<Person>> {
     Ok(PEOPLE
         .lock()
@@ -69,11 +77,247 @@ pub async fn get_people() -> Result<Vec<Person>> {
         .collect::<Vec<Person>>())
 }
 
-pub async fn get_people_categories() -> Result<Vec<PersonCategory>> {
+/// Replaces every reference to a person with another person, or removes the references if there is
+/// no replacement. Photos, photographers, cameras, auto-tagging rules, relationships and the person
+/// itself are rewritten in a single transaction before the caches are updated.
+async fn replace_person(from: &String, into: Option<&String>) -> Result<()> {
     ensure_db().await?;
-    Ok(people_categories::table
-        .load::<PersonCategory>(DB.lock().await.as_mut().unwrap())
-        .await?)
+    let context = rule_context().await;
+    let mut photos_cache = PHOTOS.lock().await;
+    let tags = TAGS.lock().await;
+    let mut people_cache = PEOPLE.lock().await;
+    let removed = people_cache
+        .get(from)
+        .cloned()
+        .ok_or_else(|| anyhow!("Person {from} not found"))?;
+    let target = into.and_then(|into| people_cache.get(into)).cloned();
+
+    let replace = |value: Vec<String>| {
+        let mut result = Vec::<String>::new();
+        for person in value {
+            let person = if person == *from {
+                match into {
+                    Some(into) => into.clone(),
+                    None => continue,
+                }
+            } else {
+                person
+            };
+            if !result.contains(&person) {
+                result.push(person);
+            }
+        }
+        result
+    };
+
+    let photographed = photos_cache
+        .values()
+        .filter(|photo| photo.photographer.as_ref() == Some(from))
+        .map(|photo| photo.name.clone())
+        .collect::<Vec<String>>();
+
+    // The person the photos were merged into picks up the old portrait if they don't have one
+    let portrait = target
+        .as_ref()
+        .filter(|target| target.photo.is_none())
+        .and(removed.photo.clone())
+        .map(|photo| (photo, removed.photo_region.clone()));
+    let portrait = &portrait;
+    let mut conn = DB.lock().await;
+    let conn = conn.as_mut().unwrap();
+    let changed_photos = conn
+        .transaction::<_, anyhow::Error, _>(|conn| {
+            async move {
+                // Regions move to the merged person, unless they already have one in the same photo
+                if let Some(into) = into {
+                    let marked = photo_people_regions::table
+                        .filter(photo_people_regions::person.eq(into))
+                        .select(photo_people_regions::photo)
+                        .load::<String>(conn)
+                        .await?;
+                    update(
+                        photo_people_regions::table.filter(
+                            photo_people_regions::person
+                                .eq(from)
+                                .and(photo_people_regions::photo.ne_all(marked)),
+                        ),
+                    )
+                    .set(photo_people_regions::person.eq(into))
+                    .execute(conn)
+                    .await?;
+                }
+                // Photos whose files are missing aren't cached, but they still need rewriting
+                let named = photo_people::table
+                    .filter(photo_people::person.eq(from))
+                    .select(photo_people::photo)
+                    .load::<String>(conn)
+                    .await?;
+                let mut tagged = Vec::<(String, Vec<String>)>::new();
+                for (photo, person) in photo_people::table
+                    .filter(photo_people::photo.eq_any(&named))
+                    .order((photo_people::photo, photo_people::position))
+                    .select((photo_people::photo, photo_people::person))
+                    .load::<(String, String)>(conn)
+                    .await?
+                {
+                    match tagged.last_mut() {
+                        Some((name, existing)) if *name == photo => existing.push(person),
+                        _ => tagged.push((photo, vec![person])),
+                    }
+                }
+                let mut changed_photos = Vec::<(String, Vec<String>, Vec<String>)>::new();
+                for (name, existing) in tagged {
+                    let value = replace(existing.clone());
+                    write_photo_people(conn, &name, &value).await?;
+                    changed_photos.push((name, existing, value));
+                }
+                update(photos::table.filter(photos::photographer.eq(from)))
+                    .set(photos::photographer.eq(into))
+                    .execute(conn)
+                    .await?;
+                // Cameras are deleted along with the person unless they were merged into someone
+                if let Some(into) = into {
+                    update(cameras::table.filter(cameras::photographer.eq(from)))
+                        .set(cameras::photographer.eq(into))
+                        .execute(conn)
+                        .await?;
+                }
+
+                for rule in autotag_rules::table.load::<AutotagRule>(conn).await? {
+                    let existing = row_to_vec(&rule.people);
+                    if !existing.contains(from) && rule.photographer.as_ref() != Some(from) {
+                        continue;
+                    }
+                    let photographer = if rule.photographer.as_ref() == Some(from) {
+                        into.cloned()
+                    } else {
+                        rule.photographer.clone()
+                    };
+                    update(autotag_rules::table.filter(autotag_rules::id.eq(&rule.id)))
+                        .set((
+                            autotag_rules::people.eq(vec_to_row(&replace(existing))),
+                            autotag_rules::photographer.eq(photographer),
+                        ))
+                        .execute(conn)
+                        .await?;
+                }
+
+                // Relationships move to the merged person too. The rest are deleted along with the
+                // person.
+                if let Some(into) = into {
+                    let relationships = people_relationships::table
+                        .filter(
+                            people_relationships::person
+                                .eq(from)
+                                .or(people_relationships::related.eq(from)),
+                        )
+                        .load::<PersonRelationship>(conn)
+                        .await?;
+                    for relationship in relationships {
+                        let replace =
+                            |id: &String| if id == from { into.clone() } else { id.clone() };
+                        let (person, related) = (
+                            replace(&relationship.person),
+                            replace(&relationship.related),
+                        );
+                        if person != related {
+                            insert_or_ignore_into(people_relationships::table)
+                                .values(PersonRelationship {
+                                    person,
+                                    kind: relationship.kind,
+                                    related,
+                                })
+                                .execute(conn)
+                                .await?;
+                        }
+                    }
+                }
+
+                if let (Some(into), Some((photo, region))) = (into, portrait) {
+                    update(people::table.filter(people::id.eq(into)))
+                        .set((people::photo.eq(photo), people::photo_region.eq(region)))
+                        .execute(conn)
+                        .await?;
+                }
+                delete(people::table.filter(people::id.eq(from)))
+                    .execute(conn)
+                    .await?;
+                Ok(changed_photos)
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+    {
+        let mut people_counts = PEOPLE_COUNTS.lock().unwrap();
+        people_counts.remove(from);
+        if let Some(into) = into {
+            // Counts only cover the photos that are loaded
+            let added = changed_photos
+                .iter()
+                .filter(|(name, existing, _)| {
+                    photos_cache.contains_key(name) && !existing.contains(into)
+                })
+                .count();
+            *people_counts.entry(into.clone()).or_insert(0) += added;
+        }
+    }
+    {
+        let mut photographer_counts = PHOTOGRAPHER_COUNTS.lock().unwrap();
+        photographer_counts.remove(from);
+        if let Some(into) = into {
+            *photographer_counts.entry(into.clone()).or_insert(0) += photographed.len();
+        }
+    }
+
+    for (name, _, value) in &changed_photos {
+        if let Some(photo) = photos_cache.get_mut(name) {
+            photo.people = value.clone();
+        }
+    }
+    for name in &photographed {
+        if let Some(photo) = photos_cache.get_mut(name) {
+            photo.photographer = into.cloned();
+        }
+    }
+    {
+        let mut validation_cache = VALIDATION_CACHE.lock().unwrap();
+        for name in changed_photos
+            .iter()
+            .map(|(name, _, _)| name)
+            .chain(&photographed)
+        {
+            if let Some(photo) = photos_cache.get(name) {
+                validation_cache.insert(name.clone(), validate_photo_with(photo, &tags, &context));
+            }
+        }
+    }
+
+    if let (Some(into), Some((photo, region))) = (into, portrait) {
+        if let Some(person) = people_cache.get_mut(into) {
+            person.photo = Some(photo.clone());
+            person.photo_region = region.clone();
+        }
+    }
+    people_cache.remove(from);
+
+    Ok(())
+}
+
+/// Removes a person from every photo they appear in or took, then deletes them.
+pub async fn delete_person(person: &String) -> Result<()> {
+    replace_person(person, None).await
+}
+
+/// Moves everything about one person onto another existing person, then removes the first.
+pub async fn merge_people(from: &String, into: &String) -> Result<()> {
+    if from == into {
+        return Err(anyhow!("Cannot merge person {from} into themselves"));
+    }
+    if !PEOPLE.lock().await.contains_key(into) {
+        return Err(anyhow!("Person {into} not found"));
+    }
+    replace_person(from, Some(into)).await
 }
 
 impl Person {
@@ -99,17 +343,24 @@ impl Person {
         Ok(())
     }
 
+    /// Sets the photo used as a person's avatar, optionally cropped to a region of it.
     pub async fn set_person_photo(
         &mut self,
         person: &String,
         value: &Option<String>,
+        region: &Option<Region>,
     ) -> Result<()> {
+        if let Some(region) = region {
+            region.check()?;
+        }
         ensure_db().await?;
+        let region = value.as_ref().and(region.map(Region::to_row));
         update(people::table.filter(people::id.eq(person)))
-            .set(people::photo.eq(value))
+            .set((people::photo.eq(value), people::photo_region.eq(&region)))
             .execute(DB.lock().await.as_mut().unwrap())
             .await?;
         self.photo = value.clone();
+        self.photo_region = region;
 
         Ok(())
     }
@@ -121,6 +372,9 @@ pub struct PersonDto {
     pub name: String,
     pub photo: Option<String>,
     pub category: String,
+    /// The part of the photo to crop the avatar to
+    pub photo_region: Option<Region>,
+    pub birthdate: Option<Birthdate>,
     pub count: usize,
     pub photographer_count: usize,
 }
@@ -134,6 +388,15 @@ impl From<&Person> for PersonDto {
             name: value.name.clone(),
             photo: value.photo.clone(),
             category: value.category.clone(),
+            photo_region: Region::from_row(&value.photo_region),
+            birthdate: value.birthdate().map(|(date, precision)| Birthdate {
+                date: match precision {
+                    BirthdatePrecision::Year => date.format("%Y"),
+                    BirthdatePrecision::Date => date.format(DATE_FORMAT),
+                }
+                .to_string(),
+                precision,
+            }),
             count: counts_cache.get(&value.id).copied().unwrap_or(0),
             photographer_count: photographer_cache.get(&value.id).copied().unwrap_or(0),
         }
@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Result};
+use diesel::{
+    dsl::{delete, insert_into, update},
+    ExpressionMethods, QueryDsl,
+};
+use diesel_async::RunQueryDsl;
+use log::debug;
+use serde::Serialize;
+
+use crate::{
+    app::{ensure_db, DB},
+    models::Camera,
+    people::PEOPLE,
+    photos::PHOTOS,
+    schema::{autotag_rules, cameras},
+    tags::ValidationChange,
+};
+
+/// A photographer name on photos that doesn't match anyone in the database.
+#[derive(Serialize)]
+pub struct UnresolvedPhotographer {
+    pub name: String,
+    /// The number of photos with the name
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct CameraDto {
+    pub serial: String,
+    /// The person photos from the camera are attributed to by default
+    pub photographer: Option<String>,
+    /// The number of photos taken with the camera
+    pub photos: usize,
+}
+
+/// Lists the photographer names that haven't been matched to a person, most used first.
+pub async fn get_unresolved_photographers() -> Result<Vec<UnresolvedPhotographer>> {
+    let mut counts = HashMap::<String, usize>::new();
+    for photo in PHOTOS.lock().await.values() {
+        if let Some(name) = &photo.photographer_name {
+            *counts.entry(name.clone()).or_insert(0) += 1;
+        }
+    }
+    let mut unresolved = counts
+        .into_iter()
+        .map(|(name, count)| UnresolvedPhotographer { name, count })
+        .collect::<Vec<UnresolvedPhotographer>>();
+    unresolved.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    Ok(unresolved)
+}
+
+/// Attributes every photo with an unresolved photographer name to a person, along with any
+/// auto-tagging rules that use the name. Returns the number of photos changed and the photos whose
+/// validation result changed.
+pub async fn resolve_photographer(
+    name: &str,
+    person: &String,
+) -> Result<(usize, Vec<ValidationChange>)> {
+    if !PEOPLE.lock().await.contains_key(person) {
+        return Err(anyhow!("Person {person} not found"));
+    }
+    ensure_db().await?;
+    let mut photos = PHOTOS.lock().await;
+    let targets = photos
+        .values()
+        .filter(|photo| photo.photographer_name.as_deref() == Some(name))
+        .map(|photo| photo.name.clone())
+        .collect::<Vec<String>>();
+    let mut changes = Vec::new();
+    for target in &targets {
+        if let Some(photo) = photos.get_mut(target) {
+            changes.extend(
+                photo
+                    .set_photographer(target, &Some(person.clone()))
+                    .await?,
+            );
+        }
+    }
+    drop(photos);
+
+    update(autotag_rules::table.filter(autotag_rules::photographer.eq(name)))
+        .set(autotag_rules::photographer.eq(person))
+        .execute(DB.lock().await.as_mut().unwrap())
+        .await?;
+    Ok((targets.len(), changes))
+}
+
+/// Lists the cameras that took photos in the library or have a default photographer.
+pub async fn get_cameras() -> Result<Vec<CameraDto>> {
+    ensure_db().await?;
+    let mut result = BTreeMap::<String, CameraDto>::new();
+    for photo in PHOTOS.lock().await.values() {
+        if let Some(serial) = &photo.camera_serial {
+            result
+                .entry(serial.clone())
+                .or_insert_with(|| CameraDto {
+                    serial: serial.clone(),
+                    photographer: None,
+                    photos: 0,
+                })
+                .photos += 1;
+        }
+    }
+    let rows = cameras::table
+        .load::<Camera>(DB.lock().await.as_mut().unwrap())
+        .await?;
+    for row in rows {
+        result
+            .entry(row.serial.clone())
+            .or_insert_with(|| CameraDto {
+                serial: row.serial.clone(),
+                photographer: None,
+                photos: 0,
+            })
+            .photographer = Some(row.photographer);
+    }
+    Ok(result.into_values().collect())
+}
+
+/// Sets or clears the default photographer for a camera body. When `apply` is set, photos already
+/// taken with the camera that have no photographer get the new one. Returns the number of photos
+/// changed and the photos whose validation result changed.
+pub async fn set_camera_photographer(
+    serial: &String,
+    person: Option<&String>,
+    apply: bool,
+) -> Result<(usize, Vec<ValidationChange>)> {
+    ensure_db().await?;
+    match person {
+        Some(person) => {
+            if !PEOPLE.lock().await.contains_key(person) {
+                return Err(anyhow!("Person {person} not found"));
+            }
+            insert_into(cameras::table)
+                .values(Camera {
+                    serial: serial.clone(),
+                    photographer: person.clone(),
+                })
+                .on_conflict(cameras::serial)
+                .do_update()
+                .set(cameras::photographer.eq(person))
+                .execute(DB.lock().await.as_mut().unwrap())
+                .await?;
+        }
+        None => {
+            delete(cameras::table.filter(cameras::serial.eq(serial)))
+                .execute(DB.lock().await.as_mut().unwrap())
+                .await?;
+        }
+    }
+
+    let Some(person) = person.filter(|_| apply) else {
+        return Ok((0, Vec::new()));
+    };
+    let mut photos = PHOTOS.lock().await;
+    let targets = photos
+        .values()
+        .filter(|photo| {
+            photo.camera_serial.as_ref() == Some(serial) && photo.photographer.is_none()
+        })
+        .map(|photo| photo.name.clone())
+        .collect::<Vec<String>>();
+    let mut changes = Vec::new();
+    for target in &targets {
+        if let Some(photo) = photos.get_mut(target) {
+            changes.extend(
+                photo
+                    .set_photographer(target, &Some(person.clone()))
+                    .await?,
+            );
+        }
+    }
+    Ok((targets.len(), changes))
+}
+
+/// Fills in the photographer of newly loaded photos from the default of the camera that took
+/// them. Photos that already have a photographer are left alone. Returns the photos whose
+/// validation result changed.
+pub async fn apply_camera_photographers(new_photos: &[String]) -> Result<Vec<ValidationChange>> {
+    ensure_db().await?;
+    let defaults = cameras::table
+        .load::<Camera>(DB.lock().await.as_mut().unwrap())
+        .await?
+        .into_iter()
+        .map(|camera| (camera.serial, camera.photographer))
+        .collect::<HashMap<String, String>>();
+    if defaults.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut photos = PHOTOS.lock().await;
+    let mut changes = Vec::new();
+    for name in new_photos {
+        let Some(photo) = photos.get_mut(name) else {
+            continue;
+        };
+        if photo.photographer.is_some() {
+            continue;
+        }
+        let Some(photographer) = photo
+            .camera_serial
+            .as_ref()
+            .and_then(|serial| defaults.get(serial))
+        else {
+            continue;
+        };
+        debug!("Setting photographer of {name} to {photographer} from its camera");
+        let photographer = Some(photographer.clone());
+        changes.extend(photo.set_photographer(name, &photographer).await?);
+    }
+    Ok(changes)
+}
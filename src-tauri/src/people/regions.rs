@@ -0,0 +1,443 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use diesel::{
+    dsl::{delete, insert_into},
+    BoolExpressionMethods, ExpressionMethods, QueryDsl,
+};
+use diesel_async::RunQueryDsl;
+use exif::In;
+use log::debug;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    app::{ensure_db, DB},
+    models::{Person, PhotoPeopleRegion},
+    people::PEOPLE,
+    photos::PHOTOS,
+    schema::photo_people_regions,
+    tags::ValidationChange,
+};
+
+/// A rectangle on a photo, normalized to the photo's size and measured from its top left corner.
+#[derive(Clone, Copy, Deserialize, PartialEq, Serialize)]
+pub struct Region {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Region {
+    /// Checks that the region is not empty and lies entirely within the photo.
+    pub fn check(&self) -> Result<()> {
+        // Allow for rounding in regions written by other tools
+        let within = |start: f32, size: f32| {
+            start >= 0.0 && size > 0.0 && start + size <= 1.0 + f32::EPSILON * 4.0
+        };
+        if !within(self.x, self.w) || !within(self.y, self.h) {
+            return Err(anyhow!("Region {} is not within the photo", self.to_row()));
+        }
+        Ok(())
+    }
+
+    pub fn to_row(self) -> String {
+        format!("{},{},{},{}", self.x, self.y, self.w, self.h)
+    }
+
+    pub fn from_row(row: &Option<String>) -> Option<Self> {
+        let values = row
+            .as_ref()?
+            .split(',')
+            .map(|value| value.parse::<f32>().ok())
+            .collect::<Option<Vec<f32>>>()?;
+        match values[..] {
+            [x, y, w, h] => Some(Self { x, y, w, h }),
+            _ => None,
+        }
+    }
+}
+
+/// The region around a person in a photo.
+#[derive(Serialize)]
+pub struct PersonRegion {
+    pub person: String,
+    pub region: Region,
+}
+
+impl From<&PhotoPeopleRegion> for PersonRegion {
+    fn from(value: &PhotoPeopleRegion) -> Self {
+        Self {
+            person: value.person.clone(),
+            region: Region {
+                x: value.x,
+                y: value.y,
+                w: value.w,
+                h: value.h,
+            },
+        }
+    }
+}
+
+/// The result of reading the regions saved in a photo's XMP metadata.
+#[derive(Serialize)]
+pub struct RegionImport {
+    pub imported: Vec<PersonRegion>,
+    /// Names from the metadata that don't match anyone
+    pub unmatched: Vec<String>,
+}
+
+pub async fn get_photo_regions(photo: &String) -> Result<Vec<PhotoPeopleRegion>> {
+    ensure_db().await?;
+    Ok(photo_people_regions::table
+        .filter(photo_people_regions::photo.eq(photo))
+        .order(photo_people_regions::person)
+        .load::<PhotoPeopleRegion>(DB.lock().await.as_mut().unwrap())
+        .await?)
+}
+
+pub(crate) async fn write_region(photo: &str, person: &str, region: &Region) -> Result<()> {
+    let row = PhotoPeopleRegion {
+        photo: photo.to_owned(),
+        person: person.to_owned(),
+        x: region.x,
+        y: region.y,
+        w: region.w,
+        h: region.h,
+    };
+    insert_into(photo_people_regions::table)
+        .values(row.clone())
+        .on_conflict((photo_people_regions::photo, photo_people_regions::person))
+        .do_update()
+        .set((
+            photo_people_regions::x.eq(row.x),
+            photo_people_regions::y.eq(row.y),
+            photo_people_regions::w.eq(row.w),
+            photo_people_regions::h.eq(row.h),
+        ))
+        .execute(DB.lock().await.as_mut().unwrap())
+        .await?;
+    Ok(())
+}
+
+/// Marks where a person is in a photo. The person has to be in the photo already.
+pub async fn set_photo_region(photo: &String, person: &String, region: &Region) -> Result<()> {
+    region.check()?;
+    ensure_db().await?;
+    {
+        let photos = PHOTOS.lock().await;
+        let target = photos
+            .get(photo)
+            .ok_or_else(|| anyhow!("Photo {photo} not found"))?;
+        if !target.people.contains(person) {
+            return Err(anyhow!("Person {person} is not in photo {photo}"));
+        }
+    }
+    write_region(photo, person, region).await
+}
+
+/// Removes the region of one person in a photo, or of everyone if no person is given.
+pub async fn clear_photo_regions(photo: &String, person: Option<&String>) -> Result<()> {
+    ensure_db().await?;
+    let mut conn = DB.lock().await;
+    let conn = conn.as_mut().unwrap();
+    match person {
+        Some(person) => {
+            delete(
+                photo_people_regions::table.filter(
+                    photo_people_regions::photo
+                        .eq(photo)
+                        .and(photo_people_regions::person.eq(person)),
+                ),
+            )
+            .execute(conn)
+            .await?
+        }
+        None => {
+            delete(photo_people_regions::table.filter(photo_people_regions::photo.eq(photo)))
+                .execute(conn)
+                .await?
+        }
+    };
+    Ok(())
+}
+
+/// The sidecar files that may hold a photo's XMP metadata: IMG_1234.xmp, as written by most
+/// editors, or IMG_1234.jpg.xmp.
+fn sidecar_paths(photo: &str) -> [PathBuf; 2] {
+    [
+        Path::new(photo).with_extension("xmp"),
+        PathBuf::from(format!("{photo}.xmp")),
+    ]
+}
+
+/// How far into a photo to look for embedded XMP metadata, which is normally near the start
+const EMBEDDED_XMP_LIMIT: u64 = 1 << 20;
+
+/// Reads a photo's XMP metadata from a sidecar file, or from the packet embedded in the photo.
+async fn read_xmp(photo: &str) -> Result<Option<String>> {
+    for path in sidecar_paths(photo) {
+        if path.exists() {
+            return Ok(Some(fs::read_to_string(path).await?));
+        }
+    }
+    let mut bytes = Vec::new();
+    File::open(photo)?
+        .take(EMBEDDED_XMP_LIMIT)
+        .read_to_end(&mut bytes)?;
+    let find = |haystack: &[u8], needle: &[u8]| {
+        haystack
+            .windows(needle.len())
+            .position(|window| window == needle)
+    };
+    let Some(start) = find(&bytes, b"<x:xmpmeta") else {
+        return Ok(None);
+    };
+    let end_tag = b"</x:xmpmeta>";
+    Ok(find(&bytes[start..], end_tag).map(|end| {
+        String::from_utf8_lossy(&bytes[start..start + end + end_tag.len()]).into_owned()
+    }))
+}
+
+fn unescape_xml(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Gets a property from an XMP block, which may be written either as an attribute or as an
+/// element.
+fn xmp_property(block: &str, name: &str) -> Option<String> {
+    let name = regex::escape(name);
+    let pattern = Regex::new(&format!(
+        r#"(?s){name}\s*=\s*(?:"([^"]*)"|'([^']*)')|<{name}>([^<]*)</{name}>"#
+    ))
+    .unwrap();
+    let captures = pattern.captures(block)?;
+    (1..=3)
+        .find_map(|i| captures.get(i))
+        .map(|value| unescape_xml(value.as_str().trim()))
+}
+
+/// Reads the named regions from XMP metadata in the Metadata Working Group layout. Areas there are
+/// measured from the center of the region, so they are converted to start at its top left corner.
+fn parse_regions(xmp: &str) -> Vec<(String, Region)> {
+    let list = Regex::new(r"(?s)<mwg-rs:RegionList\b.*?</mwg-rs:RegionList>").unwrap();
+    let item = Regex::new(r"(?s)<rdf:li\b.*?</rdf:li>").unwrap();
+    let mut regions = Vec::new();
+    for list in list.find_iter(xmp) {
+        for item in item.find_iter(list.as_str()) {
+            let block = item.as_str();
+            let Some(name) = xmp_property(block, "mwg-rs:Name").filter(|n| !n.is_empty()) else {
+                continue;
+            };
+            if xmp_property(block, "stArea:unit").is_some_and(|unit| unit != "normalized") {
+                continue;
+            }
+            let area = ["stArea:x", "stArea:y", "stArea:w", "stArea:h"]
+                .map(|field| xmp_property(block, field).and_then(|v| v.parse::<f32>().ok()));
+            if let [Some(cx), Some(cy), Some(w), Some(h)] = area {
+                let region = Region {
+                    x: (cx - w / 2.0).max(0.0),
+                    y: (cy - h / 2.0).max(0.0),
+                    w,
+                    h,
+                };
+                if region.check().is_ok() {
+                    regions.push((name, region));
+                }
+            }
+        }
+    }
+    regions
+}
+
+/// Gets the size of a photo in pixels from its EXIF data, if it has any.
+fn photo_dimensions(photo: &str) -> Option<(u32, u32)> {
+    let file = File::open(photo).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+    let dimension = |tag: exif::Tag| exif.get_field(tag, In::PRIMARY)?.value.get_uint(0);
+    Some((
+        dimension(exif::Tag::PixelXDimension)?,
+        dimension(exif::Tag::PixelYDimension)?,
+    ))
+}
+
+/// Writes regions as an MWG Regions XMP element.
+fn regions_element(regions: &[(String, Region)], dimensions: Option<(u32, u32)>) -> String {
+    // The namespaces are declared on the element itself so it can be added to any description
+    let mut element =
+        format!("<mwg-rs:Regions {REGION_NAMESPACES}\n    rdf:parseType=\"Resource\">\n");
+    if let Some((w, h)) = dimensions {
+        element.push_str(&format!(
+            "    <mwg-rs:AppliedToDimensions stDim:w=\"{w}\" stDim:h=\"{h}\" stDim:unit=\"pixel\"/>\n"
+        ));
+    }
+    element.push_str("    <mwg-rs:RegionList>\n     <rdf:Bag>\n");
+    for (name, region) in regions {
+        element.push_str(&format!(
+            concat!(
+                "      <rdf:li>\n",
+                "       <rdf:Description mwg-rs:Name=\"{}\" mwg-rs:Type=\"Face\">\n",
+                "        <mwg-rs:Area stArea:x=\"{}\" stArea:y=\"{}\" stArea:w=\"{}\" stArea:h=\"{}\" stArea:unit=\"normalized\"/>\n",
+                "       </rdf:Description>\n",
+                "      </rdf:li>\n",
+            ),
+            escape_xml(name),
+            region.x + region.w / 2.0,
+            region.y + region.h / 2.0,
+            region.w,
+            region.h,
+        ));
+    }
+    element.push_str("     </rdf:Bag>\n    </mwg-rs:RegionList>\n   </mwg-rs:Regions>");
+    element
+}
+
+const REGION_NAMESPACES: &str = concat!(
+    "xmlns:mwg-rs=\"http://www.metadataworkinggroup.com/schemas/regions/\"\n",
+    "    xmlns:stArea=\"http://ns.adobe.com/xmp/sType/Area#\"\n",
+    "    xmlns:stDim=\"http://ns.adobe.com/xap/1.0/sType/Dimensions#\"",
+);
+
+/// Adds the regions in a photo's XMP metadata to the photo. People are matched by id or name, and
+/// anyone matched who isn't in the photo yet is added to it. Also returns the photos whose
+/// validation result changed.
+pub async fn import_photo_regions(photo: &String) -> Result<(RegionImport, Vec<ValidationChange>)> {
+    let Some(xmp) = read_xmp(photo).await? else {
+        return Err(anyhow!("Photo {photo} has no XMP metadata"));
+    };
+    let parsed = parse_regions(&xmp);
+    debug!("Found {} regions in the metadata of {photo}", parsed.len());
+
+    let mut photos = PHOTOS.lock().await;
+    let target = photos
+        .get_mut(photo)
+        .ok_or_else(|| anyhow!("Photo {photo} not found"))?;
+    let mut matched = Vec::<(String, Region)>::new();
+    let mut unmatched = Vec::<String>::new();
+    {
+        let people = PEOPLE.lock().await;
+        let find = |name: &String| -> Option<&Person> {
+            people.get(name).or_else(|| {
+                people
+                    .values()
+                    .find(|person| person.name.to_uppercase() == name.to_uppercase())
+            })
+        };
+        for (name, region) in parsed {
+            match find(&name) {
+                Some(person) => matched.push((person.id.clone(), region)),
+                None => unmatched.push(name),
+            }
+        }
+    }
+
+    let mut people = target.people();
+    for (person, _) in &matched {
+        if !people.contains(person) {
+            people.push(person.clone());
+        }
+    }
+    let changes = if people != target.people {
+        target.set_photo_people(photo, &people).await?
+    } else {
+        Vec::new()
+    };
+    drop(photos);
+
+    let mut imported = Vec::new();
+    for (person, region) in matched {
+        write_region(photo, &person, &region).await?;
+        imported.push(PersonRegion { person, region });
+    }
+    Ok((
+        RegionImport {
+            imported,
+            unmatched,
+        },
+        changes,
+    ))
+}
+
+/// Saves a photo's regions to its XMP sidecar file, keeping any other metadata already in it.
+/// Returns the path of the sidecar file.
+pub async fn export_photo_regions(photo: &String) -> Result<String> {
+    if !PHOTOS.lock().await.contains_key(photo) {
+        return Err(anyhow!("Photo {photo} not found"));
+    }
+    let regions = {
+        let rows = get_photo_regions(photo).await?;
+        let people = PEOPLE.lock().await;
+        rows.iter()
+            .map(|row| {
+                let name = people
+                    .get(&row.person)
+                    .map(|person| person.name.clone())
+                    .unwrap_or_else(|| row.person.clone());
+                (name, PersonRegion::from(row).region)
+            })
+            .collect::<Vec<(String, Region)>>()
+    };
+    let element = regions_element(&regions, photo_dimensions(photo));
+
+    let [default_path, alternate_path] = sidecar_paths(photo);
+    let path = if !default_path.exists() && alternate_path.exists() {
+        alternate_path
+    } else {
+        default_path
+    };
+    let existing_regions = Regex::new(r"(?s)<mwg-rs:Regions\b.*?</mwg-rs:Regions>").unwrap();
+    let xmp = if path.exists() {
+        let existing = fs::read_to_string(&path).await?;
+        if existing_regions.is_match(&existing) {
+            existing_regions
+                .replace(&existing, regex::NoExpand(&element))
+                .into_owned()
+        } else if let Some(end) = existing.find("</rdf:RDF>") {
+            format!(
+                "{}  <rdf:Description rdf:about=\"\">\n   {element}\n  </rdf:Description>\n {}",
+                &existing[..end],
+                &existing[end..]
+            )
+        } else {
+            return Err(anyhow!("Could not read XMP file {}", path.display()));
+        }
+    } else {
+        format!(
+            concat!(
+                "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+                "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+                " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+                "  <rdf:Description rdf:about=\"\">\n",
+                "   {}\n",
+                "  </rdf:Description>\n",
+                " </rdf:RDF>\n",
+                "</x:xmpmeta>\n",
+                "<?xpacket end=\"w\"?>\n",
+            ),
+            element
+        )
+    };
+    fs::write(&path, xmp).await?;
+    Ok(path.to_string_lossy().into_owned())
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Result};
+use diesel::{
+    dsl::{delete, insert_or_ignore_into},
+    BoolExpressionMethods, ExpressionMethods, QueryDsl,
+};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
+
+use crate::{
+    app::{ensure_db, DB},
+    models::PersonRelationship,
+    people::PEOPLE,
+    schema::people_relationships,
+};
+
+#[derive(
+    Clone, Copy, Debug, Deserialize, Display, EnumIter, EnumString, Eq, Hash, PartialEq, Serialize,
+)]
+pub enum RelationshipKind {
+    #[strum(ascii_case_insensitive)]
+    Parent,
+    #[strum(ascii_case_insensitive)]
+    Child,
+    #[strum(ascii_case_insensitive)]
+    Sibling,
+    #[strum(ascii_case_insensitive)]
+    Partner,
+    #[strum(ascii_case_insensitive)]
+    Friend,
+}
+
+impl RelationshipKind {
+    /// The relationship seen from the other person's side.
+    pub fn inverse(self) -> Self {
+        match self {
+            Self::Parent => Self::Child,
+            Self::Child => Self::Parent,
+            kind => kind,
+        }
+    }
+
+    /// Whether the relationship makes someone part of the family, which friends aren't.
+    pub fn is_family(self) -> bool {
+        self != Self::Friend
+    }
+
+    /// The plural used in search terms, e.g. "children" in children-of:alice
+    fn plural(self) -> &'static str {
+        match self {
+            Self::Parent => "parents",
+            Self::Child => "children",
+            Self::Sibling => "siblings",
+            Self::Partner => "partners",
+            Self::Friend => "friends",
+        }
+    }
+}
+
+/// Splits a person search term that follows relationships, like children-of:alice or
+/// family-of:bob, into the kinds of relationship and the person they start from. Terms can be
+/// nested, e.g. children-of:children-of:alice for Alice's grandchildren.
+pub fn parse_relative_term(term: &str) -> Option<(Vec<RelationshipKind>, &str)> {
+    let index = term.to_ascii_lowercase().find("-of:")?;
+    let (prefix, person) = (term[..index].to_ascii_lowercase(), &term[index + 4..]);
+    if prefix == "family" {
+        return Some((
+            RelationshipKind::iter()
+                .filter(|kind| kind.is_family())
+                .collect(),
+            person,
+        ));
+    }
+    RelationshipKind::iter()
+        .find(|kind| kind.plural() == prefix)
+        .map(|kind| (vec![kind], person))
+}
+
+/// Gets the people who are any of the given kinds of relative to any of the given people.
+pub fn relatives(
+    relationships: &[PersonRelationship],
+    people: &[String],
+    kinds: &[RelationshipKind],
+) -> Vec<String> {
+    let kinds = kinds
+        .iter()
+        .map(|kind| kind.to_string())
+        .collect::<Vec<_>>();
+    let mut result = Vec::<String>::new();
+    for relationship in relationships {
+        if people.contains(&relationship.person)
+            && kinds.contains(&relationship.kind)
+            && !people.contains(&relationship.related)
+            && !result.contains(&relationship.related)
+        {
+            result.push(relationship.related.clone());
+        }
+    }
+    result
+}
+
+/// Gets every relationship, or the relationships of one person.
+pub async fn get_relationships(person: Option<&String>) -> Result<Vec<PersonRelationship>> {
+    ensure_db().await?;
+    let mut query = people_relationships::table.into_boxed();
+    if let Some(person) = person {
+        query = query.filter(people_relationships::person.eq(person));
+    }
+    Ok(query
+        .order((
+            people_relationships::person,
+            people_relationships::kind,
+            people_relationships::related,
+        ))
+        .load::<PersonRelationship>(DB.lock().await.as_mut().unwrap())
+        .await?)
+}
+
+/// Records that `related` is `person`'s `kind`, along with the inverse relationship.
+pub async fn add_relationship(
+    person: &String,
+    kind: RelationshipKind,
+    related: &String,
+) -> Result<()> {
+    if person == related {
+        return Err(anyhow!("Person {person} cannot be related to themselves"));
+    }
+    {
+        let people = PEOPLE.lock().await;
+        for id in [person, related] {
+            if !people.contains_key(id) {
+                return Err(anyhow!("Person {id} not found"));
+            }
+        }
+    }
+    ensure_db().await?;
+    let mut conn = DB.lock().await;
+    let conn = conn.as_mut().unwrap();
+    conn.transaction::<_, anyhow::Error, _>(|conn| {
+        async move {
+            for (a, kind, b) in [(person, kind, related), (related, kind.inverse(), person)] {
+                insert_or_ignore_into(people_relationships::table)
+                    .values((
+                        people_relationships::person.eq(a),
+                        people_relationships::kind.eq(kind.to_string()),
+                        people_relationships::related.eq(b),
+                    ))
+                    .execute(conn)
+                    .await?;
+            }
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Removes a relationship from both sides.
+pub async fn remove_relationship(
+    person: &String,
+    kind: RelationshipKind,
+    related: &String,
+) -> Result<()> {
+    ensure_db().await?;
+    let mut conn = DB.lock().await;
+    let conn = conn.as_mut().unwrap();
+    conn.transaction::<_, anyhow::Error, _>(|conn| {
+        async move {
+            for (a, kind, b) in [(person, kind, related), (related, kind.inverse(), person)] {
+                delete(
+                    people_relationships::table.filter(
+                        people_relationships::person
+                            .eq(a)
+                            .and(people_relationships::kind.eq(kind.to_string()))
+                            .and(people_relationships::related.eq(b)),
+                    ),
+                )
+                .execute(conn)
+                .await?;
+            }
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct FamilyMember {
+    pub id: String,
+    pub name: String,
+    /// Generations relative to the person the tree is for: parents are -1, children 1
+    pub generation: i32,
+}
+
+#[derive(Serialize)]
+pub struct FamilyTree {
+    pub people: Vec<FamilyMember>,
+    /// The family relationships between the people in the tree. Parent relationships only appear
+    /// from the child's side, and symmetric ones once per pair.
+    pub relationships: Vec<PersonRelationship>,
+}
+
+/// Gets everyone connected to a person through family relationships, up to `depth` steps away.
+pub async fn get_family_tree(person: &String, depth: Option<usize>) -> Result<FamilyTree> {
+    let names = PEOPLE
+        .lock()
+        .await
+        .values()
+        .map(|person| (person.id.clone(), person.name.clone()))
+        .collect::<HashMap<String, String>>();
+    if !names.contains_key(person) {
+        return Err(anyhow!("Person {person} not found"));
+    }
+    let relationships = get_relationships(None)
+        .await?
+        .into_iter()
+        .filter(|relationship| {
+            RelationshipKind::from_str(&relationship.kind).is_ok_and(|kind| kind.is_family())
+        })
+        .collect::<Vec<PersonRelationship>>();
+
+    let mut generations = HashMap::<String, i32>::from([(person.clone(), 0)]);
+    let mut queue = VecDeque::from([(person.clone(), 0)]);
+    while let Some((current, steps)) = queue.pop_front() {
+        if depth.is_some_and(|depth| steps >= depth) {
+            continue;
+        }
+        let generation = generations[&current];
+        for relationship in relationships.iter().filter(|r| r.person == current) {
+            if generations.contains_key(&relationship.related) {
+                continue;
+            }
+            let offset = match RelationshipKind::from_str(&relationship.kind) {
+                Ok(RelationshipKind::Parent) => -1,
+                Ok(RelationshipKind::Child) => 1,
+                _ => 0,
+            };
+            generations.insert(relationship.related.clone(), generation + offset);
+            queue.push_back((relationship.related.clone(), steps + 1));
+        }
+    }
+
+    let members = generations.keys().cloned().collect::<HashSet<String>>();
+    let mut people = generations
+        .into_iter()
+        .map(|(id, generation)| FamilyMember {
+            name: names.get(&id).cloned().unwrap_or(id.clone()),
+            id,
+            generation,
+        })
+        .collect::<Vec<FamilyMember>>();
+    people.sort_by(|a, b| (a.generation, &a.name).cmp(&(b.generation, &b.name)));
+    Ok(FamilyTree {
+        people,
+        relationships: relationships
+            .into_iter()
+            .filter(|r| members.contains(&r.person) && members.contains(&r.related))
+            .filter(|r| {
+                r.kind != RelationshipKind::Child.to_string()
+                    && (r.kind == RelationshipKind::Parent.to_string() || r.person < r.related)
+            })
+            .collect(),
+    })
+}
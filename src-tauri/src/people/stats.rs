@@ -0,0 +1,179 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, NaiveDate};
+use serde::Serialize;
+
+use crate::{
+    app::search::{search_photos, SortOrder},
+    models::Photo,
+    people::PEOPLE,
+    photos::PHOTOS,
+    places::PLACES,
+};
+
+#[derive(Serialize)]
+pub struct NamedCount {
+    pub id: String,
+    pub name: String,
+    pub count: usize,
+}
+
+/// Two people who appear in the same photos. With a person, `a` is always that person.
+#[derive(Serialize)]
+pub struct PeoplePair {
+    pub a: String,
+    pub b: String,
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct YearCount {
+    pub year: i32,
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct PeopleStats {
+    /// The number of photos the statistics were computed from
+    pub photos: usize,
+    pub first_date: Option<NaiveDate>,
+    pub last_date: Option<NaiveDate>,
+    pub per_year: Vec<YearCount>,
+    pub pairs: Vec<PeoplePair>,
+    pub places: Vec<NamedCount>,
+    pub photographers: Vec<NamedCount>,
+}
+
+/// Sorts counts from highest to lowest, keeping the first `limit`.
+fn top(
+    counts: HashMap<String, usize>,
+    names: &HashMap<String, String>,
+    limit: usize,
+) -> Vec<NamedCount> {
+    let mut result = counts
+        .into_iter()
+        .map(|(id, count)| NamedCount {
+            name: names.get(&id).cloned().unwrap_or(id.clone()),
+            id,
+            count,
+        })
+        .collect::<Vec<NamedCount>>();
+    result.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    result.truncate(limit);
+    result
+}
+
+/// Gets who appears with whom, when, where, and who took the photos, for the photos of one person
+/// or for everyone. Photos can be limited with a search query first. Pairs, places and
+/// photographers are limited to the `limit` most common.
+pub async fn get_people_stats(
+    person: Option<&String>,
+    query: Option<&Vec<String>>,
+    limit: usize,
+) -> Result<PeopleStats> {
+    let photos = match query {
+        Some(query) => {
+            search_photos(query, SortOrder::from_str("name")?)
+                .await?
+                .photos
+        }
+        None => PHOTOS
+            .lock()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<Photo>>(),
+    };
+    let people_names = PEOPLE
+        .lock()
+        .await
+        .values()
+        .map(|person| (person.id.clone(), person.name.clone()))
+        .collect::<HashMap<String, String>>();
+    if let Some(person) = person {
+        if !people_names.contains_key(person) {
+            return Err(anyhow!("Person {person} not found"));
+        }
+    }
+    let place_names = PLACES
+        .lock()
+        .await
+        .values()
+        .map(|place| (place.id.clone(), place.name.clone()))
+        .collect::<HashMap<String, String>>();
+
+    let mut count = 0;
+    let mut dates = Vec::<NaiveDate>::new();
+    let mut pairs = HashMap::<(String, String), usize>::new();
+    let mut places = HashMap::<String, usize>::new();
+    let mut photographers = HashMap::<String, usize>::new();
+    for photo in &photos {
+        let mut people = photo.people();
+        if person.is_some_and(|person| !people.contains(person)) {
+            continue;
+        }
+        count += 1;
+        if let Some(date) = photo.date() {
+            dates.push(date);
+        }
+        people.sort();
+        people.dedup();
+        match person {
+            Some(person) => {
+                for other in people.iter().filter(|other| *other != person) {
+                    *pairs.entry((person.clone(), other.clone())).or_insert(0) += 1;
+                }
+            }
+            None => {
+                for (i, a) in people.iter().enumerate() {
+                    for b in &people[i + 1..] {
+                        *pairs.entry((a.clone(), b.clone())).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+        if let Some(place) = &photo.location {
+            *places.entry(place.clone()).or_insert(0) += 1;
+        }
+        if let Some(photographer) = photo
+            .photographer
+            .as_ref()
+            .or(photo.photographer_name.as_ref())
+        {
+            *photographers.entry(photographer.clone()).or_insert(0) += 1;
+        }
+    }
+
+    let mut per_year = BTreeMap::<i32, usize>::new();
+    for date in &dates {
+        *per_year.entry(date.year()).or_insert(0) += 1;
+    }
+    let name = |id: &String| people_names.get(id).cloned().unwrap_or(id.clone());
+    let mut pairs = pairs
+        .into_iter()
+        .map(|((a, b), count)| PeoplePair { a, b, count })
+        .collect::<Vec<PeoplePair>>();
+    pairs.sort_by(|x, y| {
+        y.count
+            .cmp(&x.count)
+            .then_with(|| (name(&x.a), name(&x.b)).cmp(&(name(&y.a), name(&y.b))))
+    });
+    pairs.truncate(limit);
+
+    Ok(PeopleStats {
+        photos: count,
+        first_date: dates.iter().min().copied(),
+        last_date: dates.iter().max().copied(),
+        per_year: per_year
+            .into_iter()
+            .map(|(year, count)| YearCount { year, count })
+            .collect(),
+        pairs,
+        places: top(places, &place_names, limit),
+        photographers: top(photographers, &people_names, limit),
+    })
+}
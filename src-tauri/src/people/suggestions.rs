@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, NaiveDate};
+use serde::Serialize;
+use strum::{EnumIter, IntoEnumIterator};
+
+use crate::{
+    models::{Person, Photo},
+    people::{birthdays::BirthdatePrecision, PEOPLE},
+    photos::PHOTOS,
+};
+
+#[derive(Clone, Copy, EnumIter, Eq, Hash, PartialEq, Serialize)]
+pub enum SuggestionReason {
+    /// Tagged on photos taken around the same time
+    NearbyInTime,
+    SameGroup,
+    SamePlace,
+    SamePhotographer,
+}
+
+impl SuggestionReason {
+    /// How much the reason counts towards a suggestion. Photos in the same group are usually of
+    /// the same moment, while a place or photographer can cover years of photos.
+    fn weight(self) -> f32 {
+        match self {
+            Self::SameGroup => 4.0,
+            Self::NearbyInTime => 3.0,
+            Self::SamePlace => 2.0,
+            Self::SamePhotographer => 1.0,
+        }
+    }
+
+    /// Whether another photo shares this context with the photo people are suggested for.
+    fn matches(self, photo: &Photo, other: &Photo, hours: u32) -> bool {
+        match self {
+            Self::NearbyInTime => match (photo.date(), other.date()) {
+                // Photos only record the day they were taken
+                (Some(a), Some(b)) => (a - b).num_days().unsigned_abs() * 24 <= u64::from(hours),
+                _ => false,
+            },
+            Self::SameGroup => {
+                photo.photo_group.is_some() && photo.photo_group == other.photo_group
+            }
+            Self::SamePlace => photo.location.is_some() && photo.location == other.location,
+            Self::SamePhotographer => {
+                let photographer = |photo: &Photo| {
+                    photo
+                        .photographer
+                        .clone()
+                        .or(photo.photographer_name.clone())
+                };
+                photographer(photo).is_some() && photographer(photo) == photographer(other)
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SuggestionMatch {
+    pub reason: SuggestionReason,
+    /// The number of photos sharing the context that the person is on
+    pub photos: usize,
+    /// The number of photos sharing the context
+    pub total: usize,
+}
+
+#[derive(Serialize)]
+pub struct PersonSuggestion {
+    pub person: String,
+    pub name: String,
+    pub score: f32,
+    pub reasons: Vec<SuggestionMatch>,
+}
+
+/// Whether someone was born after a date, going by as much of their birthdate as is known.
+fn born_after(person: &Person, date: &NaiveDate) -> bool {
+    match person.birthdate() {
+        Some((birthdate, BirthdatePrecision::Year)) => birthdate.year() > date.year(),
+        Some((birthdate, BirthdatePrecision::Date)) => birthdate > *date,
+        None => false,
+    }
+}
+
+/// Ranks the people who might be in a photo by how often they are tagged on photos taken within
+/// `hours` of it, in the same group, at the same place, or by the same photographer. Each reason
+/// counts for the share of those photos the person is on. People already in the photo, and people
+/// born after it was taken, are left out.
+pub async fn suggest_people(
+    photo: &String,
+    hours: u32,
+    limit: usize,
+) -> Result<Vec<PersonSuggestion>> {
+    let photos = PHOTOS.lock().await;
+    let target = photos
+        .get(photo)
+        .ok_or_else(|| anyhow!("Photo {photo} not found"))?;
+    let existing = target.people();
+
+    let mut totals = HashMap::<SuggestionReason, usize>::new();
+    let mut counts = HashMap::<String, HashMap<SuggestionReason, usize>>::new();
+    for other in photos.values().filter(|other| other.name != *photo) {
+        let reasons = SuggestionReason::iter()
+            .filter(|reason| reason.matches(target, other, hours))
+            .collect::<Vec<SuggestionReason>>();
+        if reasons.is_empty() {
+            continue;
+        }
+        for reason in &reasons {
+            *totals.entry(*reason).or_insert(0) += 1;
+        }
+        for person in other.people() {
+            if existing.contains(&person) {
+                continue;
+            }
+            let person_counts = counts.entry(person).or_default();
+            for reason in &reasons {
+                *person_counts.entry(*reason).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let date = target.date();
+    let people = PEOPLE.lock().await;
+    let mut suggestions = Vec::<PersonSuggestion>::new();
+    for (id, person_counts) in counts {
+        let Some(person) = people.get(&id) else {
+            continue;
+        };
+        if date.is_some_and(|date| born_after(person, &date)) {
+            continue;
+        }
+        let reasons = SuggestionReason::iter()
+            .filter_map(|reason| {
+                Some(SuggestionMatch {
+                    reason,
+                    photos: *person_counts.get(&reason)?,
+                    total: totals[&reason],
+                })
+            })
+            .collect::<Vec<SuggestionMatch>>();
+        let score = reasons
+            .iter()
+            .map(|m| m.reason.weight() * m.photos as f32 / m.total as f32)
+            .sum();
+        suggestions.push(PersonSuggestion {
+            person: id,
+            name: person.name.clone(),
+            score,
+            reasons,
+        });
+    }
+    suggestions.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    suggestions.truncate(limit);
+    Ok(suggestions)
+}
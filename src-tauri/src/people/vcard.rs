@@ -0,0 +1,479 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{Datelike, NaiveDate};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::{
+    app::DATE_FORMAT,
+    people::{
+        birthdays::{Birthdate, BirthdatePrecision},
+        categories::{create_person_category, PEOPLE_CATEGORIES},
+        create_person, PEOPLE,
+    },
+    photos::{get_asset_file, get_asset_path},
+    settings::get_colors,
+};
+
+/// Image types that can be embedded in a vCard, by file extension
+const IMAGE_TYPES: [(&str, &str); 5] = [
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+];
+
+/// What to do with a card whose name matches someone who already exists.
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+pub enum DuplicatePolicy {
+    /// Fill in the birthdate and avatar if the existing person doesn't have them
+    Merge,
+    /// Leave the existing person as they are
+    Skip,
+}
+
+#[derive(Default, Serialize)]
+pub struct VcardImport {
+    pub created: usize,
+    /// Cards that matched existing people and were merged into them
+    pub merged: usize,
+    /// Cards that matched existing people and were skipped, or had no name
+    pub skipped: usize,
+}
+
+#[derive(Default)]
+struct Card {
+    uid: Option<String>,
+    name: Option<String>,
+    birthdate: Option<Birthdate>,
+    category: Option<String>,
+    /// The file extension and contents of an embedded photo
+    photo: Option<(String, Vec<u8>)>,
+}
+
+/// A property of a card, like `PHOTO;ENCODING=b;TYPE=JPEG:...`
+struct Property {
+    name: String,
+    /// Parameter values, uppercased. Named parameters keep their name, e.g. ENCODING=B.
+    params: Vec<String>,
+    value: String,
+}
+
+fn parse_property(line: &str) -> Option<Property> {
+    // The value starts at the first colon that isn't in a quoted parameter
+    let mut quoted = false;
+    let split = line.char_indices().find(|(_, c)| {
+        if *c == '"' {
+            quoted = !quoted;
+        }
+        *c == ':' && !quoted
+    })?;
+    let (head, value) = (&line[..split.0], &line[split.0 + 1..]);
+    let mut parts = head.split(';');
+    let name = parts.next()?;
+    // Properties can be grouped, e.g. item1.EMAIL
+    let name = name.rsplit('.').next().unwrap_or(name).to_uppercase();
+    Some(Property {
+        name,
+        params: parts
+            .map(|param| param.trim_matches('"').to_uppercase())
+            .collect(),
+        value: value.to_owned(),
+    })
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => result.push('\n'),
+            Some(c) => result.push(c),
+            None => {}
+        }
+    }
+    result
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
+
+/// Splits a list value, like CATEGORIES, on commas that aren't escaped.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut escaped = false;
+    for c in value.chars() {
+        if c == ',' && !escaped {
+            items.push(String::new());
+        } else {
+            items.last_mut().unwrap().push(c);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    items
+        .iter()
+        .map(|item| unescape(item).trim().to_owned())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Converts a BDAY value, like 19900415, 1990-04-15 or 1990, to a birthdate. Birthdays without a
+/// year, like --0415, can't be stored.
+fn parse_bday(value: &str) -> Option<Birthdate> {
+    let value = value.split('T').next()?.trim();
+    if value.starts_with("--") {
+        return None;
+    }
+    let digits = value
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect::<String>();
+    match digits.len() {
+        8 => NaiveDate::parse_from_str(&digits, "%Y%m%d")
+            .ok()
+            .map(|date| Birthdate {
+                date: date.format(DATE_FORMAT).to_string(),
+                precision: BirthdatePrecision::Date,
+            }),
+        // A year, or a year and month
+        4 | 6 => Some(Birthdate {
+            date: digits[..4].to_owned(),
+            precision: BirthdatePrecision::Year,
+        }),
+        _ => None,
+    }
+}
+
+/// Reads an embedded photo, either vCard 3.0 style (`PHOTO;ENCODING=b;TYPE=JPEG:...`) or as a
+/// data URI (`PHOTO:data:image/jpeg;base64,...`). Photos that link to a URL are ignored.
+fn parse_photo(property: &Property) -> Option<(String, Vec<u8>)> {
+    let (mime, data) = match property.value.strip_prefix("data:") {
+        Some(uri) => {
+            let (mime, data) = uri.split_once(";base64,")?;
+            (mime.to_lowercase(), data)
+        }
+        None => {
+            let params = &property.params;
+            if !params
+                .iter()
+                .any(|param| ["ENCODING=B", "ENCODING=BASE64", "BASE64"].contains(&param.as_str()))
+            {
+                return None;
+            }
+            // vCard 2.1 gives the type without a name, e.g. PHOTO;JPEG;ENCODING=BASE64
+            let kind = params
+                .iter()
+                .find_map(|param| param.strip_prefix("TYPE="))
+                .or_else(|| {
+                    params
+                        .iter()
+                        .find(|param| !param.contains('='))
+                        .map(String::as_str)
+                })
+                .unwrap_or("JPEG");
+            (
+                format!("image/{}", kind.to_lowercase()),
+                property.value.as_str(),
+            )
+        }
+    };
+    let extension = IMAGE_TYPES
+        .iter()
+        .find(|(_, image_type)| *image_type == mime)
+        .map(|(extension, _)| *extension)
+        .unwrap_or("jpg");
+    let data = data
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    Some((extension.to_owned(), STANDARD.decode(data).ok()?))
+}
+
+fn parse_cards(contents: &str) -> Vec<Card> {
+    // Long lines are folded onto lines starting with a space or tab
+    let mut lines = Vec::<String>::new();
+    for line in contents.trim_start_matches('\u{feff}').lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+
+    let mut cards = Vec::<Card>::new();
+    let mut card: Option<Card> = None;
+    for property in lines.iter().filter_map(|line| parse_property(line)) {
+        match (property.name.as_str(), &mut card) {
+            ("BEGIN", _) if property.value.eq_ignore_ascii_case("VCARD") => {
+                card = Some(Card::default());
+            }
+            ("END", Some(_)) if property.value.eq_ignore_ascii_case("VCARD") => {
+                cards.extend(card.take());
+            }
+            ("UID", Some(card)) => card.uid = Some(unescape(&property.value)),
+            ("FN", Some(card)) => {
+                let name = unescape(&property.value).trim().to_owned();
+                if !name.is_empty() {
+                    card.name = Some(name);
+                }
+            }
+            // N is family;given;additional;prefixes;suffixes, and is only used without FN
+            ("N", Some(card)) if card.name.is_none() => {
+                let parts = property
+                    .value
+                    .split(';')
+                    .map(unescape)
+                    .collect::<Vec<String>>();
+                let name = [
+                    parts.get(3),
+                    parts.get(1),
+                    parts.get(2),
+                    parts.first(),
+                    parts.get(4),
+                ]
+                .into_iter()
+                .flatten()
+                .map(|part| part.trim())
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<&str>>()
+                .join(" ");
+                if !name.is_empty() {
+                    card.name = Some(name);
+                }
+            }
+            ("BDAY", Some(card)) => card.birthdate = parse_bday(&property.value),
+            ("CATEGORIES", Some(card)) => {
+                card.category = split_list(&property.value).into_iter().next();
+            }
+            ("PHOTO", Some(card)) => card.photo = parse_photo(&property),
+            _ => {}
+        }
+    }
+    cards
+}
+
+/// Finds the people category with a name, creating it if there isn't one.
+async fn find_or_create_category(name: &str) -> Result<String> {
+    let (existing, count) = {
+        let categories = PEOPLE_CATEGORIES.lock().await;
+        (
+            categories
+                .values()
+                .find(|category| {
+                    category.id == name || category.name.to_uppercase() == name.to_uppercase()
+                })
+                .map(|category| category.id.clone()),
+            categories.len(),
+        )
+    };
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+    let colors = get_colors().await?;
+    let color = colors
+        .get(count % colors.len().max(1))
+        .cloned()
+        .unwrap_or_default();
+    let id = Uuid::new_v4().to_string();
+    create_person_category(&id, name, &color).await?;
+    Ok(id)
+}
+
+/// Writes a card's photo to the app data directory and returns its asset path.
+async fn save_avatar(
+    app_dir: &Path,
+    person: &str,
+    (extension, data): &(String, Vec<u8>),
+) -> Result<String> {
+    let dir = app_dir.join("avatars");
+    fs::create_dir_all(&dir).await?;
+    let path = dir.join(format!("{person}.{extension}"));
+    fs::write(&path, data).await?;
+    let path = path
+        .to_str()
+        .ok_or_else(|| anyhow!("Invalid avatar path {}", path.display()))?
+        .to_owned();
+    Ok(get_asset_path(&path))
+}
+
+/// Adds the people in a vCard file. Cards are matched to existing people by the UID they were
+/// exported with or by name, and `duplicates` decides what happens to the ones that match. New
+/// people go in the category named by the card's first CATEGORIES entry, which is created if
+/// needed, or in `category`. Photos are saved to the app data directory as avatars.
+pub async fn import_vcard(
+    path: &String,
+    category: &str,
+    duplicates: DuplicatePolicy,
+    app_dir: &Path,
+) -> Result<VcardImport> {
+    let contents = fs::read_to_string(path).await?;
+    let cards = parse_cards(&contents);
+    debug!("Read {} cards from {path}", cards.len());
+
+    let mut result = VcardImport::default();
+    for card in cards {
+        let Some(name) = &card.name else {
+            result.skipped += 1;
+            continue;
+        };
+        let existing = {
+            let people = PEOPLE.lock().await;
+            card.uid
+                .as_ref()
+                .filter(|uid| people.contains_key(*uid))
+                .cloned()
+                .or_else(|| {
+                    people
+                        .values()
+                        .find(|person| person.name.to_uppercase() == name.to_uppercase())
+                        .map(|person| person.id.clone())
+                })
+        };
+        let id = match existing {
+            Some(_) if duplicates == DuplicatePolicy::Skip => {
+                result.skipped += 1;
+                continue;
+            }
+            Some(id) => {
+                result.merged += 1;
+                id
+            }
+            None => {
+                let category = match &card.category {
+                    Some(name) => find_or_create_category(name).await?,
+                    None => category.to_owned(),
+                };
+                let id = Uuid::new_v4().to_string();
+                create_person(&id, name, &category).await?;
+                result.created += 1;
+                id
+            }
+        };
+
+        let has_avatar = PEOPLE
+            .lock()
+            .await
+            .get(&id)
+            .is_some_and(|p| p.photo.is_some());
+        let avatar = match &card.photo {
+            Some(photo) if !has_avatar => match save_avatar(app_dir, &id, photo).await {
+                Ok(avatar) => Some(avatar),
+                Err(err) => {
+                    warn!("Could not save the photo of {name}: {err}");
+                    None
+                }
+            },
+            _ => None,
+        };
+        let mut people = PEOPLE.lock().await;
+        let person = people.get_mut(&id).unwrap();
+        if person.birthdate.is_none() && card.birthdate.is_some() {
+            person.set_person_birthdate(&id, &card.birthdate).await?;
+        }
+        if avatar.is_some() {
+            person.set_person_photo(&id, &avatar, &None).await?;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Reads a person's avatar to embed in their card, if it is an image file.
+async fn read_avatar(photo: &str) -> Option<String> {
+    let file = get_asset_file(photo)?;
+    let extension = Path::new(&file).extension()?.to_str()?.to_lowercase();
+    let (_, mime) = IMAGE_TYPES.iter().find(|(e, _)| *e == extension)?;
+    match fs::read(&file).await {
+        Ok(data) => Some(format!("data:{mime};base64,{}", STANDARD.encode(data))),
+        Err(err) => {
+            warn!("Could not read avatar {file}: {err}");
+            None
+        }
+    }
+}
+
+/// Folds a line to at most 75 bytes per line, as vCards require.
+fn fold(line: &str) -> String {
+    let mut result = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            result.push_str("\r\n ");
+            length = 1;
+        }
+        result.push(c);
+        length += c.len_utf8();
+    }
+    result
+}
+
+/// Writes people to a vCard 4.0 file, with their category, birthdate and avatar. Avatars cropped
+/// from a region of a photo are left out. With a category, only the people in it are written.
+pub async fn export_vcard(path: &String, category: Option<&String>) -> Result<()> {
+    let categories = PEOPLE_CATEGORIES
+        .lock()
+        .await
+        .values()
+        .map(|category| (category.id.clone(), category.name.clone()))
+        .collect::<HashMap<String, String>>();
+    let mut people = PEOPLE
+        .lock()
+        .await
+        .values()
+        .filter(|person| category.is_none_or(|category| person.category == *category))
+        .cloned()
+        .collect::<Vec<_>>();
+    if people.is_empty() {
+        return Err(anyhow!("There are no people to export"));
+    }
+    people.sort_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+
+    let mut lines = Vec::<String>::new();
+    for person in &people {
+        lines.push("BEGIN:VCARD".to_string());
+        lines.push("VERSION:4.0".to_string());
+        lines.push(format!("UID:{}", escape(&person.id)));
+        lines.push(format!("FN:{}", escape(&person.name)));
+        if let Some((date, precision)) = person.birthdate() {
+            lines.push(match precision {
+                BirthdatePrecision::Year => format!("BDAY:{:04}", date.year()),
+                BirthdatePrecision::Date => format!("BDAY:{}", date.format("%Y%m%d")),
+            });
+        }
+        if let Some(name) = categories.get(&person.category) {
+            lines.push(format!("CATEGORIES:{}", escape(name)));
+        }
+        // An avatar with a region is a crop of a whole photo, which would be embedded uncropped
+        if let Some(photo) = person
+            .photo
+            .as_ref()
+            .filter(|_| person.photo_region.is_none())
+        {
+            if let Some(photo) = read_avatar(photo).await {
+                lines.push(format!("PHOTO:{photo}"));
+            }
+        }
+        lines.push("END:VCARD".to_string());
+    }
+
+    let contents = lines
+        .iter()
+        .map(|line| fold(line))
+        .collect::<Vec<String>>()
+        .join("\r\n");
+    fs::write(path, contents + "\r\n").await?;
+    Ok(())
+}
@@ -1,14 +1,32 @@
 use anyhow::Context;
 use log::debug;
+use tauri::{AppHandle, Emitter, Runtime};
 
 use crate::{
     app::ApiError,
-    photos::{get_group as _get_group, PhotoDto, PHOTOS},
-    tags::ValidationResult,
+    photos::{
+        get_group as _get_group, get_invalid_photos as _get_invalid_photos, PhotoDto, PHOTOS,
+    },
+    tags::{ValidationChange, ValidationResult},
 };
 
+/// Tells the frontend which photos' validation results changed after an edit.
+pub(crate) fn emit_validation<R: Runtime>(
+    app: &AppHandle<R>,
+    changes: Vec<ValidationChange>,
+) -> Result<(), ApiError> {
+    if !changes.is_empty() {
+        app.emit("validation-changed", changes)?;
+    }
+    Ok(())
+}
+
 #[tauri::command]
-pub async fn set_photo_title(photo: String, value: Option<String>) -> Result<(), ApiError> {
+pub async fn set_photo_title<R: Runtime>(
+    app: AppHandle<R>,
+    photo: String,
+    value: Option<String>,
+) -> Result<(), ApiError> {
     debug!(
         "Setting photo {photo} title to {}",
         value.as_ref().unwrap_or(&"NULL".to_string())
@@ -20,17 +38,22 @@ pub async fn set_photo_title(photo: String, value: Option<String>) -> Result<(),
         return Err(ApiError::NotFound(format!("Photo {photo} not found")));
     }
 
-    target
+    let changes = target
         .unwrap()
         .set_photo_title(&photo, &value)
         .await
         .with_context(|| format!("Could not set photo {photo} title"))?;
+    emit_validation(&app, changes)?;
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn set_photo_desc(photo: String, value: Option<String>) -> Result<(), ApiError> {
+pub async fn set_photo_desc<R: Runtime>(
+    app: AppHandle<R>,
+    photo: String,
+    value: Option<String>,
+) -> Result<(), ApiError> {
     debug!(
         "Setting photo {photo} description to {}",
         value.as_ref().unwrap_or(&"NULL".to_string())
@@ -42,17 +65,22 @@ pub async fn set_photo_desc(photo: String, value: Option<String>) -> Result<(),
         return Err(ApiError::NotFound(format!("Photo {photo} not found")));
     }
 
-    target
+    let changes = target
         .unwrap()
         .set_photo_desc(&photo, &value)
         .await
         .with_context(|| format!("Could not set photo {photo} description"))?;
+    emit_validation(&app, changes)?;
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn set_photographer(photo: String, value: Option<String>) -> Result<(), ApiError> {
+pub async fn set_photographer<R: Runtime>(
+    app: AppHandle<R>,
+    photo: String,
+    value: Option<String>,
+) -> Result<(), ApiError> {
     debug!(
         "Setting photo {photo} photographer to {}",
         value.as_ref().unwrap_or(&"NULL".to_string())
@@ -64,17 +92,22 @@ pub async fn set_photographer(photo: String, value: Option<String>) -> Result<()
         return Err(ApiError::NotFound(format!("Photo {photo} not found")));
     }
 
-    target
+    let changes = target
         .unwrap()
         .set_photographer(&photo, &value)
         .await
         .with_context(|| format!("Could not set photo {photo} photographer"))?;
+    emit_validation(&app, changes)?;
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn set_photo_people(photo: String, value: Vec<String>) -> Result<(), ApiError> {
+pub async fn set_photo_people<R: Runtime>(
+    app: AppHandle<R>,
+    photo: String,
+    value: Vec<String>,
+) -> Result<(), ApiError> {
     debug!("Setting photo {photo} people to {}", value.join(","));
 
     let mut photos = PHOTOS.lock().await;
@@ -83,17 +116,22 @@ pub async fn set_photo_people(photo: String, value: Vec<String>) -> Result<(), A
         return Err(ApiError::NotFound(format!("Photo {photo} not found")));
     }
 
-    target
+    let changes = target
         .unwrap()
         .set_photo_people(&photo, &value)
         .await
         .with_context(|| format!("Could not set photo {photo} people to {}", value.join(",")))?;
+    emit_validation(&app, changes)?;
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn set_photo_location(photo: String, value: Option<String>) -> Result<(), ApiError> {
+pub async fn set_photo_location<R: Runtime>(
+    app: AppHandle<R>,
+    photo: String,
+    value: Option<String>,
+) -> Result<(), ApiError> {
     debug!(
         "Setting photo {photo} location to {}",
         value.as_ref().unwrap_or(&"NULL".to_string())
@@ -105,7 +143,7 @@ pub async fn set_photo_location(photo: String, value: Option<String>) -> Result<
         return Err(ApiError::NotFound(format!("Photo {photo} not found")));
     }
 
-    target
+    let changes = target
         .unwrap()
         .set_photo_location(&photo, &value)
         .await
@@ -115,12 +153,17 @@ pub async fn set_photo_location(photo: String, value: Option<String>) -> Result<
                 value.unwrap_or("NULL".to_string())
             )
         })?;
+    emit_validation(&app, changes)?;
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn set_photo_date(photo: String, value: Option<String>) -> Result<(), ApiError> {
+pub async fn set_photo_date<R: Runtime>(
+    app: AppHandle<R>,
+    photo: String,
+    value: Option<String>,
+) -> Result<(), ApiError> {
     debug!(
         "Setting photo {photo} date to {}",
         value.as_ref().unwrap_or(&"NULL".to_string())
@@ -132,7 +175,7 @@ pub async fn set_photo_date(photo: String, value: Option<String>) -> Result<(),
         return Err(ApiError::NotFound(format!("Photo {photo} not found")));
     }
 
-    target
+    let changes = target
         .unwrap()
         .set_photo_date(&photo, &value)
         .await
@@ -142,12 +185,17 @@ pub async fn set_photo_date(photo: String, value: Option<String>) -> Result<(),
                 value.unwrap_or("NULL".to_string())
             )
         })?;
+    emit_validation(&app, changes)?;
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn set_photo_group(photo: String, value: Option<String>) -> Result<(), ApiError> {
+pub async fn set_photo_group<R: Runtime>(
+    app: AppHandle<R>,
+    photo: String,
+    value: Option<String>,
+) -> Result<(), ApiError> {
     debug!(
         "Setting photo {photo} group to {}",
         value.as_ref().unwrap_or(&"NULL".to_string())
@@ -159,7 +207,7 @@ pub async fn set_photo_group(photo: String, value: Option<String>) -> Result<(),
         return Err(ApiError::NotFound(format!("Photo {photo} not found")));
     }
 
-    target
+    let changes = target
         .unwrap()
         .set_photo_group(&photo, &value)
         .await
@@ -169,12 +217,17 @@ pub async fn set_photo_group(photo: String, value: Option<String>) -> Result<(),
                 value.unwrap_or("NULL".to_string())
             )
         })?;
+    emit_validation(&app, changes)?;
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn set_photo_rating(photo: String, rating: Option<i32>) -> Result<(), ApiError> {
+pub async fn set_photo_rating<R: Runtime>(
+    app: AppHandle<R>,
+    photo: String,
+    rating: Option<i32>,
+) -> Result<(), ApiError> {
     debug!(
         "Setting photo {photo} rating to {}",
         rating.as_ref().unwrap_or(&-1)
@@ -186,7 +239,7 @@ pub async fn set_photo_rating(photo: String, rating: Option<i32>) -> Result<(),
         return Err(ApiError::NotFound(format!("Photo {photo} not found")));
     }
 
-    target
+    let changes = target
         .unwrap()
         .set_photo_rating(&photo, rating)
         .await
@@ -196,6 +249,7 @@ pub async fn set_photo_rating(photo: String, rating: Option<i32>) -> Result<(),
                 rating.unwrap_or(-1)
             )
         })?;
+    emit_validation(&app, changes)?;
 
     Ok(())
 }
@@ -276,3 +330,14 @@ pub async fn get_group(group: String) -> Result<Vec<PhotoDto>, ApiError> {
         .map(PhotoDto::from)
         .collect())
 }
+
+/// Gets the photos that break the tag rules
+#[tauri::command]
+pub async fn get_invalid_photos() -> Result<Vec<PhotoDto>, ApiError> {
+    Ok(_get_invalid_photos()
+        .await
+        .with_context(|| "Failed to get invalid photos".to_string())?
+        .iter()
+        .map(PhotoDto::from)
+        .collect())
+}
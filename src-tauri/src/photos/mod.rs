@@ -5,21 +5,33 @@ use std::{
 };
 use tokio::sync::Mutex as AsyncMutex;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use chrono::NaiveDate;
-use diesel::{dsl::update, query_builder::AsChangeset, ExpressionMethods, QueryDsl};
-use diesel_async::RunQueryDsl;
+use diesel::{
+    dsl::{delete, insert_or_ignore_into, update},
+    query_builder::AsChangeset,
+    BoolExpressionMethods, ExpressionMethods, QueryDsl, SelectableHelper, SqliteConnection,
+};
+use diesel_async::{
+    scoped_futures::ScopedFutureExt, sync_connection_wrapper::SyncConnectionWrapper,
+    AsyncConnection, RunQueryDsl,
+};
 use log::warn;
 use regex::Regex;
 use serde::Serialize;
 
 use crate::{
-    app::{ensure_db, get_photo_targets, row_to_vec, vec_to_row, DATE_FORMAT, DB},
+    app::{ensure_db, get_photo_targets, DATE_FORMAT, DB},
     models::{Photo, Tag},
-    people::{PEOPLE_COUNTS, PHOTOGRAPHER_COUNTS},
+    people::{PEOPLE, PEOPLE_COUNTS, PHOTOGRAPHER_COUNTS},
     places::PLACE_COUNTS,
-    schema::photos,
-    tags::{validate_tags, ValidationResult, TAGS, TAG_COUNTS},
+    schema::{photo_people, photo_people_regions, photo_tags, photos, tags},
+    tags::{
+        resolve_tags,
+        rules::{rule_context, RuleContext},
+        update_total_counts, validate_photo_with, ValidationChange, ValidationResult, TAGS,
+        TAG_COUNTS, TAG_TOTAL_COUNTS,
+    },
 };
 
 pub mod api;
@@ -33,28 +45,131 @@ pub static PHOTOS: LazyLock<AsyncMutex<HashMap<String, Photo>>> =
 pub static VALIDATION_CACHE: LazyLock<Mutex<HashMap<String, ValidationResult>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
 
+/// Validates photos again after a field the tag rules can read has changed, returning the results
+/// that changed. Rules can depend on other fields, so each photo is validated separately.
+fn revalidate_targets(
+    targets: &[Photo],
+    tags: &HashMap<String, Tag>,
+    context: &RuleContext,
+) -> Vec<ValidationChange> {
+    let mut validation_cache = VALIDATION_CACHE.lock().unwrap();
+    let mut changes = Vec::new();
+    for target in targets {
+        let validation = validate_photo_with(target, tags, context);
+        if validation_cache.get(&target.name) != Some(&validation) {
+            validation_cache.insert(target.name.clone(), validation.clone());
+            changes.push(ValidationChange {
+                photo: target.name.clone(),
+                validation,
+            });
+        }
+    }
+    changes
+}
+
 #[derive(AsChangeset)]
 #[diesel(table_name = photos)]
 struct GroupFields {
     photo_group: String,
-    tags: Option<String>,
     location: Option<String>,
-    people: Option<String>,
     photographer: Option<String>,
     date: Option<String>,
 }
 
-pub fn get_asset_path(filename: &String) -> String {
-    format!(
-        "https://asset.localhost/{0}",
-        url_escape::encode_component(&filename)
+/// Replaces a photo's tags in the join table. Tags that don't have a row yet get one, since the
+/// join table references the tags table.
+pub async fn write_photo_tags(
+    conn: &mut SyncConnectionWrapper<SqliteConnection>,
+    photo: &String,
+    value: &[String],
+) -> Result<()> {
+    delete(photo_tags::table.filter(photo_tags::photo.eq(photo)))
+        .execute(conn)
+        .await?;
+    for (position, tag) in value.iter().enumerate() {
+        insert_or_ignore_into(tags::table)
+            .values(tags::name.eq(tag))
+            .execute(conn)
+            .await?;
+        insert_or_ignore_into(photo_tags::table)
+            .values((
+                photo_tags::photo.eq(photo),
+                photo_tags::tag.eq(tag),
+                photo_tags::position.eq(position as i32),
+            ))
+            .execute(conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Replaces the people in a photo in the join table. The regions of anyone no longer in the photo
+/// are removed as well.
+pub async fn write_photo_people(
+    conn: &mut SyncConnectionWrapper<SqliteConnection>,
+    photo: &String,
+    value: &[String],
+) -> Result<()> {
+    delete(
+        photo_people_regions::table.filter(
+            photo_people_regions::photo
+                .eq(photo)
+                .and(photo_people_regions::person.ne_all(value)),
+        ),
     )
+    .execute(conn)
+    .await?;
+    delete(photo_people::table.filter(photo_people::photo.eq(photo)))
+        .execute(conn)
+        .await?;
+    for (position, person) in value.iter().enumerate() {
+        insert_or_ignore_into(photo_people::table)
+            .values((
+                photo_people::photo.eq(photo),
+                photo_people::person.eq(person),
+                photo_people::position.eq(position as i32),
+            ))
+            .execute(conn)
+            .await?;
+    }
+    Ok(())
+}
+
+const ASSET_PREFIX: &str = "https://asset.localhost/";
+
+pub fn get_asset_path(filename: &String) -> String {
+    format!("{ASSET_PREFIX}{0}", url_escape::encode_component(&filename))
+}
+
+/// Gets the file an asset path from `get_asset_path` points to.
+pub fn get_asset_file(asset_path: &str) -> Option<String> {
+    asset_path
+        .strip_prefix(ASSET_PREFIX)
+        .map(|filename| url_escape::decode(filename).to_string())
+}
+
+/// Gets the photos that break the tag rules, sorted by name.
+pub async fn get_invalid_photos() -> Result<Vec<Photo>> {
+    let photos = PHOTOS.lock().await;
+    let validation_cache = VALIDATION_CACHE.lock().unwrap();
+    let mut invalid = photos
+        .values()
+        .filter(|photo| {
+            validation_cache
+                .get(&photo.name)
+                .is_some_and(|validation| !validation.is_valid)
+        })
+        .cloned()
+        .collect::<Vec<Photo>>();
+    invalid.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(invalid)
 }
 
 pub async fn get_group(group: &String) -> Result<Vec<Photo>> {
     ensure_db().await?;
     Ok(photos::table
         .filter(photos::photo_group.eq(group))
+        .select(Photo::as_select())
         .load(DB.lock().await.as_mut().unwrap())
         .await?)
 }
@@ -66,27 +181,29 @@ impl Photo {
             asset_path: get_asset_path(&filename),
             title: None,
             description: None,
-            tags: None,
+            tags: vec![],
             is_duplicate: None,
             rating: None,
             location: None,
             thumbnail: None,
             photo_group: None,
             date: None,
-            people: None,
+            people: vec![],
             hide_thumbnail: None,
-            photographer: None,
+            photographer_name: None,
             metadata_date: None,
             metadata_location: None,
+            photographer: None,
+            camera_serial: None,
         }
     }
 
     pub fn tags(&self) -> Vec<String> {
-        row_to_vec(&self.tags)
+        self.tags.clone()
     }
 
     pub fn people(&self) -> Vec<String> {
-        row_to_vec(&self.people)
+        self.people.clone()
     }
 
     pub fn date(&self) -> Option<NaiveDate> {
@@ -133,8 +250,14 @@ impl Photo {
         Some((split[0], split[1]))
     }
 
-    pub async fn set_photo_title(&mut self, photo: &String, value: &Option<String>) -> Result<()> {
+    pub async fn set_photo_title(
+        &mut self,
+        photo: &String,
+        value: &Option<String>,
+    ) -> Result<Vec<ValidationChange>> {
         ensure_db().await?;
+        let context = rule_context().await;
+        let tags = TAGS.lock().await;
         let mut conn = DB.lock().await;
         let conn = conn.as_mut().unwrap();
         update(photos::table.filter(photos::name.eq(photo)))
@@ -143,11 +266,21 @@ impl Photo {
             .await?;
         self.title = value.clone();
 
-        Ok(())
+        Ok(revalidate_targets(
+            std::slice::from_ref(self),
+            &tags,
+            &context,
+        ))
     }
 
-    pub async fn set_photo_desc(&mut self, photo: &String, value: &Option<String>) -> Result<()> {
+    pub async fn set_photo_desc(
+        &mut self,
+        photo: &String,
+        value: &Option<String>,
+    ) -> Result<Vec<ValidationChange>> {
         ensure_db().await?;
+        let context = rule_context().await;
+        let tags = TAGS.lock().await;
         let mut conn = DB.lock().await;
         let conn = conn.as_mut().unwrap();
         update(photos::table.filter(photos::name.eq(photo)))
@@ -156,30 +289,57 @@ impl Photo {
             .await?;
         self.description = value.clone();
 
-        Ok(())
+        Ok(revalidate_targets(
+            std::slice::from_ref(self),
+            &tags,
+            &context,
+        ))
     }
 
-    pub async fn set_photographer(&mut self, photo: &String, value: &Option<String>) -> Result<()> {
+    /// Sets the person who took the photo, which also clears any unresolved photographer name.
+    pub async fn set_photographer(
+        &mut self,
+        photo: &String,
+        value: &Option<String>,
+    ) -> Result<Vec<ValidationChange>> {
+        if let Some(value) = value {
+            if !PEOPLE.lock().await.contains_key(value) {
+                return Err(anyhow!("Person {value} not found"));
+            }
+        }
         ensure_db().await?;
-        let targets = get_photo_targets(photo).await?;
-        let count = targets.len();
+        let mut targets = get_photo_targets(photo).await?;
+        // Other photos in the group may not be cached with the same photographer as this one
+        let existing = targets
+            .iter()
+            .map(|target| target.photographer.clone())
+            .collect::<Vec<Option<String>>>();
+        let context = rule_context().await;
+        let tags = TAGS.lock().await;
         let mut conn = DB.lock().await;
         let conn = conn.as_mut().unwrap();
-        for target in &targets {
+        for target in &mut targets {
             update(photos::table.filter(photos::name.eq(target.name.clone())))
-                .set(photos::photographer.eq(value))
+                .set((
+                    photos::photographer.eq(value),
+                    photos::photographer_name.eq::<Option<String>>(None),
+                ))
                 .execute(conn)
                 .await?;
+            target.photographer = value.clone();
+            target.photographer_name = None;
         }
+        let changes = revalidate_targets(&targets, &tags, &context);
 
         let mut photographer_counts = PHOTOGRAPHER_COUNTS.lock().unwrap();
-        if let Some(old) = &self.photographer {
+        for old in existing.iter().flatten() {
             if photographer_counts.contains_key(old) {
                 *photographer_counts.get_mut(old).unwrap() =
-                    photographer_counts[old].saturating_sub(count);
+                    photographer_counts[old].saturating_sub(1);
             }
         }
         if let Some(new) = value {
+            let count = targets.len();
             if photographer_counts.contains_key(new) {
                 *photographer_counts.get_mut(new).unwrap() += count;
             } else {
@@ -188,23 +348,41 @@ impl Photo {
         }
 
         self.photographer = value.clone();
-        Ok(())
+        self.photographer_name = None;
+        Ok(changes)
     }
 
-    pub async fn set_photo_people(&self, photo: &String, value: &Vec<String>) -> Result<()> {
+    pub async fn set_photo_people(
+        &mut self,
+        photo: &String,
+        value: &Vec<String>,
+    ) -> Result<Vec<ValidationChange>> {
         ensure_db().await?;
         let mut targets = get_photo_targets(photo).await?;
         let existing_people = targets[0].people();
+        let context = rule_context().await;
+        let tags = TAGS.lock().await;
         let mut conn = DB.lock().await;
         let conn = conn.as_mut().unwrap();
-        let joined = vec_to_row(value);
+        let names = targets
+            .iter()
+            .map(|t| t.name.clone())
+            .collect::<Vec<String>>();
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            async move {
+                for name in &names {
+                    write_photo_people(conn, name, value).await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await?;
         for target in &mut targets {
-            update(photos::table.filter(photos::name.eq(target.name.clone())))
-                .set(photos::people.eq(joined.clone()))
-                .execute(conn)
-                .await?;
-            target.people = joined.clone();
+            target.people = value.clone();
         }
+        self.people = value.clone();
+        let changes = revalidate_targets(&targets, &tags, &context);
 
         // Acquire sync lock after all awaits
         let mut people_counts = PEOPLE_COUNTS.lock().unwrap();
@@ -225,13 +403,19 @@ impl Photo {
             }
         }
 
-        Ok(())
+        Ok(changes)
     }
 
-    pub async fn set_photo_location(&self, photo: &String, value: &Option<String>) -> Result<()> {
+    pub async fn set_photo_location(
+        &mut self,
+        photo: &String,
+        value: &Option<String>,
+    ) -> Result<Vec<ValidationChange>> {
         ensure_db().await?;
         let mut targets = get_photo_targets(photo).await?;
         let existing_place = targets[0].location.clone();
+        let context = rule_context().await;
+        let tags = TAGS.lock().await;
         let mut conn = DB.lock().await;
         let conn = conn.as_mut().unwrap();
         for target in &mut targets {
@@ -241,6 +425,8 @@ impl Photo {
                 .await?;
             target.location = value.clone();
         }
+        self.location = value.clone();
+        let changes = revalidate_targets(&targets, &tags, &context);
 
         // Acquire sync lock after all awaits
         let mut place_counts = PLACE_COUNTS.lock().unwrap();
@@ -260,32 +446,42 @@ impl Photo {
             }
         }
 
-        Ok(())
+        Ok(changes)
     }
 
-    pub async fn set_photo_date(&mut self, photo: &String, value: &Option<String>) -> Result<()> {
+    pub async fn set_photo_date(
+        &mut self,
+        photo: &String,
+        value: &Option<String>,
+    ) -> Result<Vec<ValidationChange>> {
         ensure_db().await?;
-        let targets = get_photo_targets(photo).await?;
+        let mut targets = get_photo_targets(photo).await?;
+        let context = rule_context().await;
+        let tags = TAGS.lock().await;
         let mut conn = DB.lock().await;
         let conn = conn.as_mut().unwrap();
-        for target in targets {
-            update(photos::table.filter(photos::name.eq(target.name)))
-                .set(
-                    photos::date.eq(if value.is_some() && !value.as_ref().unwrap().is_empty() {
-                        Some(value.clone().unwrap())
-                    } else {
-                        None
-                    }),
-                )
+        let date = if value.is_some() && !value.as_ref().unwrap().is_empty() {
+            Some(value.clone().unwrap())
+        } else {
+            None
+        };
+        for target in &mut targets {
+            update(photos::table.filter(photos::name.eq(target.name.clone())))
+                .set(photos::date.eq(date.clone()))
                 .execute(conn)
                 .await?;
+            target.date = date.clone();
         }
         self.date = value.clone();
 
-        Ok(())
+        Ok(revalidate_targets(&targets, &tags, &context))
     }
 
-    pub async fn set_photo_group(&self, photo: &String, value: &Option<String>) -> Result<()> {
+    pub async fn set_photo_group(
+        &self,
+        photo: &String,
+        value: &Option<String>,
+    ) -> Result<Vec<ValidationChange>> {
         ensure_db().await?;
         if value.is_none() {
             let mut conn = DB.lock().await;
@@ -298,6 +494,8 @@ impl Photo {
                     .await?;
                 target.photo_group = None;
             }
+            // The group isn't something rules can check
+            Ok(Vec::new())
         } else {
             let value = value.as_ref().unwrap();
             let mut targets = get_photo_targets(photo).await?;
@@ -335,9 +533,13 @@ impl Photo {
 
             let tags_vec = collected_tags.into_iter().collect::<Vec<String>>();
             let people_vec = collected_people.into_iter().collect::<Vec<String>>();
-            let tags_str = vec_to_row(&tags_vec);
-            let people_str = vec_to_row(&people_vec);
 
+            let context = rule_context().await;
+            let tags = TAGS.lock().await;
+            let existing_row_tags = targets
+                .iter()
+                .map(|row| row.tags())
+                .collect::<Vec<Vec<String>>>();
             let mut conn = DB.lock().await;
             let conn = conn.as_mut().unwrap();
             for row in targets.as_mut_slice() {
@@ -345,25 +547,32 @@ impl Photo {
                     .into_boxed()
                     .set(GroupFields {
                         photo_group: value.clone(),
-                        tags: tags_str.clone(),
                         location: collected_location.clone(),
-                        people: people_str.clone(),
                         photographer: collected_photographer.clone(),
                         date: collected_date.clone(),
                     })
                     .execute(conn)
                     .await?;
+                write_photo_tags(conn, &row.name, &tags_vec).await?;
+                write_photo_people(conn, &row.name, &people_vec).await?;
                 row.photo_group = Some(value.clone());
-                row.tags = tags_str.clone();
+                row.tags = tags_vec.clone();
                 row.location = collected_location.clone();
-                row.people = people_str.clone();
+                row.people = people_vec.clone();
                 row.photographer = collected_photographer.clone();
                 row.date = collected_date.clone();
             }
+            let changes = revalidate_targets(&targets, &tags, &context);
 
             let mut people_counts = PEOPLE_COUNTS.lock().unwrap();
             let mut place_counts = PLACE_COUNTS.lock().unwrap();
             let mut tag_counts = TAG_COUNTS.lock().unwrap();
+            let mut tag_total_counts = TAG_TOTAL_COUNTS.lock().unwrap();
+
+            // Photos in the group could have had different tags, so their totals move separately
+            for existing in &existing_row_tags {
+                update_total_counts(&mut tag_total_counts, existing, &tags_vec, 1, &tags);
+            }
 
             let count = targets.len();
             for person in &existing_people {
@@ -406,13 +615,19 @@ impl Photo {
                     }
                 }
             }
-        }
 
-        Ok(())
+            Ok(changes)
+        }
     }
 
-    pub async fn set_photo_rating(&mut self, photo: &String, rating: Option<i32>) -> Result<()> {
+    pub async fn set_photo_rating(
+        &mut self,
+        photo: &String,
+        rating: Option<i32>,
+    ) -> Result<Vec<ValidationChange>> {
         ensure_db().await?;
+        let context = rule_context().await;
+        let tags = TAGS.lock().await;
         let mut conn = DB.lock().await;
         let conn = conn.as_mut().unwrap();
         update(photos::table.filter(photos::name.eq(photo)))
@@ -421,7 +636,11 @@ impl Photo {
             .await?;
         self.rating = rating;
 
-        Ok(())
+        Ok(revalidate_targets(
+            std::slice::from_ref(self),
+            &tags,
+            &context,
+        ))
     }
 
     pub async fn set_photo_is_duplicate(&mut self, photo: &String, value: bool) -> Result<()> {
@@ -453,37 +672,36 @@ impl Photo {
     }
 
     pub async fn set_photo_tags(
-        &self,
+        &mut self,
         photo: &String,
-        value: &Vec<String>,
+        value: &[String],
     ) -> Result<ValidationResult> {
         ensure_db().await?;
-        let validation = validate_tags(value).await?;
+        let value = &resolve_tags(value).await?;
+        let context = rule_context().await;
         let mut targets = get_photo_targets(photo).await?;
         let existing_tags = targets[0].tags();
         let mut tags = TAGS.lock().await;
         let mut conn = DB.lock().await;
         let conn = conn.as_mut().unwrap();
-        let joined = vec_to_row(value);
-        for target in &mut targets {
-            update(photos::table.filter(photos::name.eq(target.name.clone())))
-                .set(photos::tags.eq(joined.clone()))
-                .execute(conn)
-                .await?;
-            target.tags = joined.clone();
-        }
-
-        // Update validation cache after DB operations
-        let mut validation_cache = VALIDATION_CACHE.lock().unwrap();
-        for target in &targets {
-            if validation_cache.get(&target.name).is_none() {
-                validation_cache.insert(target.name.clone(), validation.clone());
-            } else {
-                let vc = validation_cache.get_mut(&target.name).unwrap();
-                vc.is_valid = validation.is_valid;
-                vc.message = validation.message.clone();
+        let names = targets
+            .iter()
+            .map(|t| t.name.clone())
+            .collect::<Vec<String>>();
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            async move {
+                for name in &names {
+                    write_photo_tags(conn, name, value).await?;
+                }
+                Ok(())
             }
+            .scope_boxed()
+        })
+        .await?;
+        for target in &mut targets {
+            target.tags = value.clone();
         }
+        self.tags = value.clone();
 
         for tag in value {
             if !tags.contains_key(tag) {
@@ -491,6 +709,17 @@ impl Photo {
             }
         }
 
+        // Update validation cache after DB operations. Rules can depend on other fields, so each
+        // photo in the group is validated separately.
+        let validation = validate_photo_with(&targets[0], &tags, &context);
+        let mut validation_cache = VALIDATION_CACHE.lock().unwrap();
+        for target in &targets {
+            validation_cache.insert(
+                target.name.clone(),
+                validate_photo_with(target, &tags, &context),
+            );
+        }
+
         let mut tag_counts = TAG_COUNTS.lock().unwrap();
         let count = targets.len();
         for tag in &existing_tags {
@@ -507,6 +736,8 @@ impl Photo {
                 }
             }
         }
+        let mut tag_total_counts = TAG_TOTAL_COUNTS.lock().unwrap();
+        update_total_counts(&mut tag_total_counts, &existing_tags, value, count, &tags);
 
         Ok(validation)
     }
@@ -528,12 +759,17 @@ pub struct PhotoDto {
     pub people: Vec<String>,
     pub hide_thumbnail: bool,
     pub photographer: Option<String>,
+    /// The photographer's name, when it hasn't been matched to a person
+    pub photographer_name: Option<String>,
+    pub camera_serial: Option<String>,
     pub is_video: bool,
     pub is_raw: bool,
     pub valid_tags: ValidationResult,
     pub metadata_date: Option<NaiveDate>,
     pub metadata_location: Option<(f32, f32)>,
     pub grouped_raw: Option<String>,
+    /// The number of matching photos collapsed into this one by a search
+    pub group_size: Option<usize>,
 }
 
 impl From<&Photo> for PhotoDto {
@@ -554,6 +790,8 @@ impl From<&Photo> for PhotoDto {
             people: value.people(),
             hide_thumbnail: value.hide_thumbnail.unwrap_or(0) == 1,
             photographer: value.photographer.clone(),
+            photographer_name: value.photographer_name.clone(),
+            camera_serial: value.camera_serial.clone(),
             is_video: value.is_video(),
             is_raw: value.is_raw(),
             valid_tags: validation_cache
@@ -563,6 +801,7 @@ impl From<&Photo> for PhotoDto {
             metadata_date: value.metadata_date(),
             metadata_location: value.metadata_location(),
             grouped_raw: value.grouped_raw(),
+            group_size: None,
         }
     }
 }
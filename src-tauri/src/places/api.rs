@@ -1,15 +1,17 @@
 use anyhow::Context;
 use log::debug;
+use tauri::{AppHandle, Runtime};
 
 use crate::{
     app::ApiError,
     models::Shape,
+    photos::api::emit_validation,
     places::{
         create_layer as _create_layer, create_place as _create_place,
         create_shape as _create_shape, create_trip as _create_trip, delete_layer as _delete_layer,
         delete_place as _delete_place, delete_shape as _delete_shape, get_layers as _get_layers,
-        get_places as _get_places, get_shapes as _get_shapes, get_trips as _get_trips, LayerDto,
-        PlaceDto, TripDto, LAYERS, PLACES, SHAPES,
+        get_places as _get_places, get_shapes as _get_shapes, get_trips as _get_trips,
+        layer_places, revalidate_places, LayerDto, PlaceDto, TripDto, LAYERS, PLACES, SHAPES,
     },
 };
 
@@ -80,7 +82,11 @@ pub async fn set_layer_color(layer: String, value: String) -> Result<(), ApiErro
 }
 
 #[tauri::command]
-pub async fn set_layer_name(layer: String, value: String) -> Result<(), ApiError> {
+pub async fn set_layer_name<R: Runtime>(
+    app: AppHandle<R>,
+    layer: String,
+    value: String,
+) -> Result<(), ApiError> {
     debug!("Setting layer {layer} name to {value}");
 
     let mut layers = LAYERS.lock().await;
@@ -94,22 +100,27 @@ pub async fn set_layer_name(layer: String, value: String) -> Result<(), ApiError
         .set_layer_name(&layer, &value)
         .await
         .with_context(|| format!("Could not set layer {0} name to {1}", layer, value))?;
+    drop(layers);
 
-    Ok(())
+    // Rules can refer to layers by name
+    let changes = revalidate_places(&layer_places(&layer).await)
+        .await
+        .with_context(|| "Failed to revalidate photos".to_string())?;
+    emit_validation(&app, changes)
 }
 
 #[tauri::command]
-pub async fn delete_layer(
+pub async fn delete_layer<R: Runtime>(
+    app: AppHandle<R>,
     layer: String,
     recursive: bool,
     new_layer: Option<String>,
 ) -> Result<(), ApiError> {
     debug!("Deleting layer {layer}");
-    _delete_layer(&layer, recursive, &new_layer)
+    let changes = _delete_layer(&layer, recursive, &new_layer)
         .await
         .with_context(|| format!("Could not delete layer {0}", layer))?;
-
-    Ok(())
+    emit_validation(&app, changes)
 }
 
 #[tauri::command]
@@ -130,7 +141,11 @@ pub async fn create_place(
 }
 
 #[tauri::command]
-pub async fn set_place_name(place: String, value: String) -> Result<(), ApiError> {
+pub async fn set_place_name<R: Runtime>(
+    app: AppHandle<R>,
+    place: String,
+    value: String,
+) -> Result<(), ApiError> {
     debug!("Set place {place} name to {value}");
 
     let mut places = PLACES.lock().await;
@@ -144,8 +159,13 @@ pub async fn set_place_name(place: String, value: String) -> Result<(), ApiError
         .set_place_name(&place, &value)
         .await
         .with_context(|| format!("Could not set place {0} name to {1}", place, value))?;
+    drop(places);
 
-    Ok(())
+    // Rules can refer to places by name
+    let changes = revalidate_places(std::slice::from_ref(&place))
+        .await
+        .with_context(|| "Failed to revalidate photos".to_string())?;
+    emit_validation(&app, changes)
 }
 
 #[tauri::command]
@@ -196,7 +216,11 @@ pub async fn set_place_shape(place: String, value: Option<String>) -> Result<(),
 }
 
 #[tauri::command]
-pub async fn set_place_layer(place: String, layer: String) -> Result<(), ApiError> {
+pub async fn set_place_layer<R: Runtime>(
+    app: AppHandle<R>,
+    place: String,
+    layer: String,
+) -> Result<(), ApiError> {
     debug!("Set place {place} layer to {layer}");
 
     let mut places = PLACES.lock().await;
@@ -210,8 +234,12 @@ pub async fn set_place_layer(place: String, layer: String) -> Result<(), ApiErro
         .set_place_layer(&place, &layer)
         .await
         .with_context(|| format!("Could not set place {0} layer to {1}", place, layer))?;
+    drop(places);
 
-    Ok(())
+    let changes = revalidate_places(std::slice::from_ref(&place))
+        .await
+        .with_context(|| "Failed to revalidate photos".to_string())?;
+    emit_validation(&app, changes)
 }
 
 #[tauri::command]
@@ -239,13 +267,12 @@ pub async fn set_place_position(place: String, lat: f32, lng: f32) -> Result<(),
 }
 
 #[tauri::command]
-pub async fn delete_place(place: String) -> Result<(), ApiError> {
+pub async fn delete_place<R: Runtime>(app: AppHandle<R>, place: String) -> Result<(), ApiError> {
     debug!("Deleting place {place}");
-    _delete_place(&place)
+    let changes = _delete_place(&place)
         .await
         .with_context(|| format!("Could not delete place {0}", place))?;
-
-    Ok(())
+    emit_validation(&app, changes)
 }
 
 #[tauri::command]
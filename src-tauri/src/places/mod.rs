@@ -10,16 +10,17 @@ use diesel::{
     delete,
     dsl::{insert_into, update},
     query_builder::AsChangeset,
-    ExpressionMethods, QueryDsl,
+    ExpressionMethods, QueryDsl, SelectableHelper, SqliteConnection,
 };
-use diesel_async::RunQueryDsl;
+use diesel_async::{sync_connection_wrapper::SyncConnectionWrapper, RunQueryDsl};
 use serde::Serialize;
 
 use crate::{
-    app::{ensure_db, DATE_FORMAT, DB},
+    app::{ensure_db, row_to_vec, DATE_FORMAT, DB},
     models::{Layer, Photo, Place, Shape, Trip},
     photos::PHOTOS,
     schema::{layers, photos, places, shapes, trips},
+    tags::{revalidate_named_photos, ValidationChange},
 };
 
 pub mod api;
@@ -101,15 +102,61 @@ pub async fn create_layer(id: &str, name: &str, color: &str) -> Result<()> {
     Ok(())
 }
 
+/// Revalidates the photos located at any of the given places, since rules can be limited to a
+/// place or layer. Returns the photos whose result changed.
+pub async fn revalidate_places(places: &[String]) -> Result<Vec<ValidationChange>> {
+    let names = PHOTOS
+        .lock()
+        .await
+        .values()
+        .filter(|photo| photo.location.as_ref().is_some_and(|l| places.contains(l)))
+        .map(|photo| photo.name.clone())
+        .collect::<Vec<String>>();
+    revalidate_named_photos(&names).await
+}
+
+/// The places on a layer, whose photos are affected when the layer changes.
+pub async fn layer_places(layer: &String) -> Vec<String> {
+    PLACES
+        .lock()
+        .await
+        .values()
+        .filter(|place| place.layer == *layer)
+        .map(|place| place.id.clone())
+        .collect()
+}
+
 pub async fn delete_layer(
     layer: &String,
     recursive: bool,
     new_layer: &Option<String>,
-) -> Result<()> {
+) -> Result<Vec<ValidationChange>> {
     ensure_db().await?;
-    let mut conn = DB.lock().await;
-    let conn = conn.as_mut().unwrap();
+    // Photos that lose their location, and places whose photos now fall under another layer
+    let mut cleared = Vec::new();
+    let moved = if recursive {
+        Vec::new()
+    } else {
+        layer_places(layer).await
+    };
+    {
+        let mut conn = DB.lock().await;
+        let conn = conn.as_mut().unwrap();
+        delete_layer_rows(conn, layer, recursive, new_layer, &mut cleared).await?;
+    }
 
+    let mut changes = revalidate_named_photos(&cleared).await?;
+    changes.extend(revalidate_places(&moved).await?);
+    Ok(changes)
+}
+
+async fn delete_layer_rows(
+    conn: &mut SyncConnectionWrapper<SqliteConnection>,
+    layer: &String,
+    recursive: bool,
+    new_layer: &Option<String>,
+    cleared: &mut Vec<String>,
+) -> Result<()> {
     if recursive {
         delete(shapes::table.filter(shapes::layer.eq(layer.clone())))
             .execute(conn)
@@ -124,6 +171,7 @@ pub async fn delete_layer(
         {
             for photo in photos::table
                 .filter(photos::location.eq(place.id))
+                .select(Photo::as_select())
                 .load::<Photo>(conn)
                 .await?
             {
@@ -131,7 +179,10 @@ pub async fn delete_layer(
                     .set(photos::location.eq::<Option<String>>(None))
                     .execute(conn)
                     .await?;
-                photos.get_mut(&photo.name).unwrap().location = None;
+                if let Some(cached) = photos.get_mut(&photo.name) {
+                    cached.location = None;
+                    cleared.push(photo.name);
+                }
             }
         }
 
@@ -202,12 +253,25 @@ pub async fn create_place(
     Ok(())
 }
 
-pub async fn delete_place(place: &String) -> Result<()> {
+pub async fn delete_place(place: &String) -> Result<Vec<ValidationChange>> {
     ensure_db().await?;
-    let mut conn = DB.lock().await;
-    let conn = conn.as_mut().unwrap();
+    let mut cleared = Vec::new();
+    {
+        let mut conn = DB.lock().await;
+        let conn = conn.as_mut().unwrap();
+        delete_place_rows(conn, place, &mut cleared).await?;
+    }
+    revalidate_named_photos(&cleared).await
+}
+
+async fn delete_place_rows(
+    conn: &mut SyncConnectionWrapper<SqliteConnection>,
+    place: &String,
+    cleared: &mut Vec<String>,
+) -> Result<()> {
     for photo in photos::table
         .filter(photos::location.eq(place.clone()))
+        .select(Photo::as_select())
         .load::<Photo>(conn)
         .await?
     {
@@ -220,6 +284,7 @@ pub async fn delete_place(place: &String) -> Result<()> {
     for (_, photo) in photos.iter_mut() {
         if photo.location.as_ref().unwrap_or(&String::new()) == place {
             photo.location = None;
+            cleared.push(photo.name.clone());
         }
     }
 
@@ -467,7 +532,7 @@ pub async fn create_trip(
     let new_trip = Trip {
         name: name.to_owned(),
         id,
-        shapes: shapes.join(","),
+        shapes: serde_json::to_string(&shapes)?,
         date,
     };
     insert_into(trips::table)
@@ -491,7 +556,7 @@ impl From<&Trip> for TripDto {
         Self {
             id: value.id.clone(),
             name: value.name.clone(),
-            shapes: value.shapes.split(",").map(|s| s.to_string()).collect(),
+            shapes: value.shapes(),
             date: value.date(),
         }
     }
@@ -501,4 +566,8 @@ impl Trip {
     pub fn date(&self) -> Option<NaiveDate> {
         NaiveDate::parse_from_str(self.date.as_ref()?, DATE_FORMAT).ok()
     }
+
+    pub fn shapes(&self) -> Vec<String> {
+        row_to_vec(&Some(self.shapes.clone()))
+    }
 }
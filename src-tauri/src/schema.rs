@@ -1,5 +1,42 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    autotag_rules (id) {
+        id -> Text,
+        name -> Text,
+        query -> Text,
+        tags -> Nullable<Text>,
+        people -> Nullable<Text>,
+        photographer -> Nullable<Text>,
+        location -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    cameras (serial) {
+        serial -> Text,
+        photographer -> Text,
+    }
+}
+
+diesel::table! {
+    face_scans (photo) {
+        photo -> Text,
+    }
+}
+
+diesel::table! {
+    faces (id) {
+        id -> Text,
+        photo -> Text,
+        x -> Float,
+        y -> Float,
+        w -> Float,
+        h -> Float,
+        score -> Float,
+    }
+}
+
 diesel::table! {
     layers (id) {
         id -> Text,
@@ -14,6 +51,9 @@ diesel::table! {
         name -> Text,
         photo -> Nullable<Text>,
         category -> Text,
+        photo_region -> Nullable<Text>,
+        birthdate -> Nullable<Text>,
+        birthdate_precision -> Nullable<Text>,
     }
 }
 
@@ -25,6 +65,14 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    people_relationships (person, kind, related) {
+        person -> Text,
+        kind -> Text,
+        related -> Text,
+    }
+}
+
 diesel::table! {
     photo_groups (id) {
         id -> Text,
@@ -32,24 +80,51 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    photo_people (photo, person) {
+        photo -> Text,
+        person -> Text,
+        position -> Integer,
+    }
+}
+
+diesel::table! {
+    photo_people_regions (photo, person) {
+        photo -> Text,
+        person -> Text,
+        x -> Float,
+        y -> Float,
+        w -> Float,
+        h -> Float,
+    }
+}
+
+diesel::table! {
+    photo_tags (photo, tag) {
+        photo -> Text,
+        tag -> Text,
+        position -> Integer,
+    }
+}
+
 diesel::table! {
     photos (name) {
         name -> Text,
         asset_path -> Text,
         title -> Nullable<Text>,
         description -> Nullable<Text>,
-        tags -> Nullable<Text>,
         is_duplicate -> Nullable<Integer>,
         rating -> Nullable<Integer>,
         location -> Nullable<Text>,
         thumbnail -> Nullable<Text>,
         photo_group -> Nullable<Text>,
         date -> Nullable<Text>,
-        people -> Nullable<Text>,
         hide_thumbnail -> Nullable<Integer>,
-        photographer -> Nullable<Text>,
+        photographer_name -> Nullable<Text>,
         metadata_date -> Nullable<Text>,
         metadata_location -> Nullable<Text>,
+        photographer -> Nullable<Text>,
+        camera_serial -> Nullable<Text>,
     }
 }
 
@@ -82,13 +157,43 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    tag_categories (id) {
+        id -> Text,
+        name -> Text,
+        color -> Text,
+        sort_order -> Integer,
+        max_per_photo -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    tag_relations (tag, kind, related) {
+        tag -> Text,
+        kind -> Text,
+        related -> Text,
+        position -> Integer,
+    }
+}
+
+diesel::table! {
+    tag_rules (id) {
+        id -> Text,
+        name -> Text,
+        kind -> Text,
+        tags -> Nullable<Text>,
+        field -> Nullable<Text>,
+        condition -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     tags (name) {
         name -> Text,
         color -> Nullable<Text>,
-        prereqs -> Nullable<Text>,
-        coreqs -> Nullable<Text>,
-        incompatible -> Nullable<Text>,
+        parent -> Nullable<Text>,
+        aliases -> Nullable<Text>,
+        category -> Nullable<Text>,
     }
 }
 
@@ -101,15 +206,35 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(cameras -> people (photographer));
+diesel::joinable!(face_scans -> photos (photo));
+diesel::joinable!(faces -> photos (photo));
+diesel::joinable!(photo_people -> photos (photo));
+diesel::joinable!(photo_people_regions -> photos (photo));
+diesel::joinable!(photo_tags -> photos (photo));
+diesel::joinable!(photo_tags -> tags (tag));
+diesel::joinable!(photos -> people (photographer));
+
 diesel::allow_tables_to_appear_in_same_query!(
+    autotag_rules,
+    cameras,
+    face_scans,
+    faces,
     layers,
     people,
     people_categories,
+    people_relationships,
     photo_groups,
+    photo_people,
+    photo_people_regions,
+    photo_tags,
     photos,
     places,
     settings,
     shapes,
+    tag_categories,
+    tag_relations,
+    tag_rules,
     tags,
     trips,
 );
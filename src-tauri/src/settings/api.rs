@@ -4,7 +4,9 @@ use crate::{
     app::ApiError,
     settings::{
         add_color as _add_color, get_colors as _get_colors, get_setting,
-        promote_color as _promote_color, set_setting, Settings, ThemeSetting,
+        get_tag_canonicalization as _get_tag_canonicalization, promote_color as _promote_color,
+        set_setting, set_tag_canonicalization as _set_tag_canonicalization, Settings,
+        TagCanonicalization, ThemeSetting,
     },
 };
 
@@ -36,3 +38,15 @@ pub async fn promote_color(color: String) -> Result<Vec<String>, ApiError> {
 pub async fn add_color(color: String) -> Result<Vec<String>, ApiError> {
     Ok(_add_color(&color).await?)
 }
+
+#[tauri::command]
+pub async fn get_tag_canonicalization() -> Result<Vec<TagCanonicalization>, ApiError> {
+    Ok(_get_tag_canonicalization().await?)
+}
+
+#[tauri::command]
+pub async fn set_tag_canonicalization(
+    value: Vec<String>,
+) -> Result<Vec<TagCanonicalization>, ApiError> {
+    Ok(_set_tag_canonicalization(&value).await?)
+}
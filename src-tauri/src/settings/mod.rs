@@ -1,11 +1,11 @@
-use std::{collections::HashMap, sync::LazyLock};
+use std::{collections::HashMap, str::FromStr, sync::LazyLock};
 use tokio::sync::Mutex as AsyncMutex;
 
 use anyhow::Result;
 use diesel::{dsl::insert_into, query_dsl::methods::FilterDsl, update, ExpressionMethods};
 use diesel_async::RunQueryDsl;
 use serde::Serialize;
-use strum::{Display, EnumString};
+use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
 
 use crate::{
     app::{ensure_db, DB},
@@ -24,6 +24,11 @@ pub enum Settings {
     Theme,
     #[strum(ascii_case_insensitive)]
     Colors,
+    #[strum(ascii_case_insensitive)]
+    TagCanonicalization,
+    /// Set once camera serials have been read for photos added before they were stored
+    #[strum(ascii_case_insensitive)]
+    CameraSerialsRead,
 }
 
 #[derive(Display, EnumString, Serialize)]
@@ -34,6 +39,20 @@ pub enum ThemeSetting {
     Light,
 }
 
+/// Steps applied to tag names as they are entered, so different spellings resolve to one tag.
+#[derive(Clone, Copy, Display, EnumIter, EnumString, PartialEq, Serialize)]
+pub enum TagCanonicalization {
+    /// Remove leading and trailing whitespace
+    #[strum(ascii_case_insensitive)]
+    Trim,
+    /// Match existing tags and aliases regardless of case
+    #[strum(ascii_case_insensitive)]
+    CaseFold,
+    /// Apply Unicode normalization so equivalent characters compare equal
+    #[strum(ascii_case_insensitive)]
+    Normalize,
+}
+
 pub async fn set_setting(setting: Settings, value: String) -> Result<()> {
     ensure_db().await?;
 
@@ -106,3 +125,32 @@ pub async fn add_color(color: &str) -> Result<Vec<String>> {
     set_setting(Settings::Colors, colors.join(",")).await?;
     Ok(colors)
 }
+
+pub async fn get_tag_canonicalization() -> Result<Vec<TagCanonicalization>> {
+    let set_steps = get_setting(Settings::TagCanonicalization).await;
+    if let Ok(set_steps) = set_steps {
+        return Ok(set_steps
+            .split(",")
+            .filter_map(|s| TagCanonicalization::from_str(s).ok())
+            .collect::<Vec<TagCanonicalization>>());
+    }
+    // Every step is enabled until configured otherwise
+    Ok(TagCanonicalization::iter().collect::<Vec<TagCanonicalization>>())
+}
+
+pub async fn set_tag_canonicalization(value: &[String]) -> Result<Vec<TagCanonicalization>> {
+    let steps = value
+        .iter()
+        .map(|s| TagCanonicalization::from_str(s))
+        .collect::<Result<Vec<TagCanonicalization>, _>>()?;
+    set_setting(
+        Settings::TagCanonicalization,
+        steps
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>()
+            .join(","),
+    )
+    .await?;
+    Ok(steps)
+}
@@ -1,14 +1,47 @@
 use anyhow::Context;
 use log::debug;
+use tauri::{AppHandle, Emitter, Runtime};
 
 use crate::{
-    app::ApiError,
+    app::{vec_to_row, ApiError},
+    models::{TagCategory, TagRule},
+    settings::get_tag_canonicalization,
     tags::{
-        get_tags as _get_tags, validate_photo as _validate_photo, TagDto, TagRelationship,
-        ValidationResult, TAGS,
+        canonical_name,
+        categories::{
+            create_tag_category as _create_tag_category,
+            delete_tag_category as _delete_tag_category, get_tag_categories as _get_tag_categories,
+            get_tag_category_counts, update_tag_category as _update_tag_category, TagCategoryDto,
+            TAG_CATEGORIES,
+        },
+        check_aliases, check_parent, check_relationships, delete_tag as _delete_tag,
+        find_similar_tags as _find_similar_tags, get_tag_subtree as _get_tag_subtree,
+        get_tags as _get_tags, merge_tags as _merge_tags, refresh_total_tag_counts,
+        rename_tag as _rename_tag, resolve_tags as _resolve_tags, revalidate_photos,
+        rules::{
+            create_tag_rule as _create_tag_rule, delete_tag_rule as _delete_tag_rule,
+            get_tag_rules as _get_tag_rules, update_tag_rule as _update_tag_rule, TagRuleDto,
+        },
+        suggest::{suggest_tags as _suggest_tags, TagSuggestion},
+        validate_photo as _validate_photo, SimilarTags, TagDto, TagRelationship, ValidationResult,
+        TAGS,
     },
 };
 
+/// Revalidates photos after the tag rules change and tells the frontend which results changed.
+async fn revalidate<R: Runtime>(
+    app: &AppHandle<R>,
+    changed: Option<&[String]>,
+) -> Result<(), ApiError> {
+    let changes = revalidate_photos(changed)
+        .await
+        .with_context(|| "Failed to revalidate photos".to_string())?;
+    if !changes.is_empty() {
+        app.emit("validation-changed", changes)?;
+    }
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn set_tag_color(tag: String, value: Option<String>) -> Result<(), ApiError> {
     debug!(
@@ -37,54 +70,77 @@ pub async fn set_tag_color(tag: String, value: Option<String>) -> Result<(), Api
 }
 
 #[tauri::command]
-pub async fn set_tag_prereqs(tag: String, value: Vec<String>) -> Result<(), ApiError> {
+pub async fn set_tag_prereqs<R: Runtime>(
+    app: AppHandle<R>,
+    tag: String,
+    value: Vec<String>,
+) -> Result<(), ApiError> {
     debug!("Setting tag {tag} prereqs to {}", value.join(","));
 
     let mut tags = TAGS.lock().await;
-    let target = tags.get_mut(&tag);
-    if target.is_none() {
+    if !tags.contains_key(&tag) {
         return Err(ApiError::NotFound(format!("Tag {tag} not found")));
     }
+    check_relationships(&tag, &TagRelationship::Prereqs, &value, &tags)
+        .with_context(|| format!("Could not set tag {tag} prereqs to {}", value.join(",")))?;
 
-    target
+    tags.get_mut(&tag)
         .unwrap()
         .modify_tag_relationships(TagRelationship::Prereqs, &tag, &value)
         .await
         .with_context(|| format!("Could not set tag {tag} prereqs to {}", value.join(",")))?;
+    drop(tags);
 
-    Ok(())
+    revalidate(&app, Some(&[tag])).await
 }
 
 #[tauri::command]
-pub async fn set_tag_coreqs(tag: String, value: Vec<String>) -> Result<(), ApiError> {
+pub async fn set_tag_coreqs<R: Runtime>(
+    app: AppHandle<R>,
+    tag: String,
+    value: Vec<String>,
+) -> Result<(), ApiError> {
     debug!("Setting tag {tag} coreqs to {}", value.join(","));
 
     let mut tags = TAGS.lock().await;
-    let target = tags.get_mut(&tag);
-    if target.is_none() {
+    if !tags.contains_key(&tag) {
         return Err(ApiError::NotFound(format!("Tag {tag} not found")));
     }
+    check_relationships(&tag, &TagRelationship::Coreqs, &value, &tags)
+        .with_context(|| format!("Could not set tag {tag} coreqs to {}", value.join(",")))?;
 
-    target
+    tags.get_mut(&tag)
         .unwrap()
         .modify_tag_relationships(TagRelationship::Coreqs, &tag, &value)
         .await
         .with_context(|| format!("Could not set tag {tag} coreqs to {}", value.join(",")))?;
+    drop(tags);
 
-    Ok(())
+    revalidate(&app, Some(&[tag])).await
 }
 
 #[tauri::command]
-pub async fn set_tag_incompatible(tag: String, value: Vec<String>) -> Result<(), ApiError> {
+pub async fn set_tag_incompatible<R: Runtime>(
+    app: AppHandle<R>,
+    tag: String,
+    value: Vec<String>,
+) -> Result<(), ApiError> {
     debug!("Setting tag {tag} incompatible to {}", value.join(","));
 
     let mut tags = TAGS.lock().await;
-    let target = tags.get_mut(&tag);
-    if target.is_none() {
+    if !tags.contains_key(&tag) {
         return Err(ApiError::NotFound(format!("Tag {tag} not found")));
     }
+    check_relationships(&tag, &TagRelationship::Incompatible, &value, &tags).with_context(
+        || {
+            format!(
+                "Could not set tag {tag} incompatible to {}",
+                value.join(",")
+            )
+        },
+    )?;
 
-    target
+    tags.get_mut(&tag)
         .unwrap()
         .modify_tag_relationships(TagRelationship::Incompatible, &tag, &value)
         .await
@@ -94,10 +150,251 @@ pub async fn set_tag_incompatible(tag: String, value: Vec<String>) -> Result<(),
                 value.join(",")
             )
         })?;
+    drop(tags);
+
+    revalidate(&app, Some(&[tag])).await
+}
+
+#[tauri::command]
+pub async fn set_tag_parent<R: Runtime>(
+    app: AppHandle<R>,
+    tag: String,
+    value: Option<String>,
+) -> Result<(), ApiError> {
+    debug!(
+        "Setting tag {tag} parent to {}",
+        value.as_ref().unwrap_or(&"NULL".to_string())
+    );
+
+    let mut tags = TAGS.lock().await;
+    if !tags.contains_key(&tag) {
+        return Err(ApiError::NotFound(format!("Tag {tag} not found")));
+    }
+    if let Some(parent) = &value {
+        check_parent(&tag, parent, &tags)
+            .with_context(|| format!("Could not move tag {tag} under {parent}"))?;
+    }
+
+    tags.get_mut(&tag)
+        .unwrap()
+        .modify_tag_relationships(TagRelationship::Parent, &tag, value.as_slice())
+        .await
+        .with_context(|| {
+            format!(
+                "Could not set tag {tag} parent to {}",
+                value.unwrap_or("NULL".to_string())
+            )
+        })?;
+    drop(tags);
+    refresh_total_tag_counts()
+        .await
+        .with_context(|| "Failed to count tags".to_string())?;
+
+    revalidate(&app, Some(&[tag])).await
+}
+
+#[tauri::command]
+pub async fn set_tag_category<R: Runtime>(
+    app: AppHandle<R>,
+    tag: String,
+    value: Option<String>,
+) -> Result<(), ApiError> {
+    debug!(
+        "Setting tag {tag} category to {}",
+        value.as_ref().unwrap_or(&"NULL".to_string())
+    );
+
+    if let Some(category) = &value {
+        if !TAG_CATEGORIES.lock().await.contains_key(category) {
+            return Err(ApiError::NotFound(format!(
+                "Tag category {category} not found"
+            )));
+        }
+    }
+
+    let mut tags = TAGS.lock().await;
+    let target = tags.get_mut(&tag);
+    if target.is_none() {
+        return Err(ApiError::NotFound(format!("Tag {tag} not found")));
+    }
+
+    target
+        .unwrap()
+        .set_tag_category(&tag, &value)
+        .await
+        .with_context(|| {
+            format!(
+                "Could not set tag {tag} category to {}",
+                value.unwrap_or("NULL".to_string())
+            )
+        })?;
+    drop(tags);
+
+    revalidate(&app, Some(&[tag])).await
+}
+
+#[tauri::command]
+pub async fn get_tag_categories() -> Result<Vec<TagCategoryDto>, ApiError> {
+    let counts = get_tag_category_counts()
+        .await
+        .with_context(|| "Failed to count tag categories".to_string())?;
+    Ok(_get_tag_categories()
+        .await
+        .with_context(|| "Failed to get tag categories".to_string())?
+        .iter()
+        .map(|category| {
+            let mut dto = TagCategoryDto::from(category);
+            dto.count = counts.get(&category.id).copied().unwrap_or(0);
+            dto
+        })
+        .collect::<Vec<TagCategoryDto>>())
+}
+
+#[tauri::command]
+pub async fn create_tag_category(
+    id: String,
+    name: String,
+    color: String,
+    sort_order: i32,
+    max_per_photo: Option<i32>,
+) -> Result<(), ApiError> {
+    debug!("Creating tag category {name}");
+
+    Ok(_create_tag_category(TagCategory {
+        id,
+        name: name.clone(),
+        color,
+        sort_order,
+        max_per_photo,
+    })
+    .await
+    .with_context(|| format!("Could not create tag category {name}"))?)
+}
+
+#[tauri::command]
+pub async fn update_tag_category<R: Runtime>(
+    app: AppHandle<R>,
+    id: String,
+    name: String,
+    color: String,
+    sort_order: i32,
+    max_per_photo: Option<i32>,
+) -> Result<(), ApiError> {
+    debug!("Updating tag category {id}");
+
+    _update_tag_category(TagCategory {
+        id: id.clone(),
+        name,
+        color,
+        sort_order,
+        max_per_photo,
+    })
+    .await
+    .with_context(|| format!("Could not update tag category {id}"))?;
+
+    revalidate(&app, None).await
+}
+
+#[tauri::command]
+pub async fn delete_tag_category<R: Runtime>(
+    app: AppHandle<R>,
+    category: String,
+) -> Result<(), ApiError> {
+    debug!("Deleting tag category {category}");
+
+    _delete_tag_category(&category)
+        .await
+        .with_context(|| format!("Could not delete tag category {category}"))?;
+
+    revalidate(&app, None).await
+}
+
+#[tauri::command]
+pub async fn set_tag_aliases(tag: String, value: Vec<String>) -> Result<(), ApiError> {
+    debug!("Setting tag {tag} aliases to {}", value.join(","));
+
+    let steps = get_tag_canonicalization()
+        .await
+        .with_context(|| "Failed to get tag canonicalization".to_string())?;
+    let value = value
+        .iter()
+        .map(|alias| canonical_name(alias, &steps))
+        .filter(|alias| !alias.is_empty() && *alias != tag)
+        .collect::<Vec<String>>();
+
+    let mut tags = TAGS.lock().await;
+    if !tags.contains_key(&tag) {
+        return Err(ApiError::NotFound(format!("Tag {tag} not found")));
+    }
+    check_aliases(&tag, &value, &tags, &steps)
+        .with_context(|| format!("Could not set tag {tag} aliases"))?;
+
+    tags.get_mut(&tag)
+        .unwrap()
+        .modify_tag_relationships(TagRelationship::Aliases, &tag, &value)
+        .await
+        .with_context(|| format!("Could not set tag {tag} aliases to {}", value.join(",")))?;
 
     Ok(())
 }
 
+/// Resolves tags entered by the user to the tags they will be saved as
+#[tauri::command]
+pub async fn resolve_tags(value: Vec<String>) -> Result<Vec<String>, ApiError> {
+    Ok(_resolve_tags(&value)
+        .await
+        .with_context(|| format!("Failed to resolve tags {}", value.join(",")))?)
+}
+
+/// Lists pairs of tags that are probably duplicates of each other
+#[tauri::command]
+pub async fn find_similar_tags(max_distance: Option<usize>) -> Result<Vec<SimilarTags>, ApiError> {
+    Ok(_find_similar_tags(max_distance.unwrap_or(2))
+        .await
+        .with_context(|| "Failed to find similar tags".to_string())?)
+}
+
+#[tauri::command]
+pub async fn rename_tag<R: Runtime>(
+    app: AppHandle<R>,
+    tag: String,
+    value: String,
+) -> Result<(), ApiError> {
+    debug!("Renaming tag {tag} to {value}");
+
+    _rename_tag(&tag, &value)
+        .await
+        .with_context(|| format!("Could not rename tag {tag} to {value}"))?;
+    // Relationships and rules may have changed too, so every photo has to be checked again
+    revalidate(&app, None).await
+}
+
+#[tauri::command]
+pub async fn merge_tags<R: Runtime>(
+    app: AppHandle<R>,
+    from: String,
+    into: String,
+) -> Result<(), ApiError> {
+    debug!("Merging tag {from} into {into}");
+
+    _merge_tags(&from, &into)
+        .await
+        .with_context(|| format!("Could not merge tag {from} into {into}"))?;
+    // Relationships and rules may have changed too, so every photo has to be checked again
+    revalidate(&app, None).await
+}
+
+#[tauri::command]
+pub async fn delete_tag<R: Runtime>(app: AppHandle<R>, tag: String) -> Result<(), ApiError> {
+    debug!("Deleting tag {tag}");
+
+    _delete_tag(&tag)
+        .await
+        .with_context(|| format!("Could not delete tag {tag}"))?;
+    // Relationships and rules may have changed too, so every photo has to be checked again
+    revalidate(&app, None).await
+}
+
 #[tauri::command]
 pub async fn get_tags() -> Result<Vec<TagDto>, ApiError> {
     Ok(_get_tags()
@@ -108,9 +405,104 @@ pub async fn get_tags() -> Result<Vec<TagDto>, ApiError> {
         .collect::<Vec<TagDto>>())
 }
 
+/// Gets a tag and all of its descendants
+#[tauri::command]
+pub async fn get_tag_subtree(tag: String) -> Result<Vec<TagDto>, ApiError> {
+    Ok(_get_tag_subtree(&tag)
+        .await
+        .with_context(|| format!("Failed to get subtree of tag {tag}"))?
+        .iter()
+        .map(TagDto::from)
+        .collect::<Vec<TagDto>>())
+}
+
 #[tauri::command]
 pub async fn validate_photo(photo: String) -> Result<ValidationResult, ApiError> {
     Ok(_validate_photo(&photo)
         .await
         .with_context(|| format!("Failed to validate photo {photo}"))?)
 }
+
+/// Suggests tags for a photo, best first
+#[tauri::command]
+pub async fn suggest_tags(
+    photo: String,
+    limit: Option<usize>,
+) -> Result<Vec<TagSuggestion>, ApiError> {
+    Ok(_suggest_tags(&photo, limit.unwrap_or(10))
+        .await
+        .with_context(|| format!("Failed to suggest tags for photo {photo}"))?)
+}
+
+#[tauri::command]
+pub async fn get_tag_rules() -> Result<Vec<TagRuleDto>, ApiError> {
+    Ok(_get_tag_rules()
+        .await
+        .with_context(|| "Failed to get tag rules".to_string())?
+        .iter()
+        .map(TagRuleDto::from)
+        .collect::<Vec<TagRuleDto>>())
+}
+
+#[tauri::command]
+pub async fn create_tag_rule<R: Runtime>(
+    app: AppHandle<R>,
+    id: String,
+    name: String,
+    kind: String,
+    tags: Vec<String>,
+    field: Option<String>,
+    condition: Option<String>,
+) -> Result<(), ApiError> {
+    debug!("Creating {kind} tag rule {name}");
+
+    _create_tag_rule(TagRule {
+        id,
+        name: name.clone(),
+        kind,
+        tags: vec_to_row(&tags),
+        field,
+        condition,
+    })
+    .await
+    .with_context(|| format!("Could not create tag rule {name}"))?;
+
+    revalidate(&app, None).await
+}
+
+#[tauri::command]
+pub async fn update_tag_rule<R: Runtime>(
+    app: AppHandle<R>,
+    id: String,
+    name: String,
+    kind: String,
+    tags: Vec<String>,
+    field: Option<String>,
+    condition: Option<String>,
+) -> Result<(), ApiError> {
+    debug!("Updating tag rule {id}");
+
+    _update_tag_rule(TagRule {
+        id: id.clone(),
+        name,
+        kind,
+        tags: vec_to_row(&tags),
+        field,
+        condition,
+    })
+    .await
+    .with_context(|| format!("Could not update tag rule {id}"))?;
+
+    revalidate(&app, None).await
+}
+
+#[tauri::command]
+pub async fn delete_tag_rule<R: Runtime>(app: AppHandle<R>, rule: String) -> Result<(), ApiError> {
+    debug!("Deleting tag rule {rule}");
+
+    _delete_tag_rule(&rule)
+        .await
+        .with_context(|| format!("Could not delete tag rule {rule}"))?;
+
+    revalidate(&app, None).await
+}
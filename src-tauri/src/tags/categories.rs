@@ -0,0 +1,156 @@
+use std::{collections::HashMap, sync::LazyLock};
+use tokio::sync::Mutex as AsyncMutex;
+
+use anyhow::{anyhow, Result};
+use diesel::{
+    dsl::{count_distinct, delete, insert_into, update},
+    ExpressionMethods, QueryDsl,
+};
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl};
+use serde::Serialize;
+
+use crate::{
+    app::{ensure_db, DB},
+    models::{Tag, TagCategory},
+    schema::{photo_tags, tag_categories, tags},
+    tags::{ensure_tag, TAGS},
+};
+
+pub static TAG_CATEGORIES: LazyLock<AsyncMutex<HashMap<String, TagCategory>>> =
+    LazyLock::new(|| AsyncMutex::new(HashMap::new()));
+
+/// Gets the tag categories in their sort order.
+pub async fn get_tag_categories() -> Result<Vec<TagCategory>> {
+    let mut categories = TAG_CATEGORIES
+        .lock()
+        .await
+        .values()
+        .cloned()
+        .collect::<Vec<TagCategory>>();
+    categories.sort_by(|a, b| a.sort_order.cmp(&b.sort_order).then(a.name.cmp(&b.name)));
+    Ok(categories)
+}
+
+/// Counts the photos with at least one tag from each category. A photo with several tags from the
+/// same category is only counted once.
+pub async fn get_tag_category_counts() -> Result<HashMap<String, usize>> {
+    ensure_db().await?;
+    let rows = photo_tags::table
+        .inner_join(tags::table)
+        .filter(tags::category.is_not_null())
+        .group_by(tags::category)
+        .select((tags::category, count_distinct(photo_tags::photo)))
+        .load::<(Option<String>, i64)>(DB.lock().await.as_mut().unwrap())
+        .await?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(category, count)| Some((category?, count as usize)))
+        .collect())
+}
+
+pub async fn create_tag_category(category: TagCategory) -> Result<()> {
+    ensure_db().await?;
+    let mut categories = TAG_CATEGORIES.lock().await;
+    if categories.contains_key(&category.id) {
+        return Err(anyhow!("Tag category {} already exists", category.id));
+    }
+    insert_into(tag_categories::table)
+        .values(category.clone())
+        .execute(DB.lock().await.as_mut().unwrap())
+        .await?;
+    categories.insert(category.id.clone(), category);
+
+    Ok(())
+}
+
+pub async fn update_tag_category(category: TagCategory) -> Result<()> {
+    ensure_db().await?;
+    let mut categories = TAG_CATEGORIES.lock().await;
+    if !categories.contains_key(&category.id) {
+        return Err(anyhow!("Tag category {} not found", category.id));
+    }
+    update(tag_categories::table.filter(tag_categories::id.eq(&category.id)))
+        .set((
+            tag_categories::name.eq(&category.name),
+            tag_categories::color.eq(&category.color),
+            tag_categories::sort_order.eq(category.sort_order),
+            tag_categories::max_per_photo.eq(category.max_per_photo),
+        ))
+        .execute(DB.lock().await.as_mut().unwrap())
+        .await?;
+    categories.insert(category.id.clone(), category);
+
+    Ok(())
+}
+
+/// Deletes a tag category. Its tags are left without a category.
+pub async fn delete_tag_category(category: &String) -> Result<()> {
+    ensure_db().await?;
+    let mut categories = TAG_CATEGORIES.lock().await;
+    let mut tags_cache = TAGS.lock().await;
+    let mut conn = DB.lock().await;
+    let conn = conn.as_mut().unwrap();
+    conn.transaction::<_, anyhow::Error, _>(|conn| {
+        async move {
+            update(tags::table.filter(tags::category.eq(category)))
+                .set(tags::category.eq(None::<String>))
+                .execute(conn)
+                .await?;
+            delete(tag_categories::table.filter(tag_categories::id.eq(category)))
+                .execute(conn)
+                .await?;
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await?;
+
+    for tag in tags_cache.values_mut() {
+        if tag.category.as_ref() == Some(category) {
+            tag.category = None;
+        }
+    }
+    categories.remove(category);
+
+    Ok(())
+}
+
+impl Tag {
+    pub async fn set_tag_category(&mut self, tag: &String, value: &Option<String>) -> Result<()> {
+        ensure_db().await?;
+        let mut conn = DB.lock().await;
+        let conn = conn.as_mut().unwrap();
+        ensure_tag(tag, conn).await?;
+        update(tags::table.filter(tags::name.eq(tag)))
+            .set(tags::category.eq(value))
+            .execute(conn)
+            .await?;
+        self.category = value.clone();
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+pub struct TagCategoryDto {
+    pub id: String,
+    pub name: String,
+    pub color: String,
+    pub sort_order: i32,
+    pub max_per_photo: Option<i32>,
+    /// The number of photos with at least one tag in this category
+    pub count: usize,
+}
+
+impl From<&TagCategory> for TagCategoryDto {
+    fn from(value: &TagCategory) -> Self {
+        Self {
+            id: value.id.clone(),
+            name: value.name.clone(),
+            color: value.color.clone(),
+            sort_order: value.sort_order,
+            max_per_photo: value.max_per_photo,
+            count: 0,
+        }
+    }
+}
@@ -1,35 +1,51 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{LazyLock, Mutex},
 };
 use tokio::sync::Mutex as AsyncMutex;
 
 use anyhow::{anyhow, Result};
 use diesel::{
-    dsl::{insert_into, update},
-    ExpressionMethods, QueryDsl, SqliteConnection,
+    dsl::{delete, exists, insert_into, insert_or_ignore_into, select, update},
+    BoolExpressionMethods, ExpressionMethods, QueryDsl, SqliteConnection,
+};
+use diesel_async::{
+    scoped_futures::ScopedFutureExt, sync_connection_wrapper::SyncConnectionWrapper,
+    AsyncConnection, RunQueryDsl,
 };
-use diesel_async::{sync_connection_wrapper::SyncConnectionWrapper, RunQueryDsl};
 use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
 
 use crate::{
-    app::{ensure_db, row_to_vec, DB},
-    models::Tag,
-    photos::PHOTOS,
-    schema::tags,
+    app::{ensure_db, row_to_vec, search::tag_term, vec_to_row, DB},
+    models::{AutotagRule, Photo, Tag, TagRule},
+    photos::{write_photo_tags, PHOTOS, VALIDATION_CACHE},
+    schema::{autotag_rules, photo_tags, tag_relations, tag_rules, tags},
+    settings::{get_tag_canonicalization, TagCanonicalization},
+    tags::rules::{
+        check_category_limits, check_rules, rule_context, RuleCondition, RuleContext, RuleKind,
+        RuleViolation, TAG_RULES,
+    },
 };
 
 pub mod api;
+pub mod categories;
+pub mod rules;
+pub mod suggest;
 
 pub static TAGS: LazyLock<AsyncMutex<HashMap<String, Tag>>> =
     LazyLock::new(|| AsyncMutex::new(HashMap::new()));
 pub static TAG_COUNTS: LazyLock<Mutex<HashMap<String, usize>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
+/// The number of photos with each tag or any of its descendants
+pub static TAG_TOTAL_COUNTS: LazyLock<Mutex<HashMap<String, usize>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, PartialEq, Serialize)]
 pub struct ValidationResult {
     pub is_valid: bool,
     pub message: Option<String>,
+    pub violations: Vec<RuleViolation>,
 }
 
 impl Default for ValidationResult {
@@ -37,6 +53,7 @@ impl Default for ValidationResult {
         Self {
             is_valid: true,
             message: None,
+            violations: vec![],
         }
     }
 }
@@ -45,17 +62,44 @@ pub enum TagRelationship {
     Prereqs,
     Coreqs,
     Incompatible,
+    Parent,
+    Aliases,
+}
+
+impl TagRelationship {
+    /// The kind stored in the tag relations table, for the relationships that are kept there.
+    fn kind(&self) -> Option<&'static str> {
+        match self {
+            TagRelationship::Prereqs => Some("Prereq"),
+            TagRelationship::Coreqs => Some("Coreq"),
+            TagRelationship::Incompatible => Some("Incompatible"),
+            TagRelationship::Parent | TagRelationship::Aliases => None,
+        }
+    }
+}
+
+/// A photo whose validation result changed after the tag rules were edited.
+#[derive(Clone, Serialize)]
+pub struct ValidationChange {
+    pub photo: String,
+    pub validation: ValidationResult,
+}
+
+/// A pair of tags whose names are similar enough that they may be the same tag.
+#[derive(Serialize)]
+pub struct SimilarTags {
+    pub tag: String,
+    pub other: String,
+    pub distance: usize,
 }
 
 async fn ensure_tag(
     tag: &String,
     conn: &mut SyncConnectionWrapper<SqliteConnection>,
 ) -> Result<()> {
-    if tags::table
-        .filter(tags::name.eq(tag))
-        .load::<Tag>(conn)
+    if !select(exists(tags::table.filter(tags::name.eq(tag))))
+        .get_result::<bool>(conn)
         .await?
-        .is_empty()
     {
         insert_into(tags::table)
             .values(Tag::new(tag))
@@ -65,82 +109,812 @@ async fn ensure_tag(
     Ok(())
 }
 
-pub async fn validate_tags(tags: &Vec<String>) -> Result<ValidationResult> {
-    if tags.is_empty() {
-        Ok(ValidationResult {
-            is_valid: true,
-            message: None,
-        })
+/// Replaces one kind of relationship of a tag in the tag relations table.
+async fn write_tag_relations(
+    conn: &mut SyncConnectionWrapper<SqliteConnection>,
+    tag: &String,
+    kind: &str,
+    value: &[String],
+) -> Result<()> {
+    delete(
+        tag_relations::table.filter(tag_relations::tag.eq(tag).and(tag_relations::kind.eq(kind))),
+    )
+    .execute(conn)
+    .await?;
+    for (position, related) in value.iter().enumerate() {
+        insert_or_ignore_into(tags::table)
+            .values(tags::name.eq(related))
+            .execute(conn)
+            .await?;
+        insert_or_ignore_into(tag_relations::table)
+            .values((
+                tag_relations::tag.eq(tag),
+                tag_relations::kind.eq(kind),
+                tag_relations::related.eq(related),
+                tag_relations::position.eq(position as i32),
+            ))
+            .execute(conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Gets the ancestors of a tag, starting with its parent.
+pub fn ancestors(tag: &String, tags: &HashMap<String, Tag>) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = tags.get(tag).and_then(|t| t.parent.clone());
+    while let Some(parent) = current {
+        // Guard against cycles left behind by manual database edits
+        if parent == *tag || result.contains(&parent) {
+            break;
+        }
+        current = tags.get(&parent).and_then(|t| t.parent.clone());
+        result.push(parent);
+    }
+    result
+}
+
+/// Gets every tag below a tag in the hierarchy.
+pub fn descendants(tag: &String, tags: &HashMap<String, Tag>) -> Vec<String> {
+    tags.keys()
+        .filter(|t| *t != tag && ancestors(t, tags).contains(tag))
+        .cloned()
+        .collect()
+}
+
+/// Adds the ancestors implied by each tag to a list of tags.
+pub fn with_ancestors(value: &[String], tags: &HashMap<String, Tag>) -> Vec<String> {
+    let mut result = value.to_vec();
+    for tag in value {
+        for ancestor in ancestors(tag, tags) {
+            if !result.contains(&ancestor) {
+                result.push(ancestor);
+            }
+        }
+    }
+    result
+}
+
+/// Checks that a tag can be moved under a new parent without creating a cycle.
+pub fn check_parent(tag: &String, parent: &String, tags: &HashMap<String, Tag>) -> Result<()> {
+    if !tags.contains_key(parent) {
+        return Err(anyhow!("Tag {parent} not found"));
+    }
+    if parent == tag || ancestors(parent, tags).contains(tag) {
+        return Err(anyhow!("Tag {parent} is {tag} or one of its descendants"));
+    }
+    Ok(())
+}
+
+/// Applies the configured canonicalization steps that affect how a tag is stored. Case folding
+/// only affects matching, so a new tag keeps the case it was first entered with.
+pub fn canonical_name(tag: &str, steps: &[TagCanonicalization]) -> String {
+    let mut name = if steps.contains(&TagCanonicalization::Trim) {
+        tag.trim().to_string()
     } else {
-        let mut valid = true;
-        let mut message = String::new();
-        let mut missing_prereqs = String::new();
-        let mut missing_coreqs = String::new();
-        let mut incompatibles = String::new();
-        let tags_repo = TAGS.lock().await;
-        for tag in tags {
-            if tags_repo.contains_key(tag) {
-                let tag_data = tags_repo.get(tag).unwrap();
-                for prereq in &tag_data.prereqs() {
-                    if !tags.contains(prereq) {
-                        valid = false;
-                        missing_prereqs.push_str(prereq);
-                        missing_prereqs.push_str(", ");
-                    }
+        tag.to_string()
+    };
+    if steps.contains(&TagCanonicalization::Normalize) {
+        name = name.nfc().collect();
+    }
+    name
+}
+
+/// The key used to decide whether two spellings refer to the same tag.
+fn comparison_key(tag: &str, steps: &[TagCanonicalization]) -> String {
+    let mut key = canonical_name(tag, steps);
+    if steps.contains(&TagCanonicalization::Normalize) {
+        key = key.nfkc().collect();
+    }
+    if steps.contains(&TagCanonicalization::CaseFold) {
+        key = key.to_lowercase();
+    }
+    key
+}
+
+/// Resolves a tag to the existing tag or alias it matches, or to its canonical name if it's new.
+pub fn resolve_tag(
+    tag: &str,
+    tags: &HashMap<String, Tag>,
+    steps: &[TagCanonicalization],
+) -> String {
+    let name = canonical_name(tag, steps);
+    if tags.contains_key(&name) {
+        return name;
+    }
+    if let Some(aliased) = tags
+        .values()
+        .filter(|t| t.aliases().contains(&name))
+        .map(|t| &t.name)
+        .min()
+    {
+        return aliased.clone();
+    }
+    let key = comparison_key(&name, steps);
+    tags.values()
+        .filter(|t| {
+            comparison_key(&t.name, steps) == key
+                || t.aliases().iter().any(|a| comparison_key(a, steps) == key)
+        })
+        .map(|t| t.name.clone())
+        .min()
+        .unwrap_or(name)
+}
+
+/// Resolves a list of tags entered by the user, dropping empty and repeated tags.
+pub async fn resolve_tags(value: &[String]) -> Result<Vec<String>> {
+    let steps = get_tag_canonicalization().await?;
+    let tags = TAGS.lock().await;
+    let mut resolved = Vec::<String>::new();
+    for tag in value {
+        let tag = resolve_tag(tag, &tags, &steps);
+        if !tag.is_empty() && !resolved.contains(&tag) {
+            resolved.push(tag);
+        }
+    }
+    Ok(resolved)
+}
+
+/// Checks that aliases don't collide with other tags or their aliases.
+pub fn check_aliases(
+    tag: &String,
+    aliases: &[String],
+    tags: &HashMap<String, Tag>,
+    steps: &[TagCanonicalization],
+) -> Result<()> {
+    for alias in aliases {
+        let resolved = resolve_tag(alias, tags, steps);
+        if resolved != *tag && tags.contains_key(&resolved) {
+            return Err(anyhow!("{alias} already refers to tag {resolved}"));
+        }
+    }
+    Ok(())
+}
+
+/// The number of single-character edits needed to turn one string into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut previous = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Lists pairs of tags within the given edit distance of each other, closest first.
+pub async fn find_similar_tags(max_distance: usize) -> Result<Vec<SimilarTags>> {
+    let steps = get_tag_canonicalization().await?;
+    let tags = TAGS.lock().await;
+    let mut keys = tags
+        .keys()
+        .map(|t| (t.clone(), comparison_key(t, &steps)))
+        .collect::<Vec<(String, String)>>();
+    keys.sort();
+
+    let mut similar = Vec::new();
+    for (i, (tag, key)) in keys.iter().enumerate() {
+        for (other, other_key) in &keys[i + 1..] {
+            let distance = edit_distance(key, other_key);
+            if distance <= max_distance {
+                similar.push(SimilarTags {
+                    tag: tag.clone(),
+                    other: other.clone(),
+                    distance,
+                });
+            }
+        }
+    }
+    similar.sort_by_key(|s| s.distance);
+    Ok(similar)
+}
+
+/// Validates a list of tags against the relationships in an already locked tag cache.
+pub fn validate_tags_with(tags: &[String], tags_repo: &HashMap<String, Tag>) -> ValidationResult {
+    if tags.is_empty() {
+        return ValidationResult::default();
+    }
+    let mut message = String::new();
+    let mut missing_prereqs = String::new();
+    let mut missing_coreqs = String::new();
+    let mut incompatibles = String::new();
+    let mut violations = Vec::new();
+    // A tag implies all of its ancestors, so their rules apply and they satisfy requirements
+    let tags = &with_ancestors(tags, tags_repo);
+    for tag in tags {
+        if tags_repo.contains_key(tag) {
+            let tag_data = tags_repo.get(tag).unwrap();
+            for prereq in &tag_data.prereqs() {
+                if !tags.contains(prereq) {
+                    missing_prereqs.push_str(prereq);
+                    missing_prereqs.push_str(", ");
+                    violations.push(RuleViolation {
+                        rule: tag.clone(),
+                        kind: RuleKind::Prereq,
+                        tags: vec![tag.clone(), prereq.clone()],
+                        message: format!("{tag} requires {prereq}"),
+                    });
                 }
-                for coreq in &tag_data.coreqs() {
-                    if !tags.contains(coreq) {
-                        valid = false;
-                        missing_coreqs.push_str(coreq);
-                        missing_coreqs.push_str(", ");
-                    }
+            }
+            for coreq in &tag_data.coreqs() {
+                if !tags.contains(coreq) {
+                    missing_coreqs.push_str(coreq);
+                    missing_coreqs.push_str(", ");
+                    violations.push(RuleViolation {
+                        rule: tag.clone(),
+                        kind: RuleKind::Coreq,
+                        tags: vec![tag.clone(), coreq.clone()],
+                        message: format!("{tag} must be used with {coreq}"),
+                    });
                 }
-                for incompatible in &tag_data.incompatible() {
-                    if tags.contains(incompatible) {
-                        valid = false;
-                        incompatibles.push_str(incompatible);
-                        incompatibles.push_str(", ");
-                    }
+            }
+            for incompatible in &tag_data.incompatible() {
+                if tags.contains(incompatible) {
+                    incompatibles.push_str(incompatible);
+                    incompatibles.push_str(", ");
+                    violations.push(RuleViolation {
+                        rule: tag.clone(),
+                        kind: RuleKind::Incompatible,
+                        tags: vec![tag.clone(), incompatible.clone()],
+                        message: format!("{tag} can't be used with {incompatible}"),
+                    });
                 }
             }
         }
+    }
+
+    if !missing_prereqs.is_empty() {
+        message.push_str("Missing prerequisite tag(s): ");
+        message.push_str(&missing_prereqs);
+    }
+    if !missing_coreqs.is_empty() {
+        message.push_str("Missing corequisite tag(s): ");
+        message.push_str(&missing_coreqs);
+    }
+    if !incompatibles.is_empty() {
+        message.push_str("Incompatible tag(s) present: ");
+        message.push_str(&incompatibles);
+    }
+
+    ValidationResult {
+        is_valid: violations.is_empty(),
+        message: if !message.is_empty() {
+            Some(message)
+        } else {
+            None
+        },
+        violations,
+    }
+}
+
+/// Validates a photo against its tags' relationships, the tag category limits and the rules table.
+pub fn validate_photo_with(
+    photo: &Photo,
+    tags_repo: &HashMap<String, Tag>,
+    context: &RuleContext,
+) -> ValidationResult {
+    let tags = photo.tags();
+    let mut result = validate_tags_with(&tags, tags_repo);
+    let mut rule_violations = check_category_limits(&tags, tags_repo, context);
+    rule_violations.extend(check_rules(
+        photo,
+        &with_ancestors(&tags, tags_repo),
+        tags_repo,
+        context,
+    ));
+    if !rule_violations.is_empty() {
+        let mut message = result.message.unwrap_or_default();
+        for violation in &rule_violations {
+            message.push_str(&violation.message);
+            message.push_str(". ");
+        }
+        result.is_valid = false;
+        result.message = Some(message);
+        result.violations.extend(rule_violations);
+    }
+    result
+}
 
-        if !missing_prereqs.is_empty() {
-            message.push_str("Missing prerequisite tag(s): ");
-            message.push_str(&missing_prereqs);
+/// Revalidates the photos carrying any of the given tags, either directly or through one of their
+/// descendants, or every photo if no tags are given. Returns the photos whose result changed.
+pub async fn revalidate_photos(changed: Option<&[String]>) -> Result<Vec<ValidationChange>> {
+    revalidate_where(|photo, tags| match changed {
+        Some(changed) => {
+            let photo_tags = with_ancestors(&photo.tags(), tags);
+            changed.iter().any(|tag| photo_tags.contains(tag))
         }
-        if !missing_coreqs.is_empty() {
-            message.push_str("Missing corequisite tag(s): ");
-            message.push_str(&missing_coreqs);
+        None => true,
+    })
+    .await
+}
+
+/// Revalidates the given photos, for changes that don't come from their tags. Returns the photos
+/// whose result changed.
+pub async fn revalidate_named_photos(names: &[String]) -> Result<Vec<ValidationChange>> {
+    revalidate_where(|photo, _| names.contains(&photo.name)).await
+}
+
+async fn revalidate_where(
+    filter: impl Fn(&Photo, &HashMap<String, Tag>) -> bool,
+) -> Result<Vec<ValidationChange>> {
+    let context = rule_context().await;
+    let photos = PHOTOS.lock().await;
+    let tags = TAGS.lock().await;
+    let mut validation_cache = VALIDATION_CACHE.lock().unwrap();
+    let mut changes = Vec::new();
+    for photo in photos.values() {
+        if !filter(photo, &tags) {
+            continue;
         }
-        if !incompatibles.is_empty() {
-            message.push_str("Incompatible tag(s) present: ");
-            message.push_str(&incompatibles);
+        let validation = validate_photo_with(photo, &tags, &context);
+        if validation_cache.get(&photo.name) != Some(&validation) {
+            validation_cache.insert(photo.name.clone(), validation.clone());
+            changes.push(ValidationChange {
+                photo: photo.name.clone(),
+                validation,
+            });
         }
+    }
+    Ok(changes)
+}
 
-        Ok(ValidationResult {
-            is_valid: valid,
-            message: if !message.is_empty() {
-                Some(message)
-            } else {
-                None
-            },
-        })
+/// Checks that changing one of a tag's relationships won't produce rules that no photo can
+/// satisfy: requiring a tag that is incompatible, or prerequisites that depend on each other.
+pub fn check_relationships(
+    tag: &String,
+    category: &TagRelationship,
+    value: &[String],
+    tags: &HashMap<String, Tag>,
+) -> Result<()> {
+    let mut proposed = tags.clone();
+    let target = proposed
+        .get_mut(tag)
+        .ok_or_else(|| anyhow!("Tag {tag} not found"))?;
+    match category {
+        TagRelationship::Prereqs => target.prereqs = value.to_vec(),
+        TagRelationship::Coreqs => target.coreqs = value.to_vec(),
+        TagRelationship::Incompatible => target.incompatible = value.to_vec(),
+        TagRelationship::Parent | TagRelationship::Aliases => return Ok(()),
+    }
+    let target = proposed.get(tag).unwrap();
+
+    let mut required = target.prereqs();
+    required.extend(target.coreqs());
+    required.extend(ancestors(tag, &proposed));
+    for incompatible in target.incompatible() {
+        if incompatible == *tag || required.contains(&incompatible) {
+            return Err(anyhow!(
+                "{tag} can't both require and be incompatible with {incompatible}"
+            ));
+        }
+        if proposed
+            .get(&incompatible)
+            .is_some_and(|other| other.prereqs().contains(tag) || other.coreqs().contains(tag))
+        {
+            return Err(anyhow!(
+                "{incompatible} requires {tag}, so they can't be incompatible"
+            ));
+        }
+    }
+    for requirement in &required {
+        if proposed
+            .get(requirement)
+            .is_some_and(|other| other.incompatible().contains(tag))
+        {
+            return Err(anyhow!(
+                "{requirement} is incompatible with {tag}, so {tag} can't require it"
+            ));
+        }
     }
+
+    // Walk the prerequisites looking for a path back to this tag
+    let mut stack = target
+        .prereqs()
+        .into_iter()
+        .map(|prereq| vec![tag.clone(), prereq])
+        .collect::<Vec<Vec<String>>>();
+    let mut visited = HashSet::<String>::new();
+    while let Some(path) = stack.pop() {
+        let last = path.last().unwrap();
+        if last == tag {
+            return Err(anyhow!("Prerequisite cycle: {}", path.join(" -> ")));
+        }
+        if !visited.insert(last.clone()) {
+            continue;
+        }
+        if let Some(next) = proposed.get(last) {
+            for prereq in next.prereqs() {
+                let mut next_path = path.clone();
+                next_path.push(prereq);
+                stack.push(next_path);
+            }
+        }
+    }
+    Ok(())
 }
 
 pub async fn get_tags() -> Result<Vec<Tag>> {
     Ok(TAGS.lock().await.values().cloned().collect::<Vec<Tag>>())
 }
 
+/// Gets a tag and every tag below it in the hierarchy.
+pub async fn get_tag_subtree(tag: &String) -> Result<Vec<Tag>> {
+    let tags = TAGS.lock().await;
+    if !tags.contains_key(tag) {
+        return Err(anyhow!("Tag {tag} not found"));
+    }
+    let mut subtree = vec![tags.get(tag).unwrap().clone()];
+    for descendant in descendants(tag, &tags) {
+        subtree.push(tags.get(&descendant).unwrap().clone());
+    }
+    Ok(subtree)
+}
+
+/// Counts the photos with each tag, including photos that only have one of its descendants.
+pub fn count_total_tags<'a>(
+    photo_tags: impl IntoIterator<Item = &'a Vec<String>>,
+    tags: &HashMap<String, Tag>,
+) -> HashMap<String, usize> {
+    let mut totals = HashMap::<String, usize>::new();
+    for value in photo_tags {
+        let implied = with_ancestors(value, tags)
+            .into_iter()
+            .collect::<HashSet<String>>();
+        for tag in implied {
+            *totals.entry(tag).or_insert(0) += 1;
+        }
+    }
+    totals
+}
+
+/// Moves `count` photos from the totals of the tags `existing` implies to the totals of the tags
+/// `value` implies. Tags implied by both are left alone.
+pub fn update_total_counts(
+    totals: &mut HashMap<String, usize>,
+    existing: &[String],
+    value: &[String],
+    count: usize,
+    tags: &HashMap<String, Tag>,
+) {
+    let existing = with_ancestors(existing, tags);
+    let value = with_ancestors(value, tags);
+    for tag in existing.iter().filter(|tag| !value.contains(tag)) {
+        if let Some(total) = totals.get_mut(tag) {
+            *total = total.saturating_sub(count);
+        }
+    }
+    for tag in value.iter().filter(|tag| !existing.contains(tag)) {
+        *totals.entry(tag.clone()).or_insert(0) += count;
+    }
+}
+
+/// Counts the tag totals again from the database. Moving a tag changes what every photo with it or
+/// its descendants implies, so this is simpler than updating the totals in place.
+pub async fn refresh_total_tag_counts() -> Result<()> {
+    ensure_db().await?;
+    let rows = {
+        let mut conn = DB.lock().await;
+        let conn = conn.as_mut().unwrap();
+        photo_tags::table
+            .select((photo_tags::photo, photo_tags::tag))
+            .load::<(String, String)>(conn)
+            .await?
+    };
+    let mut photo_tags = HashMap::<String, Vec<String>>::new();
+    for (photo, tag) in rows {
+        photo_tags.entry(photo).or_default().push(tag);
+    }
+
+    let tags = TAGS.lock().await;
+    *TAG_TOTAL_COUNTS.lock().unwrap() = count_total_tags(photo_tags.values(), &tags);
+    Ok(())
+}
+
 pub async fn validate_photo(photo: &String) -> Result<ValidationResult> {
+    let context = rule_context().await;
     let photos = PHOTOS.lock().await;
     if !photos.contains_key(photo) {
         return Err(anyhow!("Photo {photo} not found!"));
     }
     let target = photos.get(photo).unwrap();
 
-    validate_tags(&target.tags()).await
+    Ok(validate_photo_with(target, &*TAGS.lock().await, &context))
+}
+
+/// Replaces every reference to a tag with another tag, or removes the references if there is no
+/// replacement. The photos, the other tags' relationships, the tag and auto-tagging rules and the
+/// tag itself are all rewritten in a single transaction before the caches are updated. Photos
+/// aren't validated again here; callers should revalidate them once the tag caches are unlocked.
+/// `merging` says whether `into` is an existing tag or the new name of the tag.
+async fn replace_tag(from: &String, into: Option<&String>, merging: bool) -> Result<()> {
+    ensure_db().await?;
+    let steps = get_tag_canonicalization().await?;
+    let mut photos_cache = PHOTOS.lock().await;
+    let mut tag_rules_cache = TAG_RULES.lock().await;
+    let mut tags = TAGS.lock().await;
+    let removed = tags
+        .get(from)
+        .cloned()
+        .ok_or_else(|| anyhow!("Tag {from} not found"))?;
+    if let Some(into) = into {
+        if merging && !tags.contains_key(into) {
+            return Err(anyhow!("Tag {into} not found"));
+        }
+        // A new name can't be taken by another tag, or be one of its aliases or spellings
+        let existing = resolve_tag(into, &tags, &steps);
+        if !merging && existing != *from && tags.contains_key(&existing) {
+            return Err(anyhow!(
+                "Tag {existing} already exists; merge the tags instead"
+            ));
+        }
+    }
+
+    // Tags can't reference themselves, so the owner of a relationship list is dropped from it
+    let replace = |value: Vec<String>, owner: Option<&String>| {
+        let mut result = Vec::<String>::new();
+        for tag in value {
+            let tag = if tag == *from {
+                match into {
+                    Some(into) => into.clone(),
+                    None => continue,
+                }
+            } else {
+                tag
+            };
+            if owner != Some(&tag) && !result.contains(&tag) {
+                result.push(tag);
+            }
+        }
+        result
+    };
+
+    let mut updated = tags.clone();
+    updated.remove(from);
+    if let Some(into) = into {
+        if merging {
+            // The merged tag keeps its own parent but picks up the relationships of the old one
+            let target = updated.get_mut(into).unwrap();
+            let mut prereqs = target.prereqs();
+            prereqs.extend(removed.prereqs());
+            let mut coreqs = target.coreqs();
+            coreqs.extend(removed.coreqs());
+            let mut incompatible = target.incompatible();
+            incompatible.extend(removed.incompatible());
+            target.prereqs = prereqs;
+            target.coreqs = coreqs;
+            target.incompatible = incompatible;
+            // The old name keeps working as an alias of the tag it was merged into
+            let mut aliases = target.aliases();
+            for alias in removed.aliases().into_iter().chain([from.clone()]) {
+                if !aliases.contains(&alias) {
+                    aliases.push(alias);
+                }
+            }
+            target.aliases = vec_to_row(&aliases);
+            if target.category.is_none() {
+                target.category = removed.category.clone();
+            }
+        } else {
+            let mut renamed = removed.clone();
+            renamed.name = into.clone();
+            updated.insert(into.clone(), renamed);
+        }
+    }
+    for tag in updated.values_mut() {
+        let name = tag.name.clone();
+        tag.prereqs = replace(tag.prereqs(), Some(&name));
+        tag.coreqs = replace(tag.coreqs(), Some(&name));
+        tag.incompatible = replace(tag.incompatible(), Some(&name));
+        if tag.parent.as_ref() == Some(from) {
+            // Children of a deleted tag move up to its parent
+            tag.parent = match into {
+                Some(into) if *into != name => Some(into.clone()),
+                _ => removed.parent.clone(),
+            };
+        }
+    }
+
+    // Rules limited to the old tag follow it when it is renamed or merged. A deleted tag is left
+    // in the condition, so the rule stops applying instead of applying to every photo.
+    let mut updated_rules = tag_rules_cache.clone();
+    for rule in updated_rules.values_mut() {
+        rule.tags = vec_to_row(&replace(rule.tags(), None));
+        if let (Ok(Some(RuleCondition::Tag(tag))), Some(into)) = (rule.condition(), into) {
+            if tag == *from {
+                rule.condition = Some(RuleCondition::Tag(into.clone()).to_string());
+            }
+        }
+    }
+    // A rule about a list of tags means nothing once the last of them is deleted
+    let emptied_rules = updated_rules
+        .values()
+        .filter(|rule| rule.tags.is_none() && tag_rules_cache[&rule.id].tags.is_some())
+        .map(|rule| rule.id.clone())
+        .collect::<Vec<String>>();
+    for rule in &emptied_rules {
+        updated_rules.remove(rule);
+    }
+    let changed_rules = updated_rules
+        .values()
+        .filter(|rule| {
+            let existing = &tag_rules_cache[&rule.id];
+            rule.tags != existing.tags || rule.condition != existing.condition
+        })
+        .cloned()
+        .collect::<Vec<TagRule>>();
+
+    let existing_tags = &*tags;
+    let steps = &steps;
+    let updated_tags = &updated;
+    let mut conn = DB.lock().await;
+    let conn = conn.as_mut().unwrap();
+    let changed_photos = conn
+        .transaction::<_, anyhow::Error, _>(|conn| {
+            async move {
+                // The new tag has to exist before anything can reference it, and the old one can
+                // only be deleted once nothing does
+                if let Some(into) = into.filter(|_| !merging) {
+                    insert_into(tags::table)
+                        .values(updated_tags.get(into).unwrap())
+                        .execute(conn)
+                        .await?;
+                }
+
+                // Photos whose files are missing aren't cached but still have rows, and deleting
+                // the old tag would otherwise cascade through their tags
+                let tagged_names = photo_tags::table
+                    .filter(photo_tags::tag.eq(from))
+                    .select(photo_tags::photo)
+                    .load::<String>(conn)
+                    .await?;
+                let mut tagged = Vec::<(String, Vec<String>)>::new();
+                for (photo, tag) in photo_tags::table
+                    .filter(photo_tags::photo.eq_any(&tagged_names))
+                    .order((photo_tags::photo, photo_tags::position))
+                    .select((photo_tags::photo, photo_tags::tag))
+                    .load::<(String, String)>(conn)
+                    .await?
+                {
+                    match tagged.last_mut() {
+                        Some((name, existing)) if *name == photo => existing.push(tag),
+                        _ => tagged.push((photo, vec![tag])),
+                    }
+                }
+
+                let mut changed_photos = Vec::<(String, Vec<String>, Vec<String>)>::new();
+                for (name, existing) in tagged {
+                    let value = replace(existing.clone(), None);
+                    write_photo_tags(conn, &name, &value).await?;
+                    changed_photos.push((name, existing, value));
+                }
+
+                delete(tag_relations::table.filter(tag_relations::tag.eq(from)))
+                    .execute(conn)
+                    .await?;
+                for tag in updated_tags.values() {
+                    for (kind, value) in [
+                        (TagRelationship::Prereqs, &tag.prereqs),
+                        (TagRelationship::Coreqs, &tag.coreqs),
+                        (TagRelationship::Incompatible, &tag.incompatible),
+                    ] {
+                        write_tag_relations(conn, &tag.name, kind.kind().unwrap(), value).await?;
+                    }
+                    update(tags::table.filter(tags::name.eq(&tag.name)))
+                        .set((
+                            tags::parent.eq(&tag.parent),
+                            tags::aliases.eq(&tag.aliases),
+                            tags::category.eq(&tag.category),
+                        ))
+                        .execute(conn)
+                        .await?;
+                }
+                delete(tag_rules::table.filter(tag_rules::id.eq_any(&emptied_rules)))
+                    .execute(conn)
+                    .await?;
+                for rule in &changed_rules {
+                    update(tag_rules::table.filter(tag_rules::id.eq(&rule.id)))
+                        .set((
+                            tag_rules::tags.eq(&rule.tags),
+                            tag_rules::condition.eq(&rule.condition),
+                        ))
+                        .execute(conn)
+                        .await?;
+                }
+                // Auto-tagging rules search for the tag under its new name. Queries for a deleted
+                // tag are left alone, since dropping the term would change what the rule matches.
+                let autotag = autotag_rules::table.load::<AutotagRule>(conn).await?;
+                for rule in autotag {
+                    let rule_tags = vec_to_row(&replace(rule.tags(), None));
+                    let query = rule
+                        .query()
+                        .into_iter()
+                        .map(|term| match (tag_term(&term), into) {
+                            (Some((tag, negated)), Some(into))
+                                if resolve_tag(&tag, existing_tags, steps) == *from =>
+                            {
+                                format!("{}{into}", if negated { "-" } else { "" })
+                            }
+                            _ => term,
+                        })
+                        .collect::<Vec<String>>()
+                        .join("\n");
+                    if rule_tags != rule.tags || query != rule.query {
+                        update(autotag_rules::table.filter(autotag_rules::id.eq(&rule.id)))
+                            .set((
+                                autotag_rules::tags.eq(&rule_tags),
+                                autotag_rules::query.eq(&query),
+                            ))
+                            .execute(conn)
+                            .await?;
+                    }
+                }
+                delete(tags::table.filter(tags::name.eq(from)))
+                    .execute(conn)
+                    .await?;
+                Ok(changed_photos)
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+    {
+        let mut tag_counts = TAG_COUNTS.lock().unwrap();
+        let count = tag_counts.remove(from).unwrap_or(0);
+        if let Some(into) = into {
+            if merging {
+                // Counts only cover the photos that are loaded
+                let added = changed_photos
+                    .iter()
+                    .filter(|(name, existing, _)| {
+                        photos_cache.contains_key(name) && !existing.contains(into)
+                    })
+                    .count();
+                *tag_counts.entry(into.clone()).or_insert(0) += added;
+            } else {
+                tag_counts.insert(into.clone(), count);
+            }
+        }
+    }
+
+    for (name, _, value) in &changed_photos {
+        if let Some(photo) = photos_cache.get_mut(name) {
+            photo.tags = value.clone();
+        }
+    }
+
+    *tags = updated;
+    *tag_rules_cache = updated_rules;
+
+    Ok(())
+}
+
+/// Renames a tag everywhere it is used.
+pub async fn rename_tag(tag: &String, new_name: &str) -> Result<()> {
+    let new_name = canonical_name(new_name, &get_tag_canonicalization().await?);
+    if new_name.is_empty() {
+        return Err(anyhow!("Tag name can't be empty"));
+    }
+    replace_tag(tag, Some(&new_name), false).await?;
+    refresh_total_tag_counts().await
+}
+
+/// Replaces a tag with another existing tag everywhere it is used, then removes it.
+pub async fn merge_tags(from: &String, into: &String) -> Result<()> {
+    if from == into {
+        return Err(anyhow!("Cannot merge tag {from} into itself"));
+    }
+    replace_tag(from, Some(into), true).await?;
+    refresh_total_tag_counts().await
+}
+
+/// Removes a tag from every photo and tag that references it, then deletes it.
+pub async fn delete_tag(tag: &String) -> Result<()> {
+    replace_tag(tag, None, false).await?;
+    refresh_total_tag_counts().await
 }
 
 impl Tag {
@@ -148,22 +922,29 @@ impl Tag {
         Self {
             name: name.to_owned(),
             color: None,
-            prereqs: None,
-            coreqs: None,
-            incompatible: None,
+            prereqs: vec![],
+            coreqs: vec![],
+            incompatible: vec![],
+            parent: None,
+            aliases: None,
+            category: None,
         }
     }
 
     pub fn prereqs(&self) -> Vec<String> {
-        row_to_vec(&self.prereqs)
+        self.prereqs.clone()
     }
 
     pub fn coreqs(&self) -> Vec<String> {
-        row_to_vec(&self.coreqs)
+        self.coreqs.clone()
     }
 
     pub fn incompatible(&self) -> Vec<String> {
-        row_to_vec(&self.incompatible)
+        self.incompatible.clone()
+    }
+
+    pub fn aliases(&self) -> Vec<String> {
+        row_to_vec(&self.aliases)
     }
 
     pub async fn set_tag_color(&mut self, tag: &String, value: &Option<String>) -> Result<()> {
@@ -187,31 +968,33 @@ impl Tag {
         value: &[String],
     ) -> Result<()> {
         ensure_db().await?;
-        let joined = value.join(",");
         let mut conn = DB.lock().await;
         let conn = conn.as_mut().unwrap();
         ensure_tag(tag, conn).await?;
+        if let Some(kind) = category.kind() {
+            conn.transaction::<_, anyhow::Error, _>(|conn| {
+                async move { write_tag_relations(conn, tag, kind, value).await }.scope_boxed()
+            })
+            .await?;
+        }
         match category {
-            TagRelationship::Prereqs => {
-                self.prereqs = Some(joined.clone());
+            TagRelationship::Prereqs => self.prereqs = value.to_vec(),
+            TagRelationship::Coreqs => self.coreqs = value.to_vec(),
+            TagRelationship::Incompatible => self.incompatible = value.to_vec(),
+            TagRelationship::Parent => {
+                let parent = value.first().cloned();
+                self.parent = parent.clone();
                 update(tags::table.filter(tags::name.eq(tag)))
-                    .set(tags::prereqs.eq(joined))
+                    .set(tags::parent.eq(parent))
                     .execute(conn)
-                    .await?
-            }
-            TagRelationship::Coreqs => {
-                self.coreqs = Some(joined.clone());
-                update(tags::table.filter(tags::name.eq(tag)))
-                    .set(tags::coreqs.eq(joined))
-                    .execute(conn)
-                    .await?
+                    .await?;
             }
-            TagRelationship::Incompatible => {
-                self.incompatible = Some(joined.clone());
+            TagRelationship::Aliases => {
+                self.aliases = vec_to_row(value);
                 update(tags::table.filter(tags::name.eq(tag)))
-                    .set(tags::incompatible.eq(joined))
+                    .set(tags::aliases.eq(&self.aliases))
                     .execute(conn)
-                    .await?
+                    .await?;
             }
         };
         Ok(())
@@ -225,19 +1008,29 @@ pub struct TagDto {
     pub prereqs: Vec<String>,
     pub coreqs: Vec<String>,
     pub incompatible: Vec<String>,
+    pub parent: Option<String>,
+    pub aliases: Vec<String>,
+    pub category: Option<String>,
     pub count: usize,
+    /// The number of photos with this tag or any of its descendants
+    pub total_count: usize,
 }
 
 impl From<&Tag> for TagDto {
     fn from(value: &Tag) -> Self {
         let counts_cache = TAG_COUNTS.lock().unwrap();
+        let totals_cache = TAG_TOTAL_COUNTS.lock().unwrap();
         Self {
             name: value.name.clone(),
             color: value.color.clone(),
             prereqs: value.prereqs(),
             coreqs: value.coreqs(),
             incompatible: value.incompatible(),
+            parent: value.parent.clone(),
+            aliases: value.aliases(),
+            category: value.category.clone(),
             count: counts_cache.get(&value.name).copied().unwrap_or(0),
+            total_count: totals_cache.get(&value.name).copied().unwrap_or(0),
         }
     }
 }
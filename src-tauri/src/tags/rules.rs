@@ -0,0 +1,488 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr, sync::LazyLock};
+use tokio::sync::Mutex as AsyncMutex;
+
+use anyhow::{anyhow, Result};
+use diesel::{
+    dsl::{delete, insert_into, update},
+    ExpressionMethods, QueryDsl,
+};
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+use strum::{Display, EnumString};
+
+use crate::{
+    app::{
+        ensure_db, row_to_vec,
+        search::{parse_rating, CompOp},
+        DB,
+    },
+    models::{Photo, Tag, TagCategory, TagRule},
+    places::{LAYERS, PLACES},
+    schema::tag_rules,
+    settings::{get_tag_canonicalization, TagCanonicalization},
+    tags::{categories::TAG_CATEGORIES, resolve_tag, TAGS},
+};
+
+pub static TAG_RULES: LazyLock<AsyncMutex<HashMap<String, TagRule>>> =
+    LazyLock::new(|| AsyncMutex::new(HashMap::new()));
+
+/// The kind of check that a photo failed. The first three come from the relationships set on
+/// individual tags and CategoryLimit from tag categories, the rest from rules in the rules table.
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Serialize)]
+pub enum RuleKind {
+    #[strum(ascii_case_insensitive)]
+    Prereq,
+    #[strum(ascii_case_insensitive)]
+    Coreq,
+    #[strum(ascii_case_insensitive)]
+    Incompatible,
+    #[strum(ascii_case_insensitive)]
+    ExactlyOneOf,
+    #[strum(ascii_case_insensitive)]
+    AtLeastOneOf,
+    #[strum(ascii_case_insensitive)]
+    AtMostOneOf,
+    #[strum(ascii_case_insensitive)]
+    Requires,
+    #[strum(ascii_case_insensitive)]
+    CategoryLimit,
+}
+
+/// Photo fields that a Requires rule can demand a value for.
+#[derive(Clone, Copy, Display, EnumString, PartialEq, Serialize)]
+pub enum RuleField {
+    #[strum(ascii_case_insensitive)]
+    Title,
+    #[strum(ascii_case_insensitive)]
+    Description,
+    #[strum(ascii_case_insensitive)]
+    Location,
+    #[strum(ascii_case_insensitive)]
+    Date,
+    #[strum(ascii_case_insensitive)]
+    Rating,
+    #[strum(ascii_case_insensitive)]
+    People,
+    #[strum(ascii_case_insensitive)]
+    Photographer,
+}
+
+/// Limits a rule to the photos it applies to, e.g. `tag:beach`, `layer:nature` or `rating=5`.
+/// Layers and places can be given by id or by name.
+pub enum RuleCondition {
+    Tag(String),
+    Layer(String),
+    Place(String),
+    Rating(CompOp, i32),
+}
+
+impl FromStr for RuleCondition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let up = s.to_uppercase();
+        if up.starts_with("TAG:") {
+            Ok(RuleCondition::Tag(s[4..].to_string()))
+        } else if up.starts_with("LAYER:") {
+            Ok(RuleCondition::Layer(s[6..].to_string()))
+        } else if up.starts_with("PLACE:") {
+            Ok(RuleCondition::Place(s[6..].to_string()))
+        } else if up.starts_with("RATING<=") {
+            Ok(RuleCondition::Rating(CompOp::Le, parse_rating(&s[8..])?))
+        } else if up.starts_with("RATING>=") {
+            Ok(RuleCondition::Rating(CompOp::Ge, parse_rating(&s[8..])?))
+        } else if up.starts_with("RATING<") {
+            Ok(RuleCondition::Rating(CompOp::Lt, parse_rating(&s[7..])?))
+        } else if up.starts_with("RATING>") {
+            Ok(RuleCondition::Rating(CompOp::Gt, parse_rating(&s[7..])?))
+        } else if up.starts_with("RATING=") {
+            Ok(RuleCondition::Rating(CompOp::Eq, parse_rating(&s[7..])?))
+        } else {
+            Err(anyhow!("Unknown rule condition: {s}"))
+        }
+    }
+}
+
+impl Display for RuleCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleCondition::Tag(tag) => write!(f, "tag:{tag}"),
+            RuleCondition::Layer(layer) => write!(f, "layer:{layer}"),
+            RuleCondition::Place(place) => write!(f, "place:{place}"),
+            RuleCondition::Rating(op, rating) => {
+                let op = match op {
+                    CompOp::Eq => "=",
+                    CompOp::Ge => ">=",
+                    CompOp::Le => "<=",
+                    CompOp::Gt => ">",
+                    CompOp::Lt => "<",
+                };
+                write!(f, "rating{op}{rating}")
+            }
+        }
+    }
+}
+
+/// A single way in which a photo breaks the tag rules.
+#[derive(Clone, PartialEq, Serialize)]
+pub struct RuleViolation {
+    /// The id of the rule, or the name of the tag for tag relationships
+    pub rule: String,
+    pub kind: RuleKind,
+    pub tags: Vec<String>,
+    pub message: String,
+}
+
+/// What rules need to know about the library beyond the photo itself. This is gathered up front
+/// so validation doesn't have to lock the place and rule caches while other caches are held.
+#[derive(Default)]
+pub struct RuleContext {
+    rules: Vec<TagRule>,
+    place_layers: HashMap<String, String>,
+    place_names: HashMap<String, String>,
+    layer_names: HashMap<String, String>,
+    categories: HashMap<String, TagCategory>,
+    steps: Vec<TagCanonicalization>,
+}
+
+/// Whether a layer or place condition refers to an item, by id or by name.
+fn is_named(id: &str, names: &HashMap<String, String>, term: &str) -> bool {
+    id.eq_ignore_ascii_case(term)
+        || names
+            .get(id)
+            .is_some_and(|name| name.eq_ignore_ascii_case(term))
+}
+
+pub async fn rule_context() -> RuleContext {
+    let (place_layers, place_names) = {
+        let places = PLACES.lock().await;
+        (
+            places
+                .values()
+                .map(|p| (p.id.clone(), p.layer.clone()))
+                .collect::<HashMap<String, String>>(),
+            places
+                .values()
+                .map(|p| (p.id.clone(), p.name.clone()))
+                .collect::<HashMap<String, String>>(),
+        )
+    };
+    let layer_names = LAYERS
+        .lock()
+        .await
+        .values()
+        .map(|l| (l.id.clone(), l.name.clone()))
+        .collect::<HashMap<String, String>>();
+    let mut rules = TAG_RULES
+        .lock()
+        .await
+        .values()
+        .cloned()
+        .collect::<Vec<TagRule>>();
+    rules.sort_by(|a, b| a.name.cmp(&b.name));
+    let categories = TAG_CATEGORIES.lock().await.clone();
+    let steps = get_tag_canonicalization().await.unwrap_or_default();
+    RuleContext {
+        rules,
+        place_layers,
+        place_names,
+        layer_names,
+        categories,
+        steps,
+    }
+}
+
+/// Checks a photo against every rule in the rules table. `tags` should already include the
+/// ancestors implied by the photo's tags.
+pub fn check_rules(
+    photo: &Photo,
+    tags: &[String],
+    tags_repo: &HashMap<String, Tag>,
+    context: &RuleContext,
+) -> Vec<RuleViolation> {
+    context
+        .rules
+        .iter()
+        .filter_map(|rule| rule.check(photo, tags, tags_repo, context))
+        .collect()
+}
+
+/// Checks that a photo doesn't have more tags from any category than the category allows. Only the
+/// tags set on the photo count, not the parents they imply.
+pub fn check_category_limits(
+    tags: &[String],
+    tags_repo: &HashMap<String, Tag>,
+    context: &RuleContext,
+) -> Vec<RuleViolation> {
+    let mut by_category = HashMap::<&String, Vec<String>>::new();
+    for tag in tags {
+        if let Some(category) = tags_repo.get(tag).and_then(|t| t.category.as_ref()) {
+            by_category.entry(category).or_default().push(tag.clone());
+        }
+    }
+    let mut violations = by_category
+        .into_iter()
+        .filter_map(|(category, present)| {
+            let category = context.categories.get(category)?;
+            let max = category.max_per_photo?;
+            (present.len() > max.max(0) as usize).then(|| RuleViolation {
+                rule: category.id.clone(),
+                kind: RuleKind::CategoryLimit,
+                message: format!(
+                    "At most {max} {} tag(s) allowed: {}",
+                    category.name,
+                    present.join(", ")
+                ),
+                tags: present,
+            })
+        })
+        .collect::<Vec<RuleViolation>>();
+    violations.sort_by(|a, b| a.rule.cmp(&b.rule));
+    violations
+}
+
+impl TagRule {
+    pub fn tags(&self) -> Vec<String> {
+        row_to_vec(&self.tags)
+    }
+
+    pub fn kind(&self) -> Result<RuleKind> {
+        Ok(RuleKind::from_str(&self.kind)?)
+    }
+
+    pub fn field(&self) -> Result<Option<RuleField>> {
+        Ok(match &self.field {
+            Some(field) => Some(RuleField::from_str(field)?),
+            None => None,
+        })
+    }
+
+    pub fn condition(&self) -> Result<Option<RuleCondition>> {
+        Ok(match &self.condition {
+            Some(condition) => Some(RuleCondition::from_str(condition)?),
+            None => None,
+        })
+    }
+
+    fn applies_to(
+        &self,
+        photo: &Photo,
+        tags: &[String],
+        tags_repo: &HashMap<String, Tag>,
+        context: &RuleContext,
+    ) -> bool {
+        match self.condition().ok().flatten() {
+            None => true,
+            Some(RuleCondition::Tag(tag)) => {
+                tags.contains(&resolve_tag(&tag, tags_repo, &context.steps))
+            }
+            Some(RuleCondition::Layer(layer)) => photo
+                .location
+                .as_ref()
+                .and_then(|place| context.place_layers.get(place))
+                .is_some_and(|l| is_named(l, &context.layer_names, &layer)),
+            Some(RuleCondition::Place(place)) => photo
+                .location
+                .as_ref()
+                .is_some_and(|p| is_named(p, &context.place_names, &place)),
+            Some(RuleCondition::Rating(op, rating)) => {
+                photo.rating.is_some_and(|r| op.compare(&r, &rating))
+            }
+        }
+    }
+
+    fn check(
+        &self,
+        photo: &Photo,
+        tags: &[String],
+        tags_repo: &HashMap<String, Tag>,
+        context: &RuleContext,
+    ) -> Option<RuleViolation> {
+        if !self.applies_to(photo, tags, tags_repo, context) {
+            return None;
+        }
+        let kind = self.kind().ok()?;
+        let rule_tags = self.tags();
+        let present = rule_tags
+            .iter()
+            .filter(|t| tags.contains(t))
+            .cloned()
+            .collect::<Vec<String>>();
+        let (valid, expected) = match kind {
+            RuleKind::ExactlyOneOf => (present.len() == 1, "exactly one of"),
+            RuleKind::AtLeastOneOf => (!present.is_empty(), "at least one of"),
+            RuleKind::AtMostOneOf => (present.len() <= 1, "at most one of"),
+            RuleKind::Requires => {
+                let field = self.field().ok().flatten()?;
+                let has_value = match field {
+                    RuleField::Title => photo.title.as_ref().is_some_and(|t| !t.is_empty()),
+                    RuleField::Description => {
+                        photo.description.as_ref().is_some_and(|d| !d.is_empty())
+                    }
+                    RuleField::Location => photo.location.is_some(),
+                    RuleField::Date => photo.date().is_some(),
+                    RuleField::Rating => photo.rating.is_some(),
+                    RuleField::People => !photo.people().is_empty(),
+                    RuleField::Photographer => {
+                        photo.photographer.is_some() || photo.photographer_name.is_some()
+                    }
+                };
+                return (!has_value).then(|| RuleViolation {
+                    rule: self.id.clone(),
+                    kind,
+                    tags: vec![],
+                    message: format!("{}: {} is required", self.name, field),
+                });
+            }
+            _ => return None,
+        };
+        (!valid).then(|| RuleViolation {
+            rule: self.id.clone(),
+            kind,
+            tags: if present.is_empty() {
+                rule_tags.clone()
+            } else {
+                present
+            },
+            message: format!(
+                "{}: expected {expected} {}",
+                self.name,
+                rule_tags.join(", ")
+            ),
+        })
+    }
+}
+
+/// Checks that a rule is complete and normalizes its condition. Layers and places in the condition
+/// must exist, and tags are resolved the way they are when entered on photos.
+async fn check_rule(rule: &mut TagRule) -> Result<()> {
+    match rule.kind()? {
+        RuleKind::ExactlyOneOf | RuleKind::AtLeastOneOf | RuleKind::AtMostOneOf => {
+            if rule.tags().is_empty() {
+                return Err(anyhow!("Rule {} has no tags", rule.name));
+            }
+            rule.field = None;
+        }
+        RuleKind::Requires => {
+            if rule.field()?.is_none() {
+                return Err(anyhow!("Rule {} has no required field", rule.name));
+            }
+            rule.tags = None;
+        }
+        kind => {
+            return Err(anyhow!(
+                "{kind} is set on tags or tag categories, not with rules"
+            ))
+        }
+    }
+    rule.condition = match rule.condition()? {
+        Some(RuleCondition::Tag(tag)) => {
+            let steps = get_tag_canonicalization().await?;
+            let tags = TAGS.lock().await;
+            Some(RuleCondition::Tag(resolve_tag(&tag, &tags, &steps)))
+        }
+        Some(RuleCondition::Layer(layer)) => {
+            let layers = LAYERS.lock().await;
+            let names = layers
+                .values()
+                .map(|l| (l.id.clone(), l.name.clone()))
+                .collect::<HashMap<String, String>>();
+            if !names.keys().any(|id| is_named(id, &names, &layer)) {
+                return Err(anyhow!("Layer {layer} not found"));
+            }
+            Some(RuleCondition::Layer(layer))
+        }
+        Some(RuleCondition::Place(place)) => {
+            let places = PLACES.lock().await;
+            let names = places
+                .values()
+                .map(|p| (p.id.clone(), p.name.clone()))
+                .collect::<HashMap<String, String>>();
+            if !names.keys().any(|id| is_named(id, &names, &place)) {
+                return Err(anyhow!("Place {place} not found"));
+            }
+            Some(RuleCondition::Place(place))
+        }
+        condition => condition,
+    }
+    .map(|c| c.to_string());
+    Ok(())
+}
+
+pub async fn get_tag_rules() -> Result<Vec<TagRule>> {
+    Ok(TAG_RULES
+        .lock()
+        .await
+        .values()
+        .cloned()
+        .collect::<Vec<TagRule>>())
+}
+
+pub async fn create_tag_rule(mut rule: TagRule) -> Result<()> {
+    check_rule(&mut rule).await?;
+    ensure_db().await?;
+    let mut rules = TAG_RULES.lock().await;
+    insert_into(tag_rules::table)
+        .values(rule.clone())
+        .execute(DB.lock().await.as_mut().unwrap())
+        .await?;
+    rules.insert(rule.id.clone(), rule);
+
+    Ok(())
+}
+
+pub async fn update_tag_rule(mut rule: TagRule) -> Result<()> {
+    check_rule(&mut rule).await?;
+    ensure_db().await?;
+    let mut rules = TAG_RULES.lock().await;
+    if !rules.contains_key(&rule.id) {
+        return Err(anyhow!("Rule {} not found", rule.id));
+    }
+    update(tag_rules::table.filter(tag_rules::id.eq(&rule.id)))
+        .set((
+            tag_rules::name.eq(&rule.name),
+            tag_rules::kind.eq(&rule.kind),
+            tag_rules::tags.eq(&rule.tags),
+            tag_rules::field.eq(&rule.field),
+            tag_rules::condition.eq(&rule.condition),
+        ))
+        .execute(DB.lock().await.as_mut().unwrap())
+        .await?;
+    rules.insert(rule.id.clone(), rule);
+
+    Ok(())
+}
+
+pub async fn delete_tag_rule(rule: &String) -> Result<()> {
+    ensure_db().await?;
+    let mut rules = TAG_RULES.lock().await;
+    delete(tag_rules::table.filter(tag_rules::id.eq(rule)))
+        .execute(DB.lock().await.as_mut().unwrap())
+        .await?;
+    rules.remove(rule);
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct TagRuleDto {
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    pub tags: Vec<String>,
+    pub field: Option<String>,
+    pub condition: Option<String>,
+}
+
+impl From<&TagRule> for TagRuleDto {
+    fn from(value: &TagRule) -> Self {
+        Self {
+            id: value.id.clone(),
+            name: value.name.clone(),
+            kind: value.kind.clone(),
+            tags: value.tags(),
+            field: value.field.clone(),
+            condition: value.condition.clone(),
+        }
+    }
+}
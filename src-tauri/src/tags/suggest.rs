@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::{
+    photos::PHOTOS,
+    tags::{
+        rules::{rule_context, RuleKind, RuleViolation},
+        validate_photo_with, with_ancestors, TAGS,
+    },
+};
+
+/// How much each kind of evidence counts towards a suggestion's score. Tags on other photos in
+/// the same group are the strongest hint, since they are usually shots of the same thing.
+const GROUP_WEIGHT: f64 = 1.0;
+const CO_OCCURRENCE_WEIGHT: f64 = 0.8;
+const PLACE_WEIGHT: f64 = 0.6;
+const DATE_WEIGHT: f64 = 0.6;
+const PEOPLE_WEIGHT: f64 = 0.4;
+
+/// A tag that probably belongs on a photo, with the strongest reason for suggesting it.
+#[derive(Serialize)]
+pub struct TagSuggestion {
+    pub tag: String,
+    pub score: f64,
+    pub reason: String,
+}
+
+/// Counts how often each tag appears on a set of related photos.
+#[derive(Default)]
+struct TagFrequency {
+    total: usize,
+    counts: HashMap<String, usize>,
+}
+
+impl TagFrequency {
+    fn add(&mut self, tags: &[String], current: &[String]) {
+        self.total += 1;
+        for tag in tags.iter().filter(|tag| !current.contains(tag)) {
+            *self.counts.entry(tag.clone()).or_insert(0) += 1;
+        }
+    }
+
+    fn fraction(&self, tag: &String) -> Option<(usize, f64)> {
+        let count = *self.counts.get(tag)?;
+        Some((count, count as f64 / self.total as f64))
+    }
+}
+
+/// Whether adding a tag made a photo break a rule that adding more tags can never fix.
+fn is_conflict(violation: &RuleViolation) -> bool {
+    matches!(
+        violation.kind,
+        RuleKind::Incompatible
+            | RuleKind::AtMostOneOf
+            | RuleKind::ExactlyOneOf
+            | RuleKind::CategoryLimit
+    )
+}
+
+/// Suggests tags for a photo based on the tags used with its current tags across the library, and
+/// on photos in the same group, at the same place, from the same day, or with the same people.
+/// Tags that would conflict with the photo's current tags are never suggested.
+pub async fn suggest_tags(photo: &String, limit: usize) -> Result<Vec<TagSuggestion>> {
+    let context = rule_context().await;
+    let photos = PHOTOS.lock().await;
+    let tags = TAGS.lock().await;
+    let target = photos
+        .get(photo)
+        .ok_or_else(|| anyhow!("Photo {photo} not found"))?;
+    let current = with_ancestors(&target.tags(), &tags);
+    let date = target.date();
+    let people = target.people();
+
+    let mut group = TagFrequency::default();
+    let mut place = TagFrequency::default();
+    let mut day = TagFrequency::default();
+    let mut with_people = TagFrequency::default();
+    let mut co_occurrence = HashMap::<String, TagFrequency>::new();
+    for other in photos.values() {
+        if other.name == target.name {
+            continue;
+        }
+        let other_tags = with_ancestors(&other.tags(), &tags);
+        if target.photo_group.is_some() && other.photo_group == target.photo_group {
+            group.add(&other_tags, &current);
+        }
+        if target.location.is_some() && other.location == target.location {
+            place.add(&other_tags, &current);
+        }
+        if date.is_some() && other.date() == date {
+            day.add(&other_tags, &current);
+        }
+        if other.people().iter().any(|person| people.contains(person)) {
+            with_people.add(&other_tags, &current);
+        }
+        for tag in current.iter().filter(|tag| other_tags.contains(tag)) {
+            co_occurrence
+                .entry(tag.clone())
+                .or_default()
+                .add(&other_tags, &current);
+        }
+    }
+
+    let mut candidates = HashMap::<String, Vec<(f64, String)>>::new();
+    for (frequency, weight, describe) in [
+        (&group, GROUP_WEIGHT, "in this group"),
+        (&place, PLACE_WEIGHT, "at this place"),
+        (&day, DATE_WEIGHT, "from the same day"),
+        (&with_people, PEOPLE_WEIGHT, "with the same people"),
+    ] {
+        for tag in frequency.counts.keys() {
+            let (count, fraction) = frequency.fraction(tag).unwrap();
+            candidates.entry(tag.clone()).or_default().push((
+                weight * fraction,
+                format!("Used on {count} of {} photos {describe}", frequency.total),
+            ));
+        }
+    }
+    // Only the tag that is most often used with the candidate counts, so that photos with many
+    // tags don't outrank photos with few
+    let mut best_co_occurrence = HashMap::<String, (f64, String)>::new();
+    for (source, frequency) in &co_occurrence {
+        for tag in frequency.counts.keys() {
+            let (count, fraction) = frequency.fraction(tag).unwrap();
+            if best_co_occurrence
+                .get(tag)
+                .is_none_or(|(best, _)| fraction > *best)
+            {
+                best_co_occurrence.insert(
+                    tag.clone(),
+                    (
+                        fraction,
+                        format!(
+                            "Used with {source} on {count} of {} photos",
+                            frequency.total
+                        ),
+                    ),
+                );
+            }
+        }
+    }
+    for (tag, (fraction, reason)) in best_co_occurrence {
+        candidates
+            .entry(tag)
+            .or_default()
+            .push((CO_OCCURRENCE_WEIGHT * fraction, reason));
+    }
+
+    let existing = validate_photo_with(target, &tags, &context).violations;
+    let mut suggestions = Vec::new();
+    for (tag, evidence) in candidates {
+        let mut with_tag = target.clone();
+        let mut value = target.tags();
+        value.push(tag.clone());
+        with_tag.tags = value;
+        if validate_photo_with(&with_tag, &tags, &context)
+            .violations
+            .iter()
+            .any(|violation| is_conflict(violation) && !existing.contains(violation))
+        {
+            continue;
+        }
+        let score = evidence.iter().map(|(score, _)| score).sum::<f64>();
+        let (_, reason) = evidence
+            .into_iter()
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .unwrap();
+        suggestions.push(TagSuggestion { tag, score, reason });
+    }
+    suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.tag.cmp(&b.tag)));
+    suggestions.truncate(limit);
+    Ok(suggestions)
+}
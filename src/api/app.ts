@@ -2,7 +2,71 @@ import { invoke } from '@tauri-apps/api/core';
 import { APIResult } from '@/classes/APIResult';
 import { Photo, type PhotoData } from '@/classes/Photo';
 
-export type Sort = 'name' | 'name_desc' | 'date' | 'date_desc' | 'rating' | 'rating_desc' | 'filedate' | 'filedate_desc';
+type SortKey =
+  | 'name'
+  | 'date'
+  | 'rating'
+  | 'filedate'
+  | 'place'
+  | 'photographer'
+  | 'people'
+  | 'tags'
+  | 'size';
+
+/** A single sort key, or a comma-separated list of keys applied in order. */
+export type Sort =
+  | SortKey
+  | `${SortKey}_desc`
+  | 'random'
+  | `random_${number}`
+  | `${string},${string}`;
+
+export type QuerySuggestion = {
+  value: string;
+  description: string;
+};
+
+export type QueryError = {
+  index: number;
+  term: string;
+  message: string;
+};
+
+export type SearchExplanation = {
+  terms: {
+    term: string;
+    parsed: string;
+    negated: boolean;
+    stage: 'sql' | 'memory' | 'sort' | 'option';
+  }[];
+  sql: string;
+  sort: string;
+  stages: { stage: string; remaining: number }[];
+  exclusions: { photo: string; reason: string }[];
+};
+
+export type NodeType = 'Tag' | 'Person' | 'Photographer' | 'Place' | 'Layer' | 'Shape' | 'Trip';
+
+export type GraphNode = {
+  /** The node type in lowercase followed by the key, e.g. tag:beach */
+  id: string;
+  node_type: NodeType;
+  key: string;
+  label: string;
+  count: number;
+};
+
+export type GraphEdge = {
+  source: GraphNode['id'];
+  target: GraphNode['id'];
+  edge_type: 'CoOccurrence' | 'Prereq' | 'Coreq' | 'Incompatible' | 'Layer' | 'Shape' | 'Trip';
+  weight: number;
+};
+
+export type Graph = {
+  nodes: GraphNode[];
+  edges: GraphEdge[];
+};
 
 type LoadedPhotos = {
   removed: string[];
@@ -20,6 +84,18 @@ export function photo_grid(query: string[], sort: Sort) {
   );
 }
 
+export function explain_search(query: string[], sort: Sort) {
+  return new APIResult<SearchExplanation>(
+    async () => await invoke('explain_search', { query, sort }),
+  );
+}
+
+export function get_graph(query?: string[], minWeight?: number, nodeTypes?: NodeType[]) {
+  return new APIResult<Graph>(
+    async () => await invoke('get_graph', { query, minWeight, nodeTypes }),
+  );
+}
+
 export async function remove_deleted(deleted: string[]) {
   await invoke('remove_deleted', { deleted });
 }
@@ -27,3 +103,11 @@ export async function remove_deleted(deleted: string[]) {
 export async function refresh() {
   await invoke('refresh');
 }
+
+export function suggest_query(partial: string) {
+  return new APIResult<QuerySuggestion[]>(async () => await invoke('suggest_query', { partial }));
+}
+
+export function check_query(query: string[]) {
+  return new APIResult<QueryError[]>(async () => await invoke('check_query', { query }));
+}
@@ -0,0 +1,48 @@
+import type { Nullable } from '@/types';
+import { invoke } from '@tauri-apps/api/core';
+import { APIResult } from '@/classes/APIResult';
+import type { PersonData } from '@/classes/Person';
+import type { PhotoData } from '@/classes/Photo';
+import type { PlaceData } from '@/classes/Place';
+import type { TagData } from '@/classes/Tag';
+
+export type AutotagRuleData = {
+  id: string;
+  name: string;
+  /** Any search query accepted by photo_grid */
+  query: string[];
+  tags: TagData['name'][];
+  people: PersonData['id'][];
+  photographer: Nullable<PersonData['id']>;
+  location: Nullable<PlaceData['id']>;
+};
+
+export type AutotagChange = {
+  photo: PhotoData['name'];
+  rules: AutotagRuleData['name'][];
+  tags: TagData['name'][];
+  people: PersonData['id'][];
+  photographer: Nullable<PersonData['id']>;
+  location: Nullable<PlaceData['id']>;
+};
+
+export function get_autotag_rules() {
+  return new APIResult<AutotagRuleData[]>(async () => await invoke('get_autotag_rules'));
+}
+
+export function create_autotag_rule(rule: AutotagRuleData) {
+  return new APIResult<void>(async () => await invoke('create_autotag_rule', rule));
+}
+
+export function update_autotag_rule(rule: AutotagRuleData) {
+  return new APIResult<void>(async () => await invoke('update_autotag_rule', rule));
+}
+
+export function delete_autotag_rule(rule: AutotagRuleData['id']) {
+  return new APIResult<void>(async () => await invoke('delete_autotag_rule', { rule }));
+}
+
+/** Runs the rules on every photo; with dryRun, only returns what would change */
+export function run_autotag_rules(dryRun: boolean) {
+  return new APIResult<AutotagChange[]>(async () => await invoke('run_autotag_rules', { dryRun }));
+}
@@ -1,6 +1,14 @@
 import { invoke } from '@tauri-apps/api/core';
 import { APIResult } from '@/classes/APIResult';
-import { Person, type PersonData, type PersonRec } from '@/classes/Person';
+import {
+  type Birthdate,
+  Person,
+  type PersonData,
+  type PersonRec,
+  type Region,
+} from '@/classes/Person';
+import type { PhotoData } from '@/classes/Photo';
+import type { Nullable } from '@/types';
 import {
   PersonCategory,
   type PersonCategoryData,
@@ -23,6 +31,21 @@ export async function create_person_category(
   await invoke('create_person_category', { id, name, color });
 }
 
+export async function update_person_category(
+  id: PersonCategoryData['id'],
+  name: PersonCategoryData['name'],
+  color: PersonCategoryData['color'],
+) {
+  await invoke('update_person_category', { id, name, color });
+}
+
+export async function delete_person_category(
+  category: PersonCategoryData['id'],
+  reassign: PersonCategoryData['id'],
+) {
+  await invoke('delete_person_category', { category, reassign });
+}
+
 export async function set_person_name(person: PersonData['id'], value: PersonData['name']) {
   await invoke('set_person_name', { person, value });
 }
@@ -31,8 +54,259 @@ export async function set_person_category(person: PersonData['id'], value: Perso
   await invoke('set_person_category', { person, value });
 }
 
-export async function set_person_photo(person: PersonData['id'], value: PersonData['photo']) {
-  await invoke('set_person_photo', { person, value });
+export type PersonRegion = {
+  person: PersonData['id'];
+  region: Region;
+};
+
+export type RegionImport = {
+  imported: PersonRegion[];
+  /** Names from the metadata that don't match anyone */
+  unmatched: string[];
+};
+
+export async function set_person_photo(
+  person: PersonData['id'],
+  value: PersonData['photo'],
+  region: PersonData['photo_region'] = null,
+) {
+  await invoke('set_person_photo', { person, value, region });
+}
+
+export function get_photo_regions(photo: PhotoData['name']) {
+  return new APIResult<PersonRegion[]>(async () => await invoke('get_photo_regions', { photo }));
+}
+
+export async function set_photo_region(
+  photo: PhotoData['name'],
+  person: PersonData['id'],
+  region: Region,
+) {
+  await invoke('set_photo_region', { photo, person, region });
+}
+
+export async function clear_photo_regions(photo: PhotoData['name'], person?: PersonData['id']) {
+  await invoke('clear_photo_regions', { photo, person });
+}
+
+export function import_photo_regions(photo: PhotoData['name']) {
+  return new APIResult<RegionImport>(async () => await invoke('import_photo_regions', { photo }));
+}
+
+export function export_photo_regions(photo: PhotoData['name']) {
+  return new APIResult<string>(async () => await invoke('export_photo_regions', { photo }));
+}
+
+export type BirthdayYear = {
+  year: number;
+  age: number;
+  photos: PhotoData['name'][];
+};
+
+/** Photos of a person taken near their birthday each year */
+export type Birthday = {
+  person: PersonData['id'];
+  name: PersonData['name'];
+  birthdate: string;
+  years: BirthdayYear[];
+};
+
+export async function set_person_birthdate(person: PersonData['id'], value: Nullable<Birthdate>) {
+  await invoke('set_person_birthdate', { person, value });
+}
+
+/** Lists photos taken within the given number of days (7 by default) of each person's birthday */
+export function get_birthdays(days?: number) {
+  return new APIResult<Birthday[]>(async () => await invoke('get_birthdays', { days }));
+}
+
+export type RelationshipKind = 'Parent' | 'Child' | 'Sibling' | 'Partner' | 'Friend';
+
+/** `related` is `person`'s `kind`, e.g. their parent */
+export type Relationship = {
+  person: PersonData['id'];
+  kind: RelationshipKind;
+  related: PersonData['id'];
+};
+
+export type FamilyTree = {
+  /** Generations are relative to the person the tree is for: parents are -1, children 1 */
+  people: { id: PersonData['id']; name: PersonData['name']; generation: number }[];
+  relationships: Relationship[];
+};
+
+export type GedcomImport = {
+  created: number;
+  matched: number;
+  relationships: number;
+};
+
+export function get_relationships(person?: PersonData['id']) {
+  return new APIResult<Relationship[]>(async () => await invoke('get_relationships', { person }));
+}
+
+export async function add_relationship(
+  person: PersonData['id'],
+  kind: RelationshipKind,
+  related: PersonData['id'],
+) {
+  await invoke('add_relationship', { person, kind, related });
+}
+
+export async function remove_relationship(
+  person: PersonData['id'],
+  kind: RelationshipKind,
+  related: PersonData['id'],
+) {
+  await invoke('remove_relationship', { person, kind, related });
+}
+
+export function get_family_tree(person: PersonData['id'], depth?: number) {
+  return new APIResult<FamilyTree>(async () => await invoke('get_family_tree', { person, depth }));
+}
+
+export function import_gedcom(path: string, category: PersonData['category']) {
+  return new APIResult<GedcomImport>(async () => await invoke('import_gedcom', { path, category }));
+}
+
+/** Exports everyone, or just one person's family tree */
+export async function export_gedcom(path: string, person?: PersonData['id']) {
+  await invoke('export_gedcom', { path, person });
+}
+
+/** A face found by face detection that hasn't been assigned to anyone yet */
+export type Face = Region & {
+  id: string;
+  photo: PhotoData['name'];
+  score: number;
+};
+
+/**
+ * Starts face detection in the background, in the given photos or every unscanned photo. Standard
+ * builds don't include detection and reject this; it needs the face-detection feature and model.
+ */
+export async function detect_faces(photos?: PhotoData['name'][]) {
+  await invoke('detect_faces', { photos });
+}
+
+export function get_unassigned_faces(photo?: PhotoData['name']) {
+  return new APIResult<Face[]>(async () => await invoke('get_unassigned_faces', { photo }));
+}
+
+export function assign_face(face: Face['id'], person: PersonData['id']) {
+  return new APIResult<Region>(async () => await invoke('assign_face', { face, person }));
+}
+
+export async function dismiss_face(face: Face['id']) {
+  await invoke('dismiss_face', { face });
+}
+
+/** A photographer name on photos that doesn't match anyone yet */
+export type UnresolvedPhotographer = {
+  name: string;
+  count: number;
+};
+
+export type Camera = {
+  serial: string;
+  photographer: Nullable<PersonData['id']>;
+  photos: number;
+};
+
+export function get_unresolved_photographers() {
+  return new APIResult<UnresolvedPhotographer[]>(
+    async () => await invoke('get_unresolved_photographers'),
+  );
+}
+
+/** Attributes the photos with a photographer name to a person, returning how many changed */
+export async function resolve_photographer(name: string, person: PersonData['id']) {
+  return await invoke<number>('resolve_photographer', { name, person });
+}
+
+export function get_cameras() {
+  return new APIResult<Camera[]>(async () => await invoke('get_cameras'));
+}
+
+/** Sets a camera's default photographer, optionally applying it to its photos without one */
+export async function set_camera_photographer(
+  serial: Camera['serial'],
+  person: Nullable<PersonData['id']>,
+  apply?: boolean,
+) {
+  return await invoke<number>('set_camera_photographer', { serial, person, apply });
+}
+
+export type NamedCount = {
+  id: string;
+  name: string;
+  count: number;
+};
+
+/** Statistics about who appears with whom, for one person or everyone */
+export type PeopleStats = {
+  photos: number;
+  first_date: Nullable<string>;
+  last_date: Nullable<string>;
+  per_year: { year: number; count: number }[];
+  pairs: { a: PersonData['id']; b: PersonData['id']; count: number }[];
+  places: NamedCount[];
+  photographers: NamedCount[];
+};
+
+export function get_people_stats(person?: PersonData['id'], query?: string[], limit?: number) {
+  return new APIResult<PeopleStats>(
+    async () => await invoke('get_people_stats', { person, query, limit }),
+  );
+}
+
+export type SuggestionReason = 'NearbyInTime' | 'SameGroup' | 'SamePlace' | 'SamePhotographer';
+
+/** Someone who might be in a photo, and the photos sharing its context that they are on */
+export type PersonSuggestion = {
+  person: PersonData['id'];
+  name: string;
+  score: number;
+  reasons: { reason: SuggestionReason; photos: number; total: number }[];
+};
+
+export function suggest_people(photo: PhotoData['name'], hours?: number, limit?: number) {
+  return new APIResult<PersonSuggestion[]>(
+    async () => await invoke('suggest_people', { photo, hours, limit }),
+  );
+}
+
+/** What to do with vCards whose name matches someone who already exists */
+export type DuplicatePolicy = 'Merge' | 'Skip';
+
+export type VcardImport = {
+  created: number;
+  merged: number;
+  skipped: number;
+};
+
+/** Adds the people in a vCard file, putting anyone without a category in `category` */
+export function import_vcard(
+  path: string,
+  category: PersonCategoryData['id'],
+  duplicates: DuplicatePolicy,
+) {
+  return new APIResult<VcardImport>(
+    async () => await invoke('import_vcard', { path, category, duplicates }),
+  );
+}
+
+/** Exports everyone, or just one category. Avatars cropped from photos are left out */
+export async function export_vcard(path: string, category?: PersonCategoryData['id']) {
+  await invoke('export_vcard', { path, category });
+}
+
+export async function delete_person(person: PersonData['id']) {
+  await invoke('delete_person', { person });
+}
+
+export async function merge_people(from: PersonData['id'], into: PersonData['id']) {
+  await invoke('merge_people', { from, into });
 }
 
 export function get_people() {
@@ -65,3 +65,10 @@ export function get_group(group: PhotoData['photo_group']) {
     photos => Photo.createPhotos(photos),
   );
 }
+
+export function get_invalid_photos() {
+  return new APIResult<PhotoData[], Photo[]>(
+    async () => await invoke('get_invalid_photos'),
+    photos => Photo.createPhotos(photos),
+  );
+}
@@ -3,6 +3,8 @@ import { APIResult } from '@/classes/APIResult';
 
 export type ThemeSetting = 'Dark' | 'Light';
 
+export type TagCanonicalization = 'Trim' | 'CaseFold' | 'Normalize';
+
 export function get_theme() {
   return new APIResult<ThemeSetting>(async () => await invoke('get_theme'));
 }
@@ -22,3 +24,15 @@ export function promote_color(color: string) {
 export function add_color(color: string) {
   return new APIResult<string[]>(async () => await invoke('add_color', { color }));
 }
+
+export function get_tag_canonicalization() {
+  return new APIResult<TagCanonicalization[]>(
+    async () => await invoke('get_tag_canonicalization'),
+  );
+}
+
+export function set_tag_canonicalization(value: TagCanonicalization[]) {
+  return new APIResult<TagCanonicalization[]>(
+    async () => await invoke('set_tag_canonicalization', { value }),
+  );
+}
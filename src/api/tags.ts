@@ -1,11 +1,79 @@
 import type { Nullable } from '@/types';
 import { invoke } from '@tauri-apps/api/core';
 import { APIResult } from '@/classes/APIResult';
+import type { PhotoData } from '@/classes/Photo';
 import { Tag, type TagData, type TagRec } from '@/classes/Tag';
 
+export type RuleKind =
+  | 'Prereq'
+  | 'Coreq'
+  | 'Incompatible'
+  | 'ExactlyOneOf'
+  | 'AtLeastOneOf'
+  | 'AtMostOneOf'
+  | 'Requires';
+
+export type RuleField =
+  | 'Title'
+  | 'Description'
+  | 'Location'
+  | 'Date'
+  | 'Rating'
+  | 'People'
+  | 'Photographer';
+
+export type RuleViolation = {
+  rule: string;
+  kind: RuleKind;
+  tags: TagData['name'][];
+  message: string;
+};
+
 export type ValidationResult = {
   is_valid: boolean;
   message: Nullable<string>;
+  violations: RuleViolation[];
+};
+
+/** Payload of the validation-changed event, sent when tag rule or photo edits change validity */
+export type ValidationChange = {
+  photo: string;
+  validation: ValidationResult;
+};
+
+export type TagRuleData = {
+  id: string;
+  name: string;
+  kind: Exclude<RuleKind, 'Prereq' | 'Coreq' | 'Incompatible'>;
+  tags: TagData['name'][];
+  field: Nullable<RuleField>;
+  /** e.g. tag:beach, layer:nature, place:paris or rating=5; layers and places by id or name */
+  condition: Nullable<string>;
+};
+
+export type SimilarTags = {
+  tag: TagData['name'];
+  other: TagData['name'];
+  distance: number;
+};
+
+export type TagCategoryData = {
+  id: string;
+  name: string;
+  color: string;
+  sort_order: number;
+  /** The most tags from this category that a photo can have */
+  max_per_photo: Nullable<number>;
+  /** The number of photos with at least one tag in this category */
+  count: number;
+};
+
+type TagCategoryArgs = Omit<TagCategoryData, 'count'>;
+
+export type TagSuggestion = {
+  tag: TagData['name'];
+  score: number;
+  reason: string;
 };
 
 export async function set_tag_color(tag: TagData['name'], value: TagData['color']) {
@@ -24,6 +92,68 @@ export async function set_tag_incompatible(tag: TagData['name'], value: TagData[
   await invoke('set_tag_incompatible', { tag, value });
 }
 
+export async function set_tag_parent(tag: TagData['name'], value: TagData['parent']) {
+  await invoke('set_tag_parent', { tag, value });
+}
+
+export async function set_tag_category(tag: TagData['name'], value: TagData['category']) {
+  await invoke('set_tag_category', { tag, value });
+}
+
+export function get_tag_categories() {
+  return new APIResult<TagCategoryData[]>(async () => await invoke('get_tag_categories'));
+}
+
+function tagCategoryArgs({ id, name, color, sort_order, max_per_photo }: TagCategoryArgs) {
+  return { id, name, color, sortOrder: sort_order, maxPerPhoto: max_per_photo };
+}
+
+export function create_tag_category(category: TagCategoryArgs) {
+  return new APIResult<void>(
+    async () => await invoke('create_tag_category', tagCategoryArgs(category)),
+  );
+}
+
+export function update_tag_category(category: TagCategoryArgs) {
+  return new APIResult<void>(
+    async () => await invoke('update_tag_category', tagCategoryArgs(category)),
+  );
+}
+
+export function delete_tag_category(category: TagCategoryData['id']) {
+  return new APIResult<void>(async () => await invoke('delete_tag_category', { category }));
+}
+
+export function set_tag_aliases(tag: TagData['name'], value: TagData['aliases']) {
+  return new APIResult<void>(async () => await invoke('set_tag_aliases', { tag, value }));
+}
+
+export function resolve_tags(value: TagData['name'][]) {
+  return new APIResult<TagData['name'][]>(async () => await invoke('resolve_tags', { value }));
+}
+
+export function find_similar_tags(maxDistance?: number) {
+  return new APIResult<SimilarTags[]>(
+    async () => await invoke('find_similar_tags', { maxDistance }),
+  );
+}
+
+export function suggest_tags(photo: PhotoData['name'], limit?: number) {
+  return new APIResult<TagSuggestion[]>(async () => await invoke('suggest_tags', { photo, limit }));
+}
+
+export function rename_tag(tag: TagData['name'], value: TagData['name']) {
+  return new APIResult<void>(async () => await invoke('rename_tag', { tag, value }));
+}
+
+export function merge_tags(from: TagData['name'], into: TagData['name']) {
+  return new APIResult<void>(async () => await invoke('merge_tags', { from, into }));
+}
+
+export function delete_tag(tag: TagData['name']) {
+  return new APIResult<void>(async () => await invoke('delete_tag', { tag }));
+}
+
 export function get_tags() {
   return new APIResult<TagData[], TagRec>(
     async () => await invoke('get_tags'),
@@ -31,6 +161,29 @@ export function get_tags() {
   );
 }
 
+export function get_tag_subtree(tag: TagData['name']) {
+  return new APIResult<TagData[], TagRec>(
+    async () => await invoke('get_tag_subtree', { tag }),
+    tags => Tag.createTags(tags),
+  );
+}
+
 export function validate_photo(photo: string) {
   return new APIResult<ValidationResult>(async () => await invoke('validate_photo', { photo }));
 }
+
+export function get_tag_rules() {
+  return new APIResult<TagRuleData[]>(async () => await invoke('get_tag_rules'));
+}
+
+export function create_tag_rule(rule: TagRuleData) {
+  return new APIResult<void>(async () => await invoke('create_tag_rule', rule));
+}
+
+export function update_tag_rule(rule: TagRuleData) {
+  return new APIResult<void>(async () => await invoke('update_tag_rule', rule));
+}
+
+export function delete_tag_rule(rule: TagRuleData['id']) {
+  return new APIResult<void>(async () => await invoke('delete_tag_rule', { rule }));
+}
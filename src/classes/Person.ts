@@ -1,13 +1,35 @@
 import type { PersonCategoryData } from './PersonCategory';
 import type { Nullable } from '@/types';
-import { set_person_category, set_person_name, set_person_photo } from '@/api/people';
+import {
+  set_person_birthdate,
+  set_person_category,
+  set_person_name,
+  set_person_photo,
+} from '@/api/people';
 import { SortableItem } from './SortableItem';
 
+/** A rectangle on a photo, normalized to the photo's size and measured from its top left corner */
+export type Region = {
+  x: number;
+  y: number;
+  w: number;
+  h: number;
+};
+
+export type Birthdate = {
+  /** YYYY-MM-DD, or just YYYY when only the year is known */
+  date: string;
+  precision: 'Year' | 'Date';
+};
+
 export type PersonData = {
   id: string;
   name: string;
   photo: Nullable<string>;
   category: PersonCategoryData['id'];
+  /** The part of the photo to crop the avatar to */
+  photo_region: Nullable<Region>;
+  birthdate: Nullable<Birthdate>;
   photographer_count: number;
   count: number;
 };
@@ -22,6 +44,8 @@ export class Person extends SortableItem implements PersonData {
     private _category: PersonData['category'],
     public photographer_count: PersonData['photographer_count'],
     public photo_count: PersonData['count'],
+    private _photo_region: PersonData['photo_region'] = null,
+    private _birthdate: PersonData['birthdate'] = null,
   ) {
     super(id, photo_count, _name, _photo);
   }
@@ -30,6 +54,14 @@ export class Person extends SortableItem implements PersonData {
     return this._category;
   }
 
+  public get photo_region() {
+    return this._photo_region;
+  }
+
+  public get birthdate() {
+    return this._birthdate;
+  }
+
   public static createPeople(people: PersonData[]) {
     const mapped: PersonRec = {};
     for (const person of people) {
@@ -40,6 +72,8 @@ export class Person extends SortableItem implements PersonData {
         person.category,
         person.photographer_count,
         person.count,
+        person.photo_region,
+        person.birthdate,
       );
     }
     return mapped;
@@ -55,8 +89,14 @@ export class Person extends SortableItem implements PersonData {
     await set_person_category(this.id, category);
   }
 
-  public async setPhoto(photo: PersonData['photo']) {
+  public async setPhoto(photo: PersonData['photo'], region: PersonData['photo_region'] = null) {
     this._photo = photo;
-    await set_person_photo(this.id, photo);
+    this._photo_region = photo ? region : null;
+    await set_person_photo(this.id, photo, region);
+  }
+
+  public async setBirthdate(birthdate: PersonData['birthdate']) {
+    this._birthdate = birthdate;
+    await set_person_birthdate(this.id, birthdate);
   }
 }
@@ -1,7 +1,11 @@
+import { update_person_category } from '@/api/people';
+
 export type PersonCategoryData = {
   id: string;
   name: string;
   color: string;
+  /** The sum of the photo counts of the people in this category */
+  count: number;
 };
 
 export type PersonCategoryRec = Record<PersonCategoryData['id'], PersonCategory>;
@@ -11,6 +15,7 @@ export class PersonCategory implements PersonCategoryData {
     public readonly id: PersonCategoryData['id'],
     public _name: PersonCategoryData['name'],
     public _color: PersonCategoryData['color'],
+    public count: PersonCategoryData['count'] = 0,
   ) {}
 
   public get name() {
@@ -24,10 +29,20 @@ export class PersonCategory implements PersonCategoryData {
   public static createCategories(categories: PersonCategoryData[]) {
     const mapped: PersonCategoryRec = {};
     for (const category of categories.map(
-      ({ id, name, color }) => new PersonCategory(id, name, color),
+      ({ id, name, color, count }) => new PersonCategory(id, name, color, count),
     )) {
       mapped[category.id] = category;
     }
     return mapped;
   }
+
+  public async setName(name: PersonCategoryData['name']) {
+    this._name = name;
+    await update_person_category(this.id, name, this.color);
+  }
+
+  public async setColor(color: PersonCategoryData['color']) {
+    this._color = color;
+    await update_person_category(this.id, this.name, color);
+  }
 }
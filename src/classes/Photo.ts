@@ -16,7 +16,7 @@ import {
   set_photo_title,
   set_photographer,
 } from '@/api/photos';
-import { validate_photo, type ValidationResult } from '@/api/tags';
+import { resolve_tags, validate_photo, type ValidationResult } from '@/api/tags';
 
 export type PhotoData = {
   name: string;
@@ -35,10 +35,14 @@ export type PhotoData = {
   people: PersonData['id'][];
   hide_thumbnail: boolean;
   photographer: Nullable<PersonData['id']>;
+  /** The photographer's name, when it hasn't been resolved to a person */
+  photographer_name: Nullable<string>;
+  camera_serial: Nullable<string>;
   valid_tags: ValidationResult;
   metadata_date: Nullable<string>;
   metadata_location: Nullable<[number, number]>;
   grouped_raw: Nullable<string>;
+  group_size: Nullable<number>;
 };
 
 // The _variables here have to be public or eslint complains about them being used in vue components
@@ -191,7 +195,7 @@ export class Photo {
       [],
       false,
       null,
-      { is_valid: true, message: null },
+      { is_valid: true, message: null, violations: [] },
       null,
       null,
       null,
@@ -214,6 +218,13 @@ export class Photo {
 
   public async setTags(value: PhotoData['tags']) {
     this._tags = value;
+    // Show the tags as they will be saved, after aliases and canonicalization are applied
+    await resolve_tags(value)
+      .ok(resolved => {
+        this._tags = resolved;
+      })
+      .err(reportError)
+      .send();
     await set_photo_tags(this.name, value)
       .ok(async () => {
         await validate_photo(this.name)
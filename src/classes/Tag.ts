@@ -1,5 +1,13 @@
 import type { Nullable } from '@/types';
-import { set_tag_color, set_tag_coreqs, set_tag_incompatible, set_tag_prereqs } from '@/api/tags';
+import {
+  set_tag_aliases,
+  set_tag_category,
+  set_tag_color,
+  set_tag_coreqs,
+  set_tag_incompatible,
+  set_tag_parent,
+  set_tag_prereqs,
+} from '@/api/tags';
 import { SortableItem } from './SortableItem';
 
 export type TagData = {
@@ -8,7 +16,11 @@ export type TagData = {
   prereqs: string[];
   coreqs: string[];
   incompatible: string[];
+  parent: Nullable<string>;
+  aliases: string[];
+  category: Nullable<string>;
   count: number;
+  total_count: number;
 };
 
 export type TagRec = Record<TagData['name'], Tag>;
@@ -23,7 +35,11 @@ export class Tag extends SortableItem implements TagData {
     public _prereqs: TagData['prereqs'],
     public _coreqs: TagData['coreqs'],
     public _incompatible: TagData['incompatible'],
+    public _parent: TagData['parent'],
+    public _aliases: TagData['aliases'],
+    public _category: TagData['category'],
     public count: TagData['count'],
+    public total_count: TagData['total_count'],
   ) {
     super(_name, count, _name, null);
   }
@@ -44,6 +60,18 @@ export class Tag extends SortableItem implements TagData {
     return this._incompatible;
   }
 
+  public get parent() {
+    return this._parent;
+  }
+
+  public get aliases() {
+    return this._aliases;
+  }
+
+  public get category() {
+    return this._category;
+  }
+
   public static createTags = (data: TagData[]) => {
     const tags: TagRec = {};
     for (const tag of data) {
@@ -53,13 +81,18 @@ export class Tag extends SortableItem implements TagData {
         tag.prereqs,
         tag.coreqs,
         tag.incompatible,
+        tag.parent,
+        tag.aliases,
+        tag.category,
         tag.count,
+        tag.total_count,
       );
     }
     return tags;
   };
 
-  public static default = (name?: TagData['name']) => new Tag(name ?? '', '', [], [], [], 0);
+  public static default = (name?: TagData['name']) =>
+    new Tag(name ?? '', '', [], [], [], null, [], null, 0, 0);
 
   public async setColor(color: TagData['color']) {
     this._color = color;
@@ -80,4 +113,19 @@ export class Tag extends SortableItem implements TagData {
     this._incompatible = tags;
     await set_tag_incompatible(this.name, tags);
   }
+
+  public async setParent(parent: TagData['parent']) {
+    this._parent = parent;
+    await set_tag_parent(this.name, parent);
+  }
+
+  public async setAliases(aliases: TagData['aliases']) {
+    this._aliases = aliases;
+    await set_tag_aliases(this.name, aliases);
+  }
+
+  public async setCategory(category: TagData['category']) {
+    this._category = category;
+    await set_tag_category(this.name, category);
+  }
 }